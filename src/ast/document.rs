@@ -16,10 +16,43 @@ pub struct Document {
 pub struct Metadata {
     /// Document title
     pub title: Option<String>,
-    /// Document author(s)
-    pub author: Option<String>,
+    /// Document authors
+    pub authors: Vec<Author>,
     /// Date
     pub date: Option<String>,
+    /// Keywords describing the document
+    pub keywords: Vec<String>,
+    /// Language, as a code like `en` or `en-US`
+    pub lang: Option<String>,
+    /// Abstract
+    pub r#abstract: Vec<Block>,
+    /// Paths of bibliography files
+    pub bibliography: Vec<String>,
+    /// Show the title, authors and date at the start of the document, as `\maketitle` does
+    pub title_block: bool,
+}
+
+/// A document author
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Author {
+    /// Name
+    pub name: String,
+    /// Institution the author belongs to
+    pub affiliation: Option<String>,
+    /// Email address
+    pub email: Option<String>,
+    /// Acknowledgement note, from `\thanks`
+    pub thanks: Option<String>,
+}
+
+impl Author {
+    /// Create an author with only a name
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Self::default()
+        }
+    }
 }
 
 /// Block-level elements
@@ -36,8 +69,16 @@ pub enum Block {
     },
     /// Code block with optional language
     CodeBlock { lang: Option<String>, code: String },
-    /// Display math block
-    MathBlock { expr: MathExpr, numbered: bool },
+    /// Display math block, numbered in sequence or by an explicit tag like `(A)`
+    MathBlock {
+        expr: MathExpr,
+        numbered: bool,
+        label: Option<String>,
+        tag: Option<String>,
+    },
+    /// Display math whose LaTeX source could not be converted, kept so that the renderer
+    /// can fall back on it
+    RawMathBlock(String),
     /// Block quote
     Quote(Vec<Block>),
     /// Figure with content, caption, and label
@@ -95,8 +136,22 @@ pub enum Inline {
     Code(String),
     /// Link with text and URL
     Link { text: Vec<Inline>, url: String },
+    /// Image with alternative text and an optional size
+    Image {
+        /// Path or URL of the image file
+        path: String,
+        /// Description for readers who cannot see the image
+        alt: String,
+        /// Width, as a length like `3cm` or a percentage of the text width like `50%`
+        width: Option<String>,
+        /// Height, as a length
+        height: Option<String>,
+    },
     /// Inline math expression
     MathInline(MathExpr),
+    /// Inline math whose LaTeX source could not be converted, kept so that the renderer
+    /// can fall back on it
+    RawMath(String),
     /// Reference to a label
     Ref(String),
     /// Line break
@@ -157,12 +212,12 @@ mod tests {
     fn test_document_with_metadata() {
         let metadata = Metadata {
             title: Some("Test Document".to_string()),
-            author: Some("Test Author".to_string()),
-            date: None,
+            authors: vec![Author::new("Test Author")],
+            ..Metadata::default()
         };
         let doc = Document::with_metadata(metadata.clone());
         assert_eq!(doc.metadata.title, Some("Test Document".to_string()));
-        assert_eq!(doc.metadata.author, Some("Test Author".to_string()));
+        assert_eq!(doc.metadata.authors[0].name, "Test Author");
     }
 
     #[test]
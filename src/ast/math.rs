@@ -1,11 +1,17 @@
 //! Math expression AST nodes
 
+use crate::parser::latex::symbols;
+
 /// Mathematical expression
 #[derive(Debug, Clone, PartialEq)]
 pub enum MathExpr {
     /// Symbol or variable (e.g., "x", "alpha", "1")
     Symbol(String),
 
+    /// Symbol whose command gives it another class than its glyph usually has, like
+    /// `\lvert`, an opening `|`
+    ClassedSymbol { symbol: String, class: MathClass },
+
     /// LaTeX command with arguments (e.g., \frac{a}{b})
     Command { name: String, args: Vec<MathExpr> },
 
@@ -28,6 +34,18 @@ pub enum MathExpr {
         sup: Box<MathExpr>,
     },
 
+    /// Accent over a base (hat, dot, arrow, etc.), named by its Typst accent or glyph
+    Accent { name: String, base: Box<MathExpr> },
+
+    /// Expression set in a math font variant (\mathrm, \mathbb, etc.)
+    Font {
+        variant: FontVariant,
+        body: Box<MathExpr>,
+    },
+
+    /// Base with prime marks (f', f'')
+    Prime { base: Box<MathExpr>, count: usize },
+
     /// Fraction (numerator/denominator)
     Fraction {
         num: Box<MathExpr>,
@@ -43,9 +61,11 @@ pub enum MathExpr {
     /// Group of expressions ({...})
     Group(Vec<MathExpr>),
 
-    /// Binary operation (a + b, a * b, etc.)
+    /// Binary operation or relation (a + b, a \cdot b, a \le b, etc.)
     Binary {
         op: String,
+        /// Either `MathClass::Binary` or `MathClass::Relation`
+        class: MathClass,
         left: Box<MathExpr>,
         right: Box<MathExpr>,
     },
@@ -55,8 +75,58 @@ pub enum MathExpr {
         name: String,
         lower: Option<Box<MathExpr>>,
         upper: Option<Box<MathExpr>>,
+        /// Where the limits are placed (\limits, \nolimits)
+        limits: LimitPlacement,
+        /// Whether `name` is custom operator text (\operatorname) rather than a Typst name
+        custom: bool,
     },
 
+    /// Base with attachments stacked above and/or below it (\overset, \xrightarrow, etc.)
+    Attach {
+        base: Box<MathExpr>,
+        top: Option<Box<MathExpr>>,
+        bottom: Option<Box<MathExpr>>,
+    },
+
+    /// Delimiter with an explicit size (\big(, \Bigg], etc.), size 1 to 4 from \big to \Bigg
+    SizedDelimiter { delim: String, size: u8 },
+
+    /// Derivative in Leibniz notation (\dv, \pdv)
+    Derivative {
+        /// Function being differentiated, absent for the bare operator (\dv{x})
+        func: Option<Box<MathExpr>>,
+        /// Variables of differentiation, several for a mixed partial derivative
+        vars: Vec<MathExpr>,
+        /// Order from the optional argument (\dv[2]{f}{x})
+        order: Option<Box<MathExpr>>,
+        /// Partial (\pdv) rather than total derivative
+        partial: bool,
+        /// Written as a slash fraction in running text (\dv*)
+        inline: bool,
+    },
+
+    /// Dirac notation: a bra, ket, inner product, matrix element or expectation value
+    Dirac {
+        bra: Option<Box<MathExpr>>,
+        operator: Option<Box<MathExpr>>,
+        ket: Option<Box<MathExpr>>,
+    },
+
+    /// Number, unit or quantity from siunitx (\num, \si, \SI, \SIrange)
+    Quantity {
+        /// Numbers, two for a range and none for a bare unit
+        values: Vec<SiNumber>,
+        /// Unit factors in the order written, none for a bare number
+        unit: Vec<UnitFactor>,
+    },
+
+    /// Chemical formula or equation from mhchem (\ce), keeping its source for
+    /// packages that read the mhchem syntax themselves
+    Chemistry { source: String, expr: Box<MathExpr> },
+
+    /// Expression set in a color (\color, \textcolor), given as a Typst color expression
+    Colored { color: String, body: Box<MathExpr> },
+
     /// Delimited expression (e.g., \left( ... \right))
     Delimited {
         left: String,
@@ -75,6 +145,119 @@ pub enum MathExpr {
 
     /// Space
     Space,
+
+    /// Horizontal space of a given width (\, \quad, \hspace{1cm}, etc.)
+    Spacing(MathSpace),
+}
+
+/// Math font variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontVariant {
+    /// Upright (roman) letters
+    Upright,
+    /// Italic letters
+    Italic,
+    /// Bold letters
+    Bold,
+    /// Sans-serif letters
+    Sans,
+    /// Monospace letters
+    Mono,
+    /// Fraktur letters
+    Fraktur,
+    /// Script (roundhand) letters
+    Script,
+    /// Calligraphic (chancery) letters
+    Calligraphic,
+    /// Blackboard bold (double-struck) letters and digits
+    Blackboard,
+}
+
+impl FontVariant {
+    /// Name of the Typst function applying this variant
+    pub fn typst_function(self) -> &'static str {
+        match self {
+            FontVariant::Upright => "upright",
+            FontVariant::Italic => "italic",
+            FontVariant::Bold => "bold",
+            FontVariant::Sans => "sans",
+            FontVariant::Mono => "mono",
+            FontVariant::Fraktur => "frak",
+            FontVariant::Script => "scr",
+            FontVariant::Calligraphic => "cal",
+            FontVariant::Blackboard => "bb",
+        }
+    }
+}
+
+/// TeX math class of a symbol, which determines the spacing around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathClass {
+    /// Ordinary symbols and variables
+    Ordinary,
+    /// Large operators (\sum, \int)
+    Large,
+    /// Binary operators (+, \cdot, \cup)
+    Binary,
+    /// Relations (=, \le, \in, \to)
+    Relation,
+    /// Opening delimiters
+    Opening,
+    /// Closing delimiters
+    Closing,
+    /// Punctuation (comma, semicolon, \colon)
+    Punctuation,
+}
+
+/// Number as siunitx reads it, e.g. `1.2+-0.1e3`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SiNumber {
+    /// Digits with sign and decimal point
+    pub mantissa: String,
+    /// Uncertainty given with `+-` or `\pm`
+    pub uncertainty: Option<String>,
+    /// Power of ten given with `e`
+    pub exponent: Option<String>,
+}
+
+/// One unit in a product of units, e.g. the `s^-2` in `m s^-2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitFactor {
+    /// Printed symbol including any prefix, e.g. `km`
+    pub symbol: String,
+    /// Spelled-out siunitx name, e.g. `kilo meter`, or the symbol for literal units
+    pub name: String,
+    /// Power, negative for units in the denominator
+    pub power: i32,
+}
+
+/// Placement of an operator's limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitPlacement {
+    /// The operator's own default (above/below for \sum, beside for \int)
+    #[default]
+    Auto,
+    /// Always above and below (\limits, \operatorname*)
+    Limits,
+    /// Always beside as scripts (\nolimits)
+    Scripts,
+}
+
+/// Width of a horizontal math space
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MathSpace {
+    /// Thin space, 1/6 em (\,)
+    Thin,
+    /// Medium space, 2/9 em (\:)
+    Medium,
+    /// Thick space, 5/18 em (\;)
+    Thick,
+    /// One em (\quad)
+    Quad,
+    /// Two em (\qquad)
+    Wide,
+    /// Any other width as a Typst length expression, e.g. `-1em/6` or `1cm`
+    Length(String),
 }
 
 impl MathExpr {
@@ -136,9 +319,35 @@ impl MathExpr {
         MathExpr::Group(exprs)
     }
 
+    /// Math class of this expression when it stands alone as an atom
+    pub fn class(&self) -> MathClass {
+        match self {
+            // Characters from the source, then symbols by their entry in the symbol table
+            MathExpr::Symbol(s) => match s.as_str() {
+                "+" | "-" | "*" => MathClass::Binary,
+                "=" | "<" | ">" => MathClass::Relation,
+                "," | ";" => MathClass::Punctuation,
+                "(" | "[" | "{" => MathClass::Opening,
+                ")" | "]" | "}" => MathClass::Closing,
+                name => symbols::typst_class(name).unwrap_or(MathClass::Ordinary),
+            },
+            MathExpr::ClassedSymbol { class, .. } => *class,
+            // Scripts and primes keep the class of their base
+            MathExpr::Subscript { base, .. }
+            | MathExpr::Superscript { base, .. }
+            | MathExpr::SubSup { base, .. }
+            | MathExpr::Prime { base, .. } => base.class(),
+            MathExpr::Operator { .. } => MathClass::Large,
+            _ => MathClass::Ordinary,
+        }
+    }
+
     /// Check if this is a simple expression (single symbol or number)
     pub fn is_simple(&self) -> bool {
-        matches!(self, MathExpr::Symbol(_) | MathExpr::Text(_))
+        matches!(
+            self,
+            MathExpr::Symbol(_) | MathExpr::ClassedSymbol { .. } | MathExpr::Text(_)
+        )
     }
 }
 
@@ -1,125 +1,294 @@
 //! Convert LaTeX math expressions to Typst math syntax
 
-use crate::ast::math::MathExpr;
+use crate::ast::math::{
+    FontVariant, LimitPlacement, MathClass, MathExpr, MathSpace, SiNumber, UnitFactor,
+};
 use crate::error::{Error, Result};
+use crate::parser::latex::symbols;
+use crate::Config;
 
 /// Convert a MathExpr to Typst math syntax
 pub fn render(expr: &MathExpr) -> Result<String> {
-    let mut renderer = MathRenderer::new();
+    render_with_config(expr, &Config::default())
+}
+
+/// Convert a MathExpr to Typst math syntax with the given configuration
+pub fn render_with_config(expr: &MathExpr, config: &Config) -> Result<String> {
+    render_with_imports(expr, config, false).map(|(output, _)| output)
+}
+
+/// Convert a MathExpr to Typst math syntax, also returning the package imports it needs.
+/// Display math keeps its style in the equations nested inside it.
+pub(crate) fn render_with_imports(
+    expr: &MathExpr,
+    config: &Config,
+    display: bool,
+) -> Result<(String, Vec<&'static str>)> {
+    let mut renderer = MathRenderer::new(config);
+    renderer.display = display;
     renderer.render_expr(expr)?;
-    Ok(renderer.output)
+    Ok((renderer.output, renderer.imports))
+}
+
+/// Import line for the `physica` package
+pub(crate) const PHYSICA_IMPORT: &str = "#import \"@preview/physica:0.9.3\": *";
+
+/// Import line for the `unify` package
+pub(crate) const UNIFY_IMPORT: &str = "#import \"@preview/unify:0.7.1\": *";
+
+/// Import line for the `typsium` package
+pub(crate) const TYPSIUM_IMPORT: &str = "#import \"@preview/typsium:0.2.0\": *";
+
+/// How to write a run of letters that is not a Typst identifier, such as the `max`
+/// in `x_{max}`, which Typst would otherwise look up as a variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdentifierPolicy {
+    /// Separate the letters as LaTeX sets them: `x_(m a x)`
+    #[default]
+    Split,
+    /// Quote the run as upright text: `x_"max"`
+    Quote,
+}
+
+/// How to write physics notation like derivatives and Dirac brackets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PhysicsNotation {
+    /// Built-in Typst math: `frac(dif f, dif x)`, `lr(angle.l psi bar.v)`
+    #[default]
+    Native,
+    /// Functions of the `physica` package: `dv(f, x)`, `bra(psi)`
+    Physica,
+}
+
+/// How to write siunitx numbers, units and quantities
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnitNotation {
+    /// Built-in Typst math: `9.81 "m"/"s"^2`, `1.2 times 10^(-3)`
+    #[default]
+    Native,
+    /// Functions of the `unify` package: `#qty("9.81", "meter per second squared")`
+    Unify,
+}
+
+/// How to write mhchem chemical formulas and equations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChemistryNotation {
+    /// Built-in Typst math: `"H"_2"O" + "CO"_2 arrow.r.long "H"_2"CO"_3`
+    #[default]
+    Native,
+    /// The mhchem source passed to the `typsium` package: `#ce("H2O + CO2 -> H2CO3")`
+    Typsium,
+}
+
+/// Text operators predefined in Typst math, e.g. `lim` or `sin`
+const TYPST_OPERATORS: &[&str] = &[
+    "arccos", "arcsin", "arctan", "arg", "cos", "cosh", "cot", "coth", "csc", "csch", "ctg", "deg",
+    "det", "dim", "exp", "gcd", "lcm", "hom", "id", "im", "inf", "ker", "lg", "lim", "liminf",
+    "limsup", "ln", "log", "max", "min", "mod", "Pr", "sec", "sech", "sin", "sinc", "sinh", "sup",
+    "tan", "tanh", "tg", "tr",
+];
+
+/// Shorthands and operator characters that Typst math reads as a single symbol
+const SYMBOL_TOKENS: &[&str] = &[
+    "<=", ">=", "!=", "->", "<-", "<->", "=>", "<=>", "|->", "...", "|", "||", "\\", "(", ")", "[",
+    "]", "*", "!", ",", ".", ":", ";", "'", "+", "-", "=", "<", ">", "&", "%", "?", "",
+];
+
+/// Check whether a symbol can be written as-is: a name, number, shorthand or glyph
+fn is_symbol_token(s: &str) -> bool {
+    SYMBOL_TOKENS.contains(&s)
+        || s.chars().all(|c| c.is_ascii_alphanumeric() || c == '.')
+        || s.chars().all(|c| !c.is_ascii())
 }
 
-struct MathRenderer {
+/// Write text as a Typst string literal
+pub(crate) fn string_literal(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Check whether a symbol is a run of letters Typst would not recognize as one name
+fn is_ambiguous_identifier(name: &str) -> bool {
+    name.len() > 1
+        && name.chars().all(|c| c.is_ascii_alphabetic())
+        && !TYPST_OPERATORS.contains(&name)
+        && !symbols::is_typst_name(name)
+}
+
+/// Check whether an expression is a single letter
+fn is_letter(expr: &MathExpr) -> bool {
+    matches!(expr, MathExpr::Symbol(s) if s.len() == 1 && s.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
+/// Accents that Typst can apply with a function call, e.g. `dot.double(x)`
+const ACCENT_FUNCTIONS: &[&str] = &[
+    "grave",
+    "acute",
+    "hat",
+    "tilde",
+    "macron",
+    "breve",
+    "dot",
+    "dot.double",
+    "dot.triple",
+    "dot.quad",
+    "caron",
+    "circle",
+    "arrow",
+];
+
+struct MathRenderer<'a> {
     output: String,
+    config: &'a Config,
+    /// Number of math calls like `frac(...)` enclosing the current position
+    arg_depth: usize,
+    /// Package imports the output relies on
+    imports: Vec<&'static str>,
+    /// Whether the expression is display math
+    display: bool,
 }
 
-impl MathRenderer {
-    fn new() -> Self {
+impl<'a> MathRenderer<'a> {
+    fn new(config: &'a Config) -> Self {
         Self {
             output: String::new(),
+            config,
+            arg_depth: 0,
+            imports: Vec::new(),
+            display: false,
+        }
+    }
+
+    /// Record that the output uses a package
+    fn import(&mut self, line: &'static str) {
+        if !self.imports.contains(&line) {
+            self.imports.push(line);
+        }
+    }
+
+    /// Render an argument of a math call, where commas and semicolons separate arguments
+    fn render_arg(&mut self, expr: &MathExpr) -> Result<()> {
+        self.arg_depth += 1;
+        let result = self.render_expr(expr);
+        self.arg_depth -= 1;
+        result
+    }
+
+    /// Render an expression as an equation of its own inside markup, e.g. in `#box[$x$]`
+    fn render_nested(&mut self, expr: &MathExpr) -> Result<()> {
+        // The nested equation starts outside of any math call
+        let depth = std::mem::take(&mut self.arg_depth);
+        // A nested equation is inline, so display math has to ask for its style again
+        self.output
+            .push_str(if self.display { "$display(" } else { "$" });
+        let result = self.render_expr(expr);
+        self.output.push_str(if self.display { ")$" } else { "$" });
+        self.arg_depth = depth;
+        result
+    }
+
+    /// Render a symbol, escaping characters that mean something else in Typst math
+    fn render_symbol(&mut self, s: &str) {
+        match s {
+            // Commas and semicolons would end the argument of an enclosing call
+            "," | ";" if self.arg_depth > 0 => {
+                self.output.push('\\');
+                self.output.push_str(s);
+            }
+            "\"" | "#" | "$" | "/" | "_" | "^" | "@" | "`" | "~" | "{" | "}" => {
+                self.output.push('\\');
+                self.output.push_str(s);
+            }
+            _ if is_symbol_token(s) => self.output.push_str(s),
+            // Anything else, like raw LaTeX that could not be parsed, is kept as text
+            _ => self.output.push_str(&string_literal(s)),
         }
     }
 
     fn render_expr(&mut self, expr: &MathExpr) -> Result<()> {
         match expr {
-            MathExpr::Symbol(s) => {
-                self.output.push_str(s);
+            MathExpr::Symbol(s) if is_ambiguous_identifier(s) => {
+                match self.config.math_identifiers {
+                    IdentifierPolicy::Split => {
+                        let letters: Vec<String> = s.chars().map(String::from).collect();
+                        self.output.push_str(&letters.join(" "));
+                    }
+                    IdentifierPolicy::Quote => self.output.push_str(&string_literal(s)),
+                }
             }
 
+            MathExpr::Symbol(s) => self.render_symbol(s),
+            MathExpr::ClassedSymbol { symbol, .. } => self.render_symbol(symbol),
+
             MathExpr::Command { name, args } => {
                 // Handle known commands with special Typst syntax
                 match name.as_str() {
-                    "bold" => {
-                        self.output.push_str("bold(");
-                        if let Some(arg) = args.first() {
-                            self.render_expr(arg)?;
-                        }
-                        self.output.push(')');
-                    }
-                    "hat" => {
-                        self.output.push_str("hat(");
-                        if let Some(arg) = args.first() {
-                            self.render_expr(arg)?;
-                        }
-                        self.output.push(')');
-                    }
                     "overline" => {
                         self.output.push_str("overline(");
                         if let Some(arg) = args.first() {
-                            self.render_expr(arg)?;
+                            self.render_arg(arg)?;
                         }
                         self.output.push(')');
                     }
-                    "arrow" => {
-                        // Vector arrow
-                        self.output.push_str("arrow(");
-                        if let Some(arg) = args.first() {
-                            self.render_expr(arg)?;
-                        }
-                        self.output.push(')');
-                    }
-                    "dot" => {
-                        // Dot accent
-                        if let Some(arg) = args.first() {
-                            self.render_expr(arg)?;
-                            self.output.push('̇'); // Unicode combining dot above
-                        }
-                    }
-                    "diaer" => {
-                        // Double dot (diaeresis)
+                    // Phantoms that keep only one dimension of their content
+                    "hphantom" | "vphantom" => {
+                        let size = if name == "hphantom" {
+                            "height"
+                        } else {
+                            "width"
+                        };
+                        self.output.push_str(&format!("#box({}: 0pt, hide(", size));
                         if let Some(arg) = args.first() {
-                            self.render_expr(arg)?;
-                            self.output.push('̈'); // Unicode combining diaeresis
+                            self.render_nested(arg)?;
                         }
+                        self.output.push_str("))");
                     }
-                    "tilde" => {
-                        self.output.push_str("tilde(");
+                    // Strike-through: \bcancel goes the other way, \xcancel both ways
+                    "cancel" | "bcancel" | "xcancel" => {
+                        self.output.push_str("cancel(");
                         if let Some(arg) = args.first() {
-                            self.render_expr(arg)?;
+                            self.render_arg(arg)?;
                         }
-                        self.output.push(')');
-                    }
-                    "cal" => {
-                        self.output.push_str("cal(");
-                        if let Some(arg) = args.first() {
-                            self.render_expr(arg)?;
+                        match name.as_str() {
+                            "bcancel" => self.output.push_str(", inverted: #true"),
+                            "xcancel" => self.output.push_str(", cross: #true"),
+                            _ => {}
                         }
                         self.output.push(')');
                     }
-                    "bb" => {
-                        self.output.push_str("bb(");
+                    // Typst math has no frame, so box a nested equation
+                    "boxed" => {
+                        self.output.push_str("#box(stroke: 0.5pt, inset: 3pt)[");
                         if let Some(arg) = args.first() {
-                            self.render_expr(arg)?;
+                            self.render_nested(arg)?;
                         }
-                        self.output.push(')');
+                        self.output.push(']');
                     }
                     "op" => {
                         self.output.push_str("op(");
                         if let Some(arg) = args.first() {
-                            self.render_expr(arg)?;
+                            self.render_arg(arg)?;
                         }
                         self.output.push(')');
                     }
                     "underbrace" => {
                         self.output.push_str("underbrace(");
                         if let Some(body) = args.first() {
-                            self.render_expr(body)?;
+                            self.render_arg(body)?;
                         }
                         if args.len() > 1 {
                             self.output.push_str(", ");
-                            self.render_expr(&args[1])?;
+                            self.render_arg(&args[1])?;
                         }
                         self.output.push(')');
                     }
                     "overbrace" => {
                         self.output.push_str("overbrace(");
                         if let Some(body) = args.first() {
-                            self.render_expr(body)?;
+                            self.render_arg(body)?;
                         }
                         if args.len() > 1 {
                             self.output.push_str(", ");
-                            self.render_expr(&args[1])?;
+                            self.render_arg(&args[1])?;
                         }
                         self.output.push(')');
                     }
@@ -132,7 +301,7 @@ impl MathRenderer {
                                 if i > 0 {
                                     self.output.push_str(", ");
                                 }
-                                self.render_expr(arg)?;
+                                self.render_arg(arg)?;
                             }
                             self.output.push(')');
                         }
@@ -160,6 +329,53 @@ impl MathRenderer {
                 self.render_script_arg(sup)?;
             }
 
+            MathExpr::Accent { name, base } => {
+                // Typst has shorthand functions for the common accents
+                if ACCENT_FUNCTIONS.contains(&name.as_str()) {
+                    self.output.push_str(name);
+                    self.output.push('(');
+                    self.render_arg(base)?;
+                    self.output.push(')');
+                } else {
+                    self.output.push_str("accent(");
+                    self.render_arg(base)?;
+                    self.output.push_str(", ");
+                    self.output.push_str(name);
+                    self.output.push(')');
+                }
+            }
+
+            MathExpr::Font { variant, body } => {
+                match (variant, &**body) {
+                    // Double-struck capitals have named symbols like RR
+                    (FontVariant::Blackboard, MathExpr::Symbol(s))
+                        if s.len() == 1 && s.chars().all(|c| c.is_ascii_uppercase()) =>
+                    {
+                        self.output.push_str(s);
+                        self.output.push_str(s);
+                    }
+                    _ => {
+                        self.output.push_str(variant.typst_function());
+                        self.output.push('(');
+                        self.render_arg(body)?;
+                        self.output.push(')');
+                    }
+                }
+            }
+
+            MathExpr::Prime { base, count } => {
+                if matches!(**base, MathExpr::Group(_) | MathExpr::Binary { .. }) {
+                    self.output.push('(');
+                    self.render_expr(base)?;
+                    self.output.push(')');
+                } else {
+                    self.render_expr(base)?;
+                }
+                for _ in 0..*count {
+                    self.output.push('\'');
+                }
+            }
+
             MathExpr::Fraction { num, den } => {
                 // Use simple fraction notation (a/b) for simple cases
                 // Use frac() function for complex cases
@@ -169,9 +385,9 @@ impl MathRenderer {
                     self.render_expr(den)?;
                 } else {
                     self.output.push_str("frac(");
-                    self.render_expr(num)?;
+                    self.render_arg(num)?;
                     self.output.push_str(", ");
-                    self.render_expr(den)?;
+                    self.render_arg(den)?;
                     self.output.push(')');
                 }
             }
@@ -180,37 +396,56 @@ impl MathRenderer {
                 if let Some(deg) = degree {
                     // nth root: root(n, x)
                     self.output.push_str("root(");
-                    self.render_expr(deg)?;
+                    self.render_arg(deg)?;
                     self.output.push_str(", ");
-                    self.render_expr(radicand)?;
+                    self.render_arg(radicand)?;
                     self.output.push(')');
                 } else {
                     // square root: sqrt(x)
                     self.output.push_str("sqrt(");
-                    self.render_expr(radicand)?;
+                    self.render_arg(radicand)?;
                     self.output.push(')');
                 }
             }
 
             MathExpr::Group(exprs) => {
-                for (i, expr) in exprs.iter().enumerate() {
+                // Empty groups, like those left by \label or `{}`, take no space
+                let exprs: Vec<&MathExpr> = exprs
+                    .iter()
+                    .filter(|expr| !matches!(expr, MathExpr::Group(inner) if inner.is_empty()))
+                    .collect();
+                for (i, &expr) in exprs.iter().enumerate() {
                     if i > 0 {
                         // Check if previous element was a unary minus - don't add space
-                        let prev_is_unary_minus = match &exprs[i - 1] {
+                        let prev_is_unary_minus = match exprs[i - 1] {
                             MathExpr::Symbol(s) => s == "-" && i == 1,
                             _ => false,
                         };
-                        if !prev_is_unary_minus {
+                        // Delimiters hug their contents and punctuation its left neighbor
+                        let tight = exprs[i - 1].class() == MathClass::Opening
+                            || matches!(expr.class(), MathClass::Closing | MathClass::Punctuation);
+                        if !prev_is_unary_minus && !tight {
                             // Add space between elements for proper Typst parsing
                             // Typst interprets adjacent letters as a single variable name
                             self.output.push(' ');
                         }
                     }
+                    let start = self.output.len();
                     self.render_expr(expr)?;
+                    // Even delimiters need a space where two names would run together,
+                    // as in `floor.l x`
+                    let joins_names = self.output[..start].ends_with(|c: char| c.is_alphanumeric())
+                        && self.output[start..].starts_with(|c: char| c.is_alphanumeric());
+                    if i > 0 && joins_names {
+                        self.output.insert(start, ' ');
+                    }
                 }
             }
 
-            MathExpr::Binary { op, left, right } => {
+            MathExpr::Binary {
+                op, left, right, ..
+            } => {
+                // Binary operators and relations both get spaces on either side
                 self.render_expr(left)?;
                 self.output.push(' ');
                 self.output.push_str(op);
@@ -218,10 +453,30 @@ impl MathRenderer {
                 self.render_expr(right)?;
             }
 
-            MathExpr::Operator { name, lower, upper } => {
-                // Convert LaTeX operator names to Typst
-                // Most are the same, but without the backslash
-                self.output.push_str(name);
+            MathExpr::Operator {
+                name,
+                lower,
+                upper,
+                limits,
+                custom,
+            } => {
+                match (custom, limits) {
+                    // Custom operator text goes through op(), which sets it upright
+                    (true, LimitPlacement::Limits) => {
+                        self.output
+                            .push_str(&format!("op({}, limits: #true)", string_literal(name)));
+                    }
+                    (true, _) => self
+                        .output
+                        .push_str(&format!("op({})", string_literal(name))),
+                    (false, LimitPlacement::Limits) => {
+                        self.output.push_str(&format!("limits({})", name));
+                    }
+                    (false, LimitPlacement::Scripts) => {
+                        self.output.push_str(&format!("scripts({})", name));
+                    }
+                    (false, LimitPlacement::Auto) => self.output.push_str(name),
+                }
 
                 if let Some(lower_expr) = lower {
                     self.output.push('_');
@@ -234,14 +489,145 @@ impl MathRenderer {
                 }
             }
 
+            MathExpr::Attach { base, top, bottom } => {
+                // Force limits so attachments sit directly above/below the base
+                self.output.push_str("attach(limits(");
+                self.render_arg(base)?;
+                self.output.push(')');
+                if let Some(top) = top {
+                    self.output.push_str(", t: ");
+                    self.render_arg(top)?;
+                }
+                if let Some(bottom) = bottom {
+                    self.output.push_str(", b: ");
+                    self.render_arg(bottom)?;
+                }
+                self.output.push(')');
+            }
+
+            MathExpr::SizedDelimiter { delim, size } => {
+                // Same scale factors as TeX's \big, \Big, \bigg and \Bigg
+                let scale = match size {
+                    1 => 120,
+                    2 => 180,
+                    3 => 240,
+                    _ => 300,
+                };
+                // Bracket characters must be escaped to stand alone inside the call
+                let delim = match delim.as_str() {
+                    "(" | ")" | "[" | "]" | "{" | "}" => format!("\\{}", delim),
+                    _ => delim.clone(),
+                };
+                self.output
+                    .push_str(&format!("lr({}, size: #{}%)", delim, scale));
+            }
+
+            MathExpr::Derivative {
+                func,
+                vars,
+                order,
+                partial,
+                inline,
+            } => {
+                if self.config.math_physics == PhysicsNotation::Physica && !inline {
+                    self.import(PHYSICA_IMPORT);
+                    self.output.push_str(if *partial { "pdv(" } else { "dv(" });
+                    if let Some(func) = func {
+                        self.render_arg(func)?;
+                    }
+                    for var in vars {
+                        self.output.push_str(", ");
+                        self.render_arg(var)?;
+                    }
+                    if let Some(order) = order {
+                        self.output.push_str(", ");
+                        self.render_arg(order)?;
+                    }
+                    self.output.push(')');
+                } else {
+                    self.render_derivative(
+                        func.as_deref(),
+                        vars,
+                        order.as_deref(),
+                        *partial,
+                        *inline,
+                    )?;
+                }
+            }
+
+            MathExpr::Dirac { bra, operator, ket } => {
+                if self.config.math_physics == PhysicsNotation::Physica {
+                    self.import(PHYSICA_IMPORT);
+                    let (name, parts) = match (bra, operator, ket) {
+                        (Some(bra), None, None) => ("bra", vec![bra]),
+                        (None, None, Some(ket)) => ("ket", vec![ket]),
+                        (None, Some(operator), None) => ("expval", vec![operator]),
+                        (Some(bra), None, Some(ket)) => ("braket", vec![bra, ket]),
+                        (Some(bra), Some(operator), Some(ket)) if bra == ket => {
+                            ("expval", vec![operator, bra])
+                        }
+                        (Some(bra), Some(operator), Some(ket)) => ("mel", vec![bra, operator, ket]),
+                        _ => ("braket", bra.iter().chain(operator).chain(ket).collect()),
+                    };
+                    self.output.push_str(name);
+                    self.output.push('(');
+                    for (i, part) in parts.into_iter().enumerate() {
+                        if i > 0 {
+                            self.output.push_str(", ");
+                        }
+                        self.render_arg(part)?;
+                    }
+                    self.output.push(')');
+                } else {
+                    self.render_dirac(bra.as_deref(), operator.as_deref(), ket.as_deref())?;
+                }
+            }
+
+            MathExpr::Quantity { values, unit } => {
+                if self.config.math_units == UnitNotation::Unify {
+                    self.import(UNIFY_IMPORT);
+                    self.render_unify_quantity(values, unit);
+                } else {
+                    for (i, value) in values.iter().enumerate() {
+                        if i > 0 {
+                            self.output.push_str(" \" to \" ");
+                        }
+                        self.render_number(value);
+                        if !unit.is_empty() {
+                            self.output.push(' ');
+                            self.render_unit(unit);
+                        }
+                    }
+                    if values.is_empty() {
+                        self.render_unit(unit);
+                    }
+                }
+            }
+
+            MathExpr::Colored { color, body } => {
+                self.output.push_str(&format!("#text(fill: {})[", color));
+                self.render_nested(body)?;
+                self.output.push(']');
+            }
+
+            MathExpr::Chemistry { source, expr } => {
+                if self.config.math_chemistry == ChemistryNotation::Typsium {
+                    self.import(TYPSIUM_IMPORT);
+                    self.output
+                        .push_str(&format!("#ce({})", string_literal(source)));
+                } else {
+                    self.render_expr(expr)?;
+                }
+            }
+
             MathExpr::Delimited {
                 left,
                 content,
                 right,
             } => {
-                self.output.push_str(left);
+                self.render_symbol(left);
                 self.render_expr(content)?;
-                self.output.push_str(right);
+                self.render_symbol(right);
             }
 
             MathExpr::Environment { name, content } => {
@@ -262,6 +648,11 @@ impl MathRenderer {
                         self.render_matrix_content(content)?;
                         self.output.push(')');
                     }
+                    "Bmatrix" => {
+                        self.output.push_str("mat(delim: \"{\", ");
+                        self.render_matrix_content(content)?;
+                        self.output.push(')');
+                    }
                     "vmatrix" => {
                         self.output.push_str("mat(delim: \"|\", ");
                         self.render_matrix_content(content)?;
@@ -282,7 +673,7 @@ impl MathRenderer {
                             // Cases: first cell is the value, rest is the condition
                             // Format: value "if" condition
                             if let Some(first) = row.first() {
-                                self.render_expr(first)?;
+                                self.render_arg(first)?;
                             }
                             if row.len() > 1 {
                                 self.output.push_str(" \"if\" ");
@@ -290,7 +681,7 @@ impl MathRenderer {
                                     if j > 0 {
                                         self.output.push(' ');
                                     }
-                                    self.render_expr(cell)?;
+                                    self.render_arg(cell)?;
                                 }
                             }
                         }
@@ -310,6 +701,17 @@ impl MathRenderer {
                             }
                         }
                     }
+                    "substack" => {
+                        // Stacked lines, e.g. multi-line limits under a sum
+                        for (i, row) in content.iter().enumerate() {
+                            if i > 0 {
+                                self.output.push_str(" \\ ");
+                            }
+                            for cell in row.iter() {
+                                self.render_expr(cell)?;
+                            }
+                        }
+                    }
                     "gather" => {
                         // Gather: centered equations separated by newlines
                         for (i, row) in content.iter().enumerate() {
@@ -330,19 +732,214 @@ impl MathRenderer {
                 }
             }
 
-            MathExpr::Text(text) => {
-                self.output.push('"');
-                self.output.push_str(text);
-                self.output.push('"');
-            }
+            MathExpr::Text(text) => self.output.push_str(&string_literal(text)),
 
             MathExpr::Space => {
                 self.output.push(' ');
             }
+
+            MathExpr::Spacing(space) => match space {
+                MathSpace::Thin => self.output.push_str("thin"),
+                MathSpace::Medium => self.output.push_str("med"),
+                MathSpace::Thick => self.output.push_str("thick"),
+                MathSpace::Quad => self.output.push_str("quad"),
+                MathSpace::Wide => self.output.push_str("wide"),
+                MathSpace::Length(length) => {
+                    self.output.push_str(&format!("#h({})", length));
+                }
+            },
         }
         Ok(())
     }
 
+    /// Render a derivative as a Leibniz fraction: `frac(dif^2 f, dif x^2)`
+    fn render_derivative(
+        &mut self,
+        func: Option<&MathExpr>,
+        vars: &[MathExpr],
+        order: Option<&MathExpr>,
+        partial: bool,
+        inline: bool,
+    ) -> Result<()> {
+        let d = if partial { "diff" } else { "dif" };
+        if !inline {
+            self.output.push_str("frac(");
+            self.arg_depth += 1;
+        }
+
+        self.output.push_str(d);
+        match order {
+            Some(order) => {
+                self.output.push('^');
+                self.render_script_arg(order)?;
+            }
+            // Mixed partial derivatives count one order per variable
+            None if vars.len() > 1 => self.output.push_str(&format!("^{}", vars.len())),
+            None => {}
+        }
+        if let Some(func) = func {
+            self.output.push(' ');
+            self.render_expr(func)?;
+        }
+
+        self.output.push_str(if inline { " \\/ " } else { ", " });
+        for (i, var) in vars.iter().enumerate() {
+            if i > 0 {
+                self.output.push(' ');
+            }
+            self.output.push_str(d);
+            self.output.push(' ');
+            match order {
+                Some(order) if vars.len() == 1 => {
+                    self.render_script_arg(var)?;
+                    self.output.push('^');
+                    self.render_script_arg(order)?;
+                }
+                _ => self.render_expr(var)?,
+            }
+        }
+
+        if !inline {
+            self.arg_depth -= 1;
+            self.output.push(')');
+        }
+        Ok(())
+    }
+
+    /// Render Dirac notation with scaled angle brackets: `lr(angle.l phi mid(bar.v) psi angle.r)`
+    fn render_dirac(
+        &mut self,
+        bra: Option<&MathExpr>,
+        operator: Option<&MathExpr>,
+        ket: Option<&MathExpr>,
+    ) -> Result<()> {
+        let parts: Vec<&MathExpr> = bra.into_iter().chain(operator).chain(ket).collect();
+        let (left, right) = match (bra, operator, ket) {
+            (Some(_), None, None) => ("angle.l", "bar.v"),
+            (None, None, Some(_)) => ("bar.v", "angle.r"),
+            _ => ("angle.l", "angle.r"),
+        };
+
+        self.output.push_str("lr(");
+        self.output.push_str(left);
+        for (i, part) in parts.into_iter().enumerate() {
+            self.output
+                .push_str(if i > 0 { " mid(bar.v) " } else { " " });
+            self.render_arg(part)?;
+        }
+        self.output.push(' ');
+        self.output.push_str(right);
+        self.output.push(')');
+        Ok(())
+    }
+
+    /// Render a siunitx number: `1.2 times 10^(-3)`
+    fn render_number(&mut self, number: &SiNumber) {
+        let grouped = number.uncertainty.is_some() && number.exponent.is_some();
+        if grouped {
+            self.output.push('(');
+        }
+        // A bare exponent like `e3` stands for a power of ten alone
+        if !number.mantissa.is_empty() || number.exponent.is_none() {
+            self.output.push_str(&number.mantissa);
+        }
+        if let Some(uncertainty) = &number.uncertainty {
+            self.output.push_str(" plus.minus ");
+            self.output.push_str(uncertainty);
+        }
+        if grouped {
+            self.output.push(')');
+        }
+        if let Some(exponent) = &number.exponent {
+            if !number.mantissa.is_empty() {
+                self.output.push_str(" times ");
+            }
+            if exponent.chars().all(|c| c.is_ascii_digit()) {
+                self.output.push_str(&format!("10^{}", exponent));
+            } else {
+                self.output.push_str(&format!("10^({})", exponent));
+            }
+        }
+    }
+
+    /// Render a product of units as a fraction of upright symbols: `"m"/"s"^2`
+    fn render_unit(&mut self, unit: &[UnitFactor]) {
+        let product = |factors: Vec<&UnitFactor>| {
+            let symbols: Vec<String> = factors
+                .iter()
+                .map(|factor| match factor.power.abs() {
+                    1 => string_literal(&factor.symbol),
+                    power => format!("{}^{}", string_literal(&factor.symbol), power),
+                })
+                .collect();
+            match symbols.len() {
+                1 => symbols.join(" "),
+                // Parentheses keep the whole product in the numerator or denominator
+                _ => format!("({})", symbols.join(" ")),
+            }
+        };
+        let numerator: Vec<&UnitFactor> = unit.iter().filter(|f| f.power > 0).collect();
+        let denominator: Vec<&UnitFactor> = unit.iter().filter(|f| f.power < 0).collect();
+
+        if denominator.is_empty() {
+            let symbols: Vec<String> = numerator
+                .into_iter()
+                .map(|factor| product(vec![factor]))
+                .collect();
+            self.output.push_str(&symbols.join(" "));
+        } else if numerator.is_empty() {
+            // A fraction with nothing above it would read `1/"s"`, so use negative powers
+            let symbols: Vec<String> = denominator
+                .into_iter()
+                .map(|factor| format!("{}^({})", string_literal(&factor.symbol), factor.power))
+                .collect();
+            self.output.push_str(&symbols.join(" "));
+        } else {
+            self.output.push_str(&product(numerator));
+            self.output.push('/');
+            self.output.push_str(&product(denominator));
+        }
+    }
+
+    /// Render a siunitx number, unit or quantity with the `unify` package
+    fn render_unify_quantity(&mut self, values: &[SiNumber], unit: &[UnitFactor]) {
+        let number = |number: &SiNumber| {
+            let mut text = number.mantissa.clone();
+            if let Some(uncertainty) = &number.uncertainty {
+                text.push_str(&format!("+-{}", uncertainty));
+            }
+            if let Some(exponent) = &number.exponent {
+                text.push_str(&format!("e{}", exponent));
+            }
+            string_literal(&text)
+        };
+        let unit_names: Vec<String> = unit
+            .iter()
+            .map(|factor| {
+                let per = if factor.power < 0 { "per " } else { "" };
+                let power = match factor.power.abs() {
+                    1 => String::new(),
+                    2 => " squared".to_string(),
+                    3 => " cubed".to_string(),
+                    power => format!("^{}", power),
+                };
+                format!("{}{}{}", per, factor.name, power)
+            })
+            .collect();
+        let unit_name = string_literal(&unit_names.join(" "));
+
+        let call = match (values, unit.is_empty()) {
+            ([], _) => format!("#unit({})", unit_name),
+            ([value], true) => format!("#num({})", number(value)),
+            ([value], false) => format!("#qty({}, {})", number(value), unit_name),
+            ([from, to, ..], true) => format!("#numrange({}, {})", number(from), number(to)),
+            ([from, to, ..], false) => {
+                format!("#qtyrange({}, {}, {})", number(from), number(to), unit_name)
+            }
+        };
+        self.output.push_str(&call);
+    }
+
     /// Render matrix content (rows and cells)
     fn render_matrix_content(&mut self, content: &[Vec<MathExpr>]) -> Result<()> {
         for (i, row) in content.iter().enumerate() {
@@ -353,7 +950,7 @@ impl MathRenderer {
                 if j > 0 {
                     self.output.push_str(", ");
                 }
-                self.render_expr(cell)?;
+                self.render_arg(cell)?;
             }
         }
         Ok(())
@@ -363,12 +960,35 @@ impl MathRenderer {
     /// Wraps in parentheses if needed
     fn render_script_arg(&mut self, expr: &MathExpr) -> Result<()> {
         match expr {
-            MathExpr::Symbol(_) => {
+            // Split letters need parentheses to stay together in the script
+            MathExpr::Symbol(s)
+                if is_ambiguous_identifier(s)
+                    && self.config.math_identifiers == IdentifierPolicy::Split =>
+            {
+                self.output.push('(');
+                self.render_expr(expr)?;
+                self.output.push(')');
+            }
+            MathExpr::Symbol(_) | MathExpr::ClassedSymbol { .. } => {
                 self.render_expr(expr)?;
             }
             MathExpr::Group(exprs) if exprs.len() == 1 => {
                 self.render_expr(&exprs[0])?;
             }
+            // A braced run of letters like {max}, which may be quoted as a whole
+            MathExpr::Group(exprs)
+                if self.config.math_identifiers == IdentifierPolicy::Quote
+                    && exprs.iter().all(is_letter) =>
+            {
+                let run: String = exprs
+                    .iter()
+                    .filter_map(|expr| match expr {
+                        MathExpr::Symbol(s) => Some(s.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                self.output.push_str(&string_literal(&run));
+            }
             _ => {
                 self.output.push('(');
                 self.render_expr(expr)?;
@@ -413,6 +1033,69 @@ mod tests {
         assert_eq!(render(&expr).unwrap(), "x_i");
     }
 
+    #[test]
+    fn test_render_accent_function() {
+        let expr = MathExpr::Accent {
+            name: "dot.double".to_string(),
+            base: Box::new(MathExpr::Group(vec![
+                MathExpr::Symbol("x".to_string()),
+                MathExpr::Symbol("y".to_string()),
+            ])),
+        };
+        assert_eq!(render(&expr).unwrap(), "dot.double(x y)");
+    }
+
+    #[test]
+    fn test_render_accent_fallback() {
+        let expr = MathExpr::Accent {
+            name: "arrow.l".to_string(),
+            base: Box::new(MathExpr::Symbol("v".to_string())),
+        };
+        assert_eq!(render(&expr).unwrap(), "accent(v, arrow.l)");
+    }
+
+    #[test]
+    fn test_render_font_variants() {
+        let expr = MathExpr::Font {
+            variant: FontVariant::Upright,
+            body: Box::new(MathExpr::Symbol("d".to_string())),
+        };
+        assert_eq!(render(&expr).unwrap(), "upright(d)");
+
+        let expr = MathExpr::Font {
+            variant: FontVariant::Fraktur,
+            body: Box::new(MathExpr::Symbol("g".to_string())),
+        };
+        assert_eq!(render(&expr).unwrap(), "frak(g)");
+    }
+
+    #[test]
+    fn test_render_blackboard() {
+        let expr = MathExpr::Font {
+            variant: FontVariant::Blackboard,
+            body: Box::new(MathExpr::Symbol("R".to_string())),
+        };
+        assert_eq!(render(&expr).unwrap(), "RR");
+
+        let expr = MathExpr::Font {
+            variant: FontVariant::Blackboard,
+            body: Box::new(MathExpr::Symbol("1".to_string())),
+        };
+        assert_eq!(render(&expr).unwrap(), "bb(1)");
+    }
+
+    #[test]
+    fn test_render_primes() {
+        let expr = MathExpr::Subscript {
+            base: Box::new(MathExpr::Prime {
+                base: Box::new(MathExpr::Symbol("f".to_string())),
+                count: 2,
+            }),
+            sub: Box::new(MathExpr::Symbol("n".to_string())),
+        };
+        assert_eq!(render(&expr).unwrap(), "f''_n");
+    }
+
     #[test]
     fn test_render_simple_fraction() {
         let expr = MathExpr::Fraction {
@@ -427,6 +1110,7 @@ mod tests {
         let expr = MathExpr::Fraction {
             num: Box::new(MathExpr::Binary {
                 op: "+".to_string(),
+                class: MathClass::Binary,
                 left: Box::new(MathExpr::Symbol("a".to_string())),
                 right: Box::new(MathExpr::Symbol("b".to_string())),
             }),
@@ -450,14 +1134,44 @@ mod tests {
             name: "sum".to_string(),
             lower: Some(Box::new(MathExpr::Symbol("i".to_string()))),
             upper: Some(Box::new(MathExpr::Symbol("n".to_string()))),
+            limits: LimitPlacement::Auto,
+            custom: false,
         };
         assert_eq!(render(&expr).unwrap(), "sum_i^n");
     }
 
+    #[test]
+    fn test_render_operator_limits() {
+        let operator = |name: &str, limits, custom| MathExpr::Operator {
+            name: name.to_string(),
+            lower: Some(Box::new(MathExpr::Symbol("x".to_string()))),
+            upper: None,
+            limits,
+            custom,
+        };
+        assert_eq!(
+            render(&operator("integral", LimitPlacement::Limits, false)).unwrap(),
+            "limits(integral)_x"
+        );
+        assert_eq!(
+            render(&operator("lim", LimitPlacement::Scripts, false)).unwrap(),
+            "scripts(lim)_x"
+        );
+        assert_eq!(
+            render(&operator("argmax", LimitPlacement::Limits, true)).unwrap(),
+            "op(\"argmax\", limits: #true)_x"
+        );
+        assert_eq!(
+            render(&operator("Var", LimitPlacement::Auto, true)).unwrap(),
+            "op(\"Var\")_x"
+        );
+    }
+
     #[test]
     fn test_render_binary_expr() {
         let expr = MathExpr::Binary {
             op: "+".to_string(),
+            class: MathClass::Binary,
             left: Box::new(MathExpr::Superscript {
                 base: Box::new(MathExpr::Symbol("x".to_string())),
                 sup: Box::new(MathExpr::Symbol("2".to_string())),
@@ -469,4 +1183,278 @@ mod tests {
         };
         assert_eq!(render(&expr).unwrap(), "x^2 + y^2");
     }
+
+    #[test]
+    fn test_render_attach() {
+        let expr = MathExpr::Attach {
+            base: Box::new(MathExpr::Symbol("arrow.r.long".to_string())),
+            top: Some(Box::new(MathExpr::Symbol("f".to_string()))),
+            bottom: Some(Box::new(MathExpr::Symbol("g".to_string()))),
+        };
+        assert_eq!(
+            render(&expr).unwrap(),
+            "attach(limits(arrow.r.long), t: f, b: g)"
+        );
+    }
+
+    #[test]
+    fn test_render_sized_delimiter() {
+        let delim = |delim: &str, size| MathExpr::SizedDelimiter {
+            delim: delim.to_string(),
+            size,
+        };
+        assert_eq!(render(&delim("(", 1)).unwrap(), "lr(\\(, size: #120%)");
+        assert_eq!(render(&delim("||", 4)).unwrap(), "lr(||, size: #300%)");
+    }
+
+    #[test]
+    fn test_render_spacing() {
+        let expr = MathExpr::Group(vec![
+            MathExpr::Symbol("a".to_string()),
+            MathExpr::Spacing(MathSpace::Medium),
+            MathExpr::Symbol("b".to_string()),
+            MathExpr::Spacing(MathSpace::Length("-1em/6".to_string())),
+            MathExpr::Symbol("c".to_string()),
+        ]);
+        assert_eq!(render(&expr).unwrap(), "a med b #h(-1em/6) c");
+    }
+
+    #[test]
+    fn test_render_phantoms() {
+        let x = || vec![MathExpr::Symbol("x".to_string())];
+        assert_eq!(render(&MathExpr::command("hide", x())).unwrap(), "hide(x)");
+        assert_eq!(
+            render(&MathExpr::command("vphantom", x())).unwrap(),
+            "#box(width: 0pt, hide($x$))"
+        );
+    }
+
+    #[test]
+    fn test_render_class_spacing() {
+        let sym = |s: &str| MathExpr::Symbol(s.to_string());
+        let expr = MathExpr::Group(vec![
+            sym("f"),
+            sym("("),
+            sym("x"),
+            sym(","),
+            MathExpr::superscript(sym("y"), sym("2")),
+            MathExpr::superscript(sym(")"), sym("2")),
+        ]);
+        assert_eq!(render(&expr).unwrap(), "f (x, y^2)^2");
+    }
+
+    #[test]
+    fn test_render_ambiguous_identifiers() {
+        let letters = |run: &str| MathExpr::Group(run.chars().map(MathExpr::symbol).collect());
+        let expr = MathExpr::Group(vec![
+            MathExpr::subscript(MathExpr::symbol("x"), MathExpr::symbol("init")),
+            MathExpr::subscript(MathExpr::symbol("x"), letters("in")),
+            MathExpr::symbol("alpha"),
+            MathExpr::symbol("ab"),
+        ]);
+        assert_eq!(render(&expr).unwrap(), "x_(i n i t) x_(i n) alpha a b");
+
+        let config = Config {
+            math_identifiers: IdentifierPolicy::Quote,
+            ..Config::default()
+        };
+        assert_eq!(
+            render_with_config(&expr, &config).unwrap(),
+            "x_\"init\" x_\"in\" alpha \"ab\""
+        );
+    }
+
+    #[test]
+    fn test_render_text_escapes() {
+        let expr = MathExpr::Text(r#"say "hi" \o/"#.to_string());
+        assert_eq!(render(&expr).unwrap(), r#""say \"hi\" \\o/""#);
+    }
+
+    #[test]
+    fn test_render_call_argument_escapes() {
+        let sym = |s: &str| MathExpr::Symbol(s.to_string());
+        let expr = MathExpr::Group(vec![
+            MathExpr::fraction(
+                MathExpr::Group(vec![sym("a"), sym(","), sym("b")]),
+                MathExpr::Group(vec![sym("c"), sym(";"), sym("d")]),
+            ),
+            sym("("),
+            sym("x"),
+            sym(","),
+            sym("y"),
+            sym(")"),
+        ]);
+        assert_eq!(render(&expr).unwrap(), r"frac(a\, b, c\; d) (x, y)");
+    }
+
+    #[test]
+    fn test_render_symbol_escapes() {
+        let sym = |s: &str| MathExpr::Symbol(s.to_string());
+        let expr = MathExpr::Group(vec![sym("a"), sym("/"), sym("b"), sym("#"), sym("$")]);
+        assert_eq!(render(&expr).unwrap(), r"a \/ b \# \$");
+        // Raw LaTeX left over from a failed parse becomes a string
+        assert_eq!(render(&sym(r"\frac{a}")).unwrap(), r#""\\frac{a}""#);
+    }
+
+    #[test]
+    fn test_render_derivative() {
+        let expr = MathExpr::Derivative {
+            func: Some(Box::new(MathExpr::Symbol("f".to_string()))),
+            vars: vec![
+                MathExpr::Symbol("x".to_string()),
+                MathExpr::Symbol("y".to_string()),
+            ],
+            order: None,
+            partial: true,
+            inline: false,
+        };
+        assert_eq!(render(&expr).unwrap(), "frac(diff^2 f, diff x diff y)");
+
+        let config = Config {
+            math_physics: PhysicsNotation::Physica,
+            ..Config::default()
+        };
+        let (output, imports) = render_with_imports(&expr, &config, false).unwrap();
+        assert_eq!(output, "pdv(f, x, y)");
+        assert_eq!(imports, vec![PHYSICA_IMPORT]);
+    }
+
+    #[test]
+    fn test_render_dirac() {
+        let symbol = |s: &str| Some(Box::new(MathExpr::Symbol(s.to_string())));
+        let bra = MathExpr::Dirac {
+            bra: symbol("psi"),
+            operator: None,
+            ket: None,
+        };
+        assert_eq!(render(&bra).unwrap(), "lr(angle.l psi bar.v)");
+
+        let element = MathExpr::Dirac {
+            bra: symbol("n"),
+            operator: symbol("H"),
+            ket: symbol("m"),
+        };
+        assert_eq!(
+            render(&element).unwrap(),
+            "lr(angle.l n mid(bar.v) H mid(bar.v) m angle.r)"
+        );
+
+        let config = Config {
+            math_physics: PhysicsNotation::Physica,
+            ..Config::default()
+        };
+        assert_eq!(
+            render_with_config(&element, &config).unwrap(),
+            "mel(n, H, m)"
+        );
+    }
+
+    #[test]
+    fn test_render_quantity() {
+        let unit = vec![
+            UnitFactor {
+                symbol: "m".to_string(),
+                name: "meter".to_string(),
+                power: 1,
+            },
+            UnitFactor {
+                symbol: "s".to_string(),
+                name: "second".to_string(),
+                power: -2,
+            },
+        ];
+        let expr = MathExpr::Quantity {
+            values: vec![SiNumber {
+                mantissa: "9.81".to_string(),
+                uncertainty: None,
+                exponent: None,
+            }],
+            unit,
+        };
+        assert_eq!(render(&expr).unwrap(), r#"9.81 "m"/"s"^2"#);
+
+        let config = Config {
+            math_units: UnitNotation::Unify,
+            ..Config::default()
+        };
+        let (output, imports) = render_with_imports(&expr, &config, false).unwrap();
+        assert_eq!(output, r#"#qty("9.81", "meter per second squared")"#);
+        assert_eq!(imports, vec![UNIFY_IMPORT]);
+    }
+
+    #[test]
+    fn test_render_number() {
+        let number = |mantissa: &str, uncertainty: Option<&str>, exponent: Option<&str>| {
+            MathExpr::Quantity {
+                values: vec![SiNumber {
+                    mantissa: mantissa.to_string(),
+                    uncertainty: uncertainty.map(str::to_string),
+                    exponent: exponent.map(str::to_string),
+                }],
+                unit: vec![],
+            }
+        };
+        assert_eq!(
+            render(&number("1.2", None, Some("-3"))).unwrap(),
+            "1.2 times 10^(-3)"
+        );
+        assert_eq!(
+            render(&number("3.0", Some("0.2"), Some("4"))).unwrap(),
+            "(3.0 plus.minus 0.2) times 10^4"
+        );
+        assert_eq!(render(&number("", None, Some("5"))).unwrap(), "10^5");
+    }
+
+    #[test]
+    fn test_render_chemistry() {
+        let expr = MathExpr::Chemistry {
+            source: "CO2".to_string(),
+            expr: Box::new(MathExpr::subscript(
+                MathExpr::Text("CO".to_string()),
+                MathExpr::symbol("2"),
+            )),
+        };
+        assert_eq!(render(&expr).unwrap(), r#""CO"_2"#);
+
+        let config = Config {
+            math_chemistry: ChemistryNotation::Typsium,
+            ..Config::default()
+        };
+        let (output, imports) = render_with_imports(&expr, &config, false).unwrap();
+        assert_eq!(output, r#"#ce("CO2")"#);
+        assert_eq!(imports, vec![TYPSIUM_IMPORT]);
+    }
+
+    #[test]
+    fn test_render_cancel_and_box() {
+        let x = || MathExpr::Symbol("x".to_string());
+        assert_eq!(
+            render(&MathExpr::command("xcancel", vec![x()])).unwrap(),
+            "cancel(x, cross: #true)"
+        );
+        assert_eq!(
+            render(&MathExpr::command("bcancel", vec![x()])).unwrap(),
+            "cancel(x, inverted: #true)"
+        );
+        assert_eq!(
+            render(&MathExpr::command("boxed", vec![x()])).unwrap(),
+            "#box(stroke: 0.5pt, inset: 3pt)[$x$]"
+        );
+        let colored = MathExpr::Colored {
+            color: "red".to_string(),
+            body: Box::new(x()),
+        };
+        assert_eq!(render(&colored).unwrap(), "#text(fill: red)[$x$]");
+    }
+
+    #[test]
+    fn test_render_delimiters_around_names() {
+        let expr = MathExpr::Group(vec![
+            MathExpr::Symbol("floor.l".to_string()),
+            MathExpr::Symbol("lg".to_string()),
+            MathExpr::Symbol("n".to_string()),
+            MathExpr::Symbol("floor.r".to_string()),
+        ]);
+        assert_eq!(render(&expr).unwrap(), "floor.l lg n floor.r");
+    }
 }
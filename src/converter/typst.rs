@@ -1,45 +1,177 @@
 //! Convert AST to Typst syntax
 
-use crate::ast::document::{Block, Document, Inline, ListKind, Style};
+use super::math::string_literal;
+use crate::ast::document::{Author, Block, Document, Inline, ListKind, Metadata, Style};
 use crate::ast::math::MathExpr;
 use crate::error::{Error, Result};
+use crate::{Config, MathFallback};
+
+/// Import line for the `mitex` package, used for math that cannot be converted
+const MITEX_IMPORT: &str = "#import \"@preview/mitex:0.2.5\": *";
 
 /// Convert a Document AST to Typst syntax
 pub fn render(document: &Document) -> Result<String> {
-    let mut renderer = TypstRenderer::new();
+    render_with_config(document, &Config::default())
+}
+
+/// Convert a Document AST to Typst syntax with the given configuration
+pub fn render_with_config(document: &Document, config: &Config) -> Result<String> {
+    let mut renderer = TypstRenderer::new(config);
     renderer.render_document(document)?;
+    if !renderer.imports.is_empty() {
+        let imports = renderer.imports.join("\n");
+        renderer.output.insert_str(0, &format!("{imports}\n\n"));
+    }
     Ok(renderer.output)
 }
 
-struct TypstRenderer {
+/// Write strings as a Typst string, or an array of strings if there are several
+fn string_array(items: &[String]) -> String {
+    match items {
+        [item] => string_literal(item),
+        items => {
+            let items: Vec<String> = items.iter().map(|item| string_literal(item)).collect();
+            format!("({})", items.join(", "))
+        }
+    }
+}
+
+/// Convert an ISO date like `2024-03-01`, or `\today`, to a Typst `datetime`
+fn typst_date(date: &str) -> Option<String> {
+    let date = date.trim();
+    if date == "\\today" {
+        return Some("datetime.today()".to_string());
+    }
+    let date = date.get(..10).unwrap_or(date);
+    let mut parts = date.splitn(3, '-').map(|part| part.parse::<u32>().ok());
+    let (Some(Some(year)), Some(Some(month)), Some(Some(day))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    Some(format!(
+        "datetime(year: {}, month: {}, day: {})",
+        year, month, day
+    ))
+}
+
+/// Write an image as a call to Typst's `image` function, without the leading `#`
+fn image_call(image: &Inline) -> String {
+    let Inline::Image {
+        path,
+        alt,
+        width,
+        height,
+    } = image
+    else {
+        return String::new();
+    };
+    let mut call = format!("image({}", string_literal(path));
+    if let Some(width) = width {
+        call.push_str(&format!(", width: {}", width));
+    }
+    if let Some(height) = height {
+        call.push_str(&format!(", height: {}", height));
+    }
+    if !alt.is_empty() {
+        call.push_str(&format!(", alt: {}", string_literal(alt)));
+    }
+    call.push(')');
+    call
+}
+
+struct TypstRenderer<'a> {
     output: String,
     #[allow(dead_code)]
     indent_level: usize,
+    config: &'a Config,
+    /// Package imports the output relies on
+    imports: Vec<&'static str>,
 }
 
-impl TypstRenderer {
-    fn new() -> Self {
+impl<'a> TypstRenderer<'a> {
+    fn new(config: &'a Config) -> Self {
         Self {
             output: String::new(),
             indent_level: 0,
+            config,
+            imports: Vec::new(),
+        }
+    }
+
+    /// Record that the output uses a package
+    fn import(&mut self, line: &'static str) {
+        if !self.imports.contains(&line) {
+            self.imports.push(line);
+        }
+    }
+
+    /// Render a math expression, collecting the package imports it needs
+    fn render_math(&mut self, expr: &MathExpr, display: bool) -> Result<String> {
+        let (math, imports) = super::math::render_with_imports(expr, self.config, display)?;
+        for line in imports {
+            self.import(line);
         }
+        Ok(math)
     }
 
     fn render_document(&mut self, document: &Document) -> Result<()> {
+        let metadata = &document.metadata;
+        let start = self.output.len();
+
         // Render metadata if present
-        if let Some(title) = &document.metadata.title {
-            self.output.push_str("#set document(title: \"");
-            self.output.push_str(title);
-            self.output.push_str("\")\n");
+        let mut fields = Vec::new();
+        if let Some(title) = &metadata.title {
+            fields.push(format!("title: {}", string_literal(title)));
+        }
+        if !metadata.authors.is_empty() {
+            let names: Vec<String> = metadata.authors.iter().map(|a| a.name.clone()).collect();
+            fields.push(format!("author: {}", string_array(&names)));
+        }
+        if let Some(date) = metadata.date.as_deref().and_then(typst_date) {
+            fields.push(format!("date: {}", date));
+        }
+        if !metadata.keywords.is_empty() {
+            fields.push(format!("keywords: {}", string_array(&metadata.keywords)));
+        }
+        if !fields.is_empty() {
+            self.output.push_str("#set document(");
+            self.output.push_str(&fields.join(", "));
+            self.output.push_str(")\n");
+        }
+
+        if let Some(lang) = &metadata.lang {
+            // Typst takes the region of codes like `en-US` separately
+            let (lang, region) = match lang.split_once(['-', '_']) {
+                Some((lang, region)) => (lang, Some(region)),
+                None => (lang.as_str(), None),
+            };
+            self.output.push_str("#set text(lang: ");
+            self.output.push_str(&string_literal(&lang.to_lowercase()));
+            if let Some(region) = region {
+                self.output.push_str(", region: ");
+                self.output
+                    .push_str(&string_literal(&region.to_uppercase()));
+            }
+            self.output.push_str(")\n");
         }
 
-        if let Some(author) = &document.metadata.author {
-            self.output.push_str("#set document(author: \"");
-            self.output.push_str(author);
-            self.output.push_str("\")\n");
+        if self.output.len() > start {
+            self.output.push('\n');
+        }
+
+        if metadata.title_block {
+            self.render_title_block(metadata);
         }
 
-        if document.metadata.title.is_some() || document.metadata.author.is_some() {
+        if !metadata.r#abstract.is_empty() {
+            self.output.push_str("#align(center)[*Abstract*]\n\n");
+            for (i, block) in metadata.r#abstract.iter().enumerate() {
+                if i > 0 {
+                    self.output.push('\n');
+                }
+                self.render_block(block)?;
+            }
             self.output.push('\n');
         }
 
@@ -53,9 +185,88 @@ impl TypstRenderer {
             }
         }
 
+        if !metadata.bibliography.is_empty() {
+            self.output.push_str("\n#bibliography(");
+            self.output.push_str(&string_array(&metadata.bibliography));
+            self.output.push_str(")\n");
+        }
+
         Ok(())
     }
 
+    /// Show the title, authors and date like LaTeX's `\maketitle`
+    fn render_title_block(&mut self, metadata: &Metadata) {
+        let mut parts = Vec::new();
+        if let Some(title) = &metadata.title {
+            parts.push(format!(
+                "#text(size: 1.5em, weight: \"bold\")[{}]",
+                self.escape_text(title)
+            ));
+        }
+
+        let authors: Vec<String> = metadata
+            .authors
+            .iter()
+            .map(|author| self.author_content(author))
+            .collect();
+        match authors.as_slice() {
+            [] => {}
+            [author] => parts.push(author.clone()),
+            authors => {
+                let columns = vec!["1fr"; authors.len()].join(", ");
+                let cells: Vec<String> = authors
+                    .iter()
+                    .map(|author| format!("    [{}],\n", author))
+                    .collect();
+                parts.push(format!(
+                    "#grid(\n    columns: ({}),\n    gutter: 1em,\n{}  )",
+                    columns,
+                    cells.concat()
+                ));
+            }
+        }
+
+        match metadata.date.as_deref() {
+            Some("\\today") => parts.push("#datetime.today().display()".to_string()),
+            Some(date) if !date.is_empty() => parts.push(self.escape_text(date)),
+            _ => {}
+        }
+
+        if parts.is_empty() {
+            return;
+        }
+        self.output.push_str("#align(center)[\n");
+        for (i, part) in parts.iter().enumerate() {
+            if i > 0 {
+                self.output.push('\n');
+            }
+            self.output.push_str("  ");
+            self.output.push_str(part);
+            self.output.push('\n');
+        }
+        self.output.push_str("]\n\n");
+    }
+
+    /// An author's name with their note, affiliation and email on separate lines
+    fn author_content(&self, author: &Author) -> String {
+        let mut content = self.escape_text(&author.name);
+        if let Some(thanks) = &author.thanks {
+            content.push_str(&format!("#footnote[{}]", self.escape_text(thanks)));
+        }
+        if let Some(affiliation) = &author.affiliation {
+            content.push_str(" \\ ");
+            content.push_str(&self.escape_text(affiliation));
+        }
+        if let Some(email) = &author.email {
+            content.push_str(&format!(
+                " \\ #link({})[{}]",
+                string_literal(&format!("mailto:{}", email)),
+                self.escape_text(email)
+            ));
+        }
+        content
+    }
+
     fn render_block(&mut self, block: &Block) -> Result<()> {
         match block {
             Block::Heading { level, content } => {
@@ -70,9 +281,24 @@ impl TypstRenderer {
             Block::CodeBlock { lang, code } => {
                 self.render_code_block(lang.as_deref(), code)?;
             }
-            Block::MathBlock { expr, numbered } => {
-                self.render_math_block(expr, *numbered)?;
+            Block::MathBlock {
+                expr,
+                numbered,
+                label,
+                tag,
+            } => {
+                self.render_math_block(expr, *numbered, label.as_deref(), tag.as_deref())?;
             }
+            Block::RawMathBlock(latex) => match self.config.math_fallback {
+                MathFallback::Text => {
+                    self.render_paragraph(&[Inline::Text(format!("$$ {latex} $$"))])?;
+                }
+                MathFallback::Mitex => {
+                    self.import(MITEX_IMPORT);
+                    self.output
+                        .push_str(&format!("#mitex({})\n", string_literal(latex.trim())));
+                }
+            },
             Block::Quote(blocks) => {
                 self.render_quote(blocks)?;
             }
@@ -80,10 +306,17 @@ impl TypstRenderer {
                 self.output.push_str("#line(length: 100%)");
                 self.output.push('\n');
             }
-            Block::Figure { .. } | Block::Table { .. } => {
+            Block::Figure {
+                content,
+                caption,
+                label,
+            } => {
+                self.render_figure(content, caption.as_deref(), label.as_deref())?;
+            }
+            Block::Table { .. } => {
                 // TODO: Implement in later phases
                 return Err(Error::ConversionError(
-                    "Figures and tables not yet supported".to_string(),
+                    "Tables not yet supported".to_string(),
                 ));
             }
         }
@@ -102,6 +335,13 @@ impl TypstRenderer {
     }
 
     fn render_paragraph(&mut self, content: &[Inline]) -> Result<()> {
+        if let [image @ Inline::Image { .. }] = content {
+            // An image on its own is a block, as Typst images are
+            self.output.push('#');
+            self.output.push_str(&image_call(image));
+            self.output.push('\n');
+            return Ok(());
+        }
         self.render_inline_content(content)?;
         self.output.push('\n');
         Ok(())
@@ -119,20 +359,26 @@ impl TypstRenderer {
                 }
             }
 
-            // Render item content (usually a single paragraph)
-            if let Some(first_block) = item.first() {
-                match first_block {
-                    Block::Paragraph(content) => {
-                        self.render_inline_content(content)?;
-                        self.output.push('\n');
-                    }
-                    _ => {
-                        // For complex items, render all blocks
-                        for block in item {
-                            self.render_block(block)?;
-                        }
-                    }
+            // Render the item's blocks on their own, then indent them under the marker
+            let outer = std::mem::take(&mut self.output);
+            for (i, block) in item.iter().enumerate() {
+                // Paragraphs need a blank line between them, nested lists do not
+                if i > 0 && !matches!(block, Block::List { .. }) {
+                    self.output.push('\n');
+                }
+                self.render_block(block)?;
+            }
+            let content = std::mem::replace(&mut self.output, outer);
+
+            for (i, line) in content.lines().enumerate() {
+                if i > 0 && !line.is_empty() {
+                    self.output.push_str("  ");
                 }
+                self.output.push_str(line);
+                self.output.push('\n');
+            }
+            if content.is_empty() {
+                self.output.push('\n');
             }
         }
         Ok(())
@@ -152,12 +398,79 @@ impl TypstRenderer {
         Ok(())
     }
 
-    fn render_math_block(&mut self, expr: &MathExpr, _numbered: bool) -> Result<()> {
-        // Display math with spaces around content
-        self.output.push_str("$ ");
-        let math_str = super::math::render(expr)?;
-        self.output.push_str(&math_str);
-        self.output.push_str(" $\n");
+    fn render_math_block(
+        &mut self,
+        expr: &MathExpr,
+        numbered: bool,
+        label: Option<&str>,
+        tag: Option<&str>,
+    ) -> Result<()> {
+        let math_str = self.render_math(expr, true)?;
+        if let Some(tag) = tag {
+            // A tag replaces the number and, as in LaTeX, does not count as one
+            self.output.push_str(&format!(
+                "#math.equation(block: true, numbering: _ => {}, $ ",
+                string_literal(tag)
+            ));
+            self.output.push_str(&math_str);
+            self.output.push_str(" $)");
+        } else if numbered {
+            // Number this equation alone, as LaTeX's equation environment does
+            self.output
+                .push_str("#math.equation(block: true, numbering: \"(1)\", $ ");
+            self.output.push_str(&math_str);
+            self.output.push_str(" $)");
+        } else {
+            // Display math with spaces around content
+            self.output.push_str("$ ");
+            self.output.push_str(&math_str);
+            self.output.push_str(" $");
+        }
+        if let Some(label) = label {
+            self.output.push_str(&format!(" <{}>", label));
+        }
+        self.output.push('\n');
+        if tag.is_some() {
+            self.output
+                .push_str("#counter(math.equation).update(n => n - 1)\n");
+        }
+        Ok(())
+    }
+
+    fn render_figure(
+        &mut self,
+        content: &[Block],
+        caption: Option<&[Inline]>,
+        label: Option<&str>,
+    ) -> Result<()> {
+        self.output.push_str("#figure(\n  ");
+        match content {
+            // A lone image is passed to the figure as it is
+            [Block::Paragraph(inlines)] if matches!(inlines.as_slice(), [Inline::Image { .. }]) => {
+                self.output.push_str(&image_call(&inlines[0]));
+            }
+            _ => {
+                let outer = std::mem::take(&mut self.output);
+                for block in content {
+                    self.render_block(block)?;
+                }
+                let inner = std::mem::replace(&mut self.output, outer);
+                self.output.push('[');
+                self.output.push_str(inner.trim_end());
+                self.output.push(']');
+            }
+        }
+        self.output.push_str(",\n");
+        if let Some(caption) = caption {
+            self.output.push_str("  caption: [");
+            self.render_inline_content(caption)?;
+            self.output.push_str("],\n");
+        }
+        self.output.push(')');
+        if let Some(label) = label {
+            self.output.push_str(&format!(" <{}>", label));
+        }
+        self.output.push('\n');
         Ok(())
     }
 
@@ -209,13 +522,30 @@ impl TypstRenderer {
                 self.render_inline_content(text)?;
                 self.output.push(']');
             }
+            // Images are blocks in Typst, so a box keeps this one in the line
+            Inline::Image { .. } => {
+                self.output.push_str("#box(");
+                self.output.push_str(&image_call(inline));
+                self.output.push(')');
+            }
             Inline::MathInline(expr) => {
                 // Inline math without spaces
                 self.output.push('$');
-                let math_str = super::math::render(expr)?;
+                let math_str = self.render_math(expr, false)?;
                 self.output.push_str(&math_str);
                 self.output.push('$');
             }
+            Inline::RawMath(latex) => match self.config.math_fallback {
+                MathFallback::Text => {
+                    let escaped = self.escape_text(&format!("${latex}$"));
+                    self.output.push_str(&escaped);
+                }
+                MathFallback::Mitex => {
+                    self.import(MITEX_IMPORT);
+                    self.output
+                        .push_str(&format!("#mi({})", string_literal(latex.trim())));
+                }
+            },
             Inline::Ref(label) => {
                 self.output.push('@');
                 self.output.push_str(label);
@@ -279,7 +609,6 @@ impl TypstRenderer {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ast::document::Metadata;
 
     #[test]
     fn test_render_heading() {
@@ -308,6 +637,30 @@ mod tests {
         assert_eq!(result, "Hello world\n");
     }
 
+    #[test]
+    fn test_render_raw_math() {
+        let doc = Document {
+            metadata: Metadata::default(),
+            content: vec![Block::Paragraph(vec![
+                Inline::Text("See ".to_string()),
+                Inline::RawMath(r"\foo{x}".to_string()),
+            ])],
+        };
+
+        let result = render(&doc).unwrap();
+        assert_eq!(result, "See \\$\\foo{x}\\$\n");
+
+        let config = Config {
+            math_fallback: MathFallback::Mitex,
+            ..Config::default()
+        };
+        let result = render_with_config(&doc, &config).unwrap();
+        assert_eq!(
+            result,
+            "#import \"@preview/mitex:0.2.5\": *\n\nSee #mi(\"\\\\foo{x}\")\n"
+        );
+    }
+
     #[test]
     fn test_render_bold_italic() {
         let doc = Document {
@@ -346,6 +699,33 @@ mod tests {
         assert_eq!(result, "- Item 1\n- Item 2\n");
     }
 
+    #[test]
+    fn test_render_nested_list() {
+        let paragraph = |text: &str| Block::Paragraph(vec![Inline::Text(text.to_string())]);
+        let doc = Document {
+            metadata: Metadata::default(),
+            content: vec![Block::List {
+                kind: ListKind::Unordered,
+                items: vec![
+                    vec![
+                        paragraph("Item 1"),
+                        Block::List {
+                            kind: ListKind::Ordered,
+                            items: vec![vec![paragraph("Sub 1")], vec![paragraph("Sub 2")]],
+                        },
+                    ],
+                    vec![paragraph("Item 2"), paragraph("More")],
+                ],
+            }],
+        };
+
+        let result = render(&doc).unwrap();
+        assert_eq!(
+            result,
+            "- Item 1\n  + Sub 1\n  + Sub 2\n- Item 2\n\n  More\n"
+        );
+    }
+
     #[test]
     fn test_render_code_block() {
         let doc = Document {
@@ -395,8 +775,8 @@ mod tests {
         let doc = Document {
             metadata: Metadata {
                 title: Some("My Document".to_string()),
-                author: Some("Author Name".to_string()),
-                date: None,
+                authors: vec![Author::new("Author Name")],
+                ..Metadata::default()
             },
             content: vec![Block::Heading {
                 level: 1,
@@ -405,8 +785,71 @@ mod tests {
         };
 
         let result = render(&doc).unwrap();
-        assert!(result.contains("#set document(title: \"My Document\")"));
-        assert!(result.contains("#set document(author: \"Author Name\")"));
+        assert!(result.contains("#set document(title: \"My Document\", author: \"Author Name\")"));
         assert!(result.contains("= Introduction"));
+        assert!(!result.contains("#align"));
+    }
+
+    #[test]
+    fn test_render_figure() {
+        let image = Inline::Image {
+            path: "plot.png".to_string(),
+            alt: "A \"plot\"".to_string(),
+            width: Some("50%".to_string()),
+            height: None,
+        };
+        let doc = Document {
+            metadata: Metadata::default(),
+            content: vec![
+                Block::Paragraph(vec![image.clone()]),
+                Block::Figure {
+                    content: vec![Block::Paragraph(vec![image])],
+                    caption: Some(vec![Inline::Text("Results".to_string())]),
+                    label: Some("fig:plot".to_string()),
+                },
+            ],
+        };
+
+        let result = render(&doc).unwrap();
+        assert_eq!(
+            result,
+            "#image(\"plot.png\", width: 50%, alt: \"A \\\"plot\\\"\")\n\n\
+             #figure(\n  image(\"plot.png\", width: 50%, alt: \"A \\\"plot\\\"\"),\n  \
+             caption: [Results],\n) <fig:plot>\n"
+        );
+    }
+
+    #[test]
+    fn test_render_title_block() {
+        let doc = Document {
+            metadata: Metadata {
+                title: Some("Notes".to_string()),
+                authors: vec![
+                    Author {
+                        email: Some("ada@example.org".to_string()),
+                        thanks: Some("Funded.".to_string()),
+                        ..Author::new("Ada")
+                    },
+                    Author::new("Alan"),
+                ],
+                date: Some("2024-03-01".to_string()),
+                keywords: vec!["math".to_string()],
+                title_block: true,
+                ..Metadata::default()
+            },
+            content: vec![],
+        };
+
+        let result = render(&doc).unwrap();
+        assert!(result.starts_with(
+            "#set document(title: \"Notes\", author: (\"Ada\", \"Alan\"), \
+             date: datetime(year: 2024, month: 3, day: 1), keywords: \"math\")\n"
+        ));
+        assert!(result.contains("#text(size: 1.5em, weight: \"bold\")[Notes]"));
+        assert!(result.contains("columns: (1fr, 1fr)"));
+        assert!(result.contains(
+            "[Ada#footnote[Funded.] \\ #link(\"mailto:ada@example.org\")[ada\\@example.org]],"
+        ));
+        assert!(result.contains("\n  2024-03-01\n]"));
     }
 }
@@ -50,6 +50,10 @@ pub fn convert_latex(input: &str) -> Result<String> {
 }
 
 /// Convert Markdown with LaTeX math to Typst
+///
+/// Math is read between `$...$` and `$$...$$` only; use a [`Converter`] with
+/// [`MathDelimiters::DollarsAndBrackets`](parser::markdown::MathDelimiters) to
+/// also read `\(...\)` and `\[...\]`.
 pub fn convert_markdown(input: &str) -> Result<String> {
     // Parse Markdown to AST
     let document = parser::markdown::parse(input)?;
@@ -76,11 +80,32 @@ pub struct Config {
     pub strict_mode: bool,
     /// Preserve LaTeX comments in output (default: false)
     pub preserve_comments: bool,
+    /// How to write letter runs in math that are not Typst identifiers (default: split)
+    pub math_identifiers: converter::math::IdentifierPolicy,
+    /// What to emit for math that cannot be converted (default: the LaTeX source as text)
+    pub math_fallback: MathFallback,
+    /// How to write physics notation like \dv and \ket (default: built-in Typst math)
+    pub math_physics: converter::math::PhysicsNotation,
+    /// How to write siunitx quantities like \SI{9.81}{\meter} (default: built-in Typst math)
+    pub math_units: converter::math::UnitNotation,
+    /// How to write mhchem formulas like \ce{H2O} (default: built-in Typst math)
+    pub math_chemistry: converter::math::ChemistryNotation,
+    /// Which delimiters mark math in Markdown (default: only dollars, so `\(...\)` and `\[...\]` stay text)
+    pub math_delimiters: parser::markdown::MathDelimiters,
+}
+
+/// Output for math formulas that cannot be converted to native Typst math
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MathFallback {
+    /// Show the LaTeX source as plain text
+    #[default]
+    Text,
+    /// Render the LaTeX source with the `mitex` package: `#mi("...")` and `#mitex("...")`
+    Mitex,
 }
 
 /// Advanced converter with configuration
 pub struct Converter {
-    #[allow(dead_code)]
     config: Config,
 }
 
@@ -99,11 +124,17 @@ impl Converter {
 
     /// Convert input to Typst with specified format
     pub fn convert(&self, input: &str, format: InputFormat) -> Result<String> {
-        match format {
-            InputFormat::Auto => convert(input),
-            InputFormat::Latex => convert_latex(input),
-            InputFormat::Markdown => convert_markdown(input),
-        }
+        let format = match format {
+            InputFormat::Auto => detector::detect_format(input),
+            format => format,
+        };
+        let document = match format {
+            InputFormat::Latex => parser::latex::document::parse(input)?,
+            InputFormat::Markdown | InputFormat::Auto => {
+                parser::markdown::parse_with_delimiters(input, self.config.math_delimiters)?
+            }
+        };
+        converter::typst::render_with_config(&document, &self.config)
     }
 }
 
@@ -0,0 +1,559 @@
+//! Front matter of Markdown documents
+//!
+//! Front matter is read with small parsers for the part of YAML and TOML that metadata
+//! uses: strings, lists and tables of strings. Values of other types are kept as text.
+
+use crate::ast::document::{Author, Metadata};
+
+/// A front matter value
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Text(String),
+    List(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Look up a key in a table
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Table(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// The value as a single string; tables give their `name` field
+    fn text(&self) -> Option<String> {
+        let text = match self {
+            Value::Text(text) => text.trim().to_string(),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().filter_map(Value::text).collect();
+                items.join(", ")
+            }
+            Value::Table(_) => self.get("name")?.text()?,
+        };
+        Some(text).filter(|text| !text.is_empty())
+    }
+
+    /// The value as a list of strings, splitting text at `separator`
+    fn texts(&self, separator: Option<char>) -> Vec<String> {
+        match (self, separator) {
+            (Value::Text(text), Some(separator)) => text
+                .split(separator)
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect(),
+            (Value::List(items), _) => items.iter().filter_map(Value::text).collect(),
+            _ => self.text().into_iter().collect(),
+        }
+    }
+}
+
+/// Split front matter off the start of a Markdown document: YAML between `---` lines
+/// (or closed by `...`), or TOML between `+++` lines. Returns the metadata and the rest.
+pub fn split(input: &str) -> Option<(Metadata, &str)> {
+    let (first, rest) = input.split_once('\n')?;
+    let (closers, parse): (&[&str], fn(&str) -> Metadata) = match first.trim_end() {
+        "---" => (&["---", "..."], parse_yaml),
+        "+++" => (&["+++"], parse_toml),
+        _ => return None,
+    };
+    // A `---` followed by a blank line is a thematic break
+    if rest.trim_start_matches([' ', '\t', '\r']).starts_with('\n') {
+        return None;
+    }
+
+    let start = input.len() - rest.len();
+    let mut end = start;
+    for line in rest.split_inclusive('\n') {
+        if closers.contains(&line.trim_end()) {
+            return Some((parse(&input[start..end]), &input[end + line.len()..]));
+        }
+        end += line.len();
+    }
+    None
+}
+
+/// Parse YAML front matter (without the `---` lines) into metadata
+pub fn parse_yaml(source: &str) -> Metadata {
+    let mut lines: Vec<YamlLine> = source
+        .lines()
+        .map(|line| {
+            let text = line.trim_start();
+            YamlLine {
+                indent: line.len() - text.len(),
+                text: text.trim_end(),
+            }
+        })
+        .collect();
+    let mut pos = 0;
+    let value = parse_yaml_node(&mut lines, &mut pos, 0);
+    to_metadata(&value)
+}
+
+/// Parse TOML front matter (without the `+++` lines) into metadata
+pub fn parse_toml(source: &str) -> Metadata {
+    let mut root = Vec::new();
+    // The table that keys are added to: its name and whether it is an array of tables
+    let mut table: Option<(String, bool)> = None;
+    let mut rest = source;
+    loop {
+        rest = skip_toml_space(rest);
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(header) = rest.strip_prefix('[') {
+            let array = header.starts_with('[');
+            let header = header.trim_start_matches('[');
+            let end = header.find(']').unwrap_or(header.len());
+            let name = header[..end].trim().trim_matches('"').to_string();
+            if array {
+                push_field(&mut root, &name, Value::List(Vec::new()));
+                if let Some((_, Value::List(tables))) = root.iter_mut().find(|(k, _)| *k == name) {
+                    tables.push(Value::Table(Vec::new()));
+                }
+            } else {
+                push_field(&mut root, &name, Value::Table(Vec::new()));
+            }
+            table = Some((name, array));
+            rest = header[end..].trim_start_matches(']');
+            continue;
+        }
+
+        let end = rest.find(['=', '\n']).unwrap_or(rest.len());
+        let key = rest[..end].trim().trim_matches(['"', '\'']).to_string();
+        if !rest[end..].starts_with('=') {
+            // Not a key-value pair: skip the line
+            rest = &rest[end..];
+            continue;
+        }
+        let (value, remaining) = parse_toml_value(rest[end + 1..].trim_start_matches([' ', '\t']));
+        rest = remaining;
+
+        let fields = match &table {
+            None => &mut root,
+            Some((name, array)) => match root.iter_mut().find(|(k, _)| k == name) {
+                Some((_, Value::Table(fields))) if !array => fields,
+                Some((_, Value::List(tables))) => match tables.last_mut() {
+                    Some(Value::Table(fields)) => fields,
+                    _ => continue,
+                },
+                _ => continue,
+            },
+        };
+        fields.push((key, value));
+    }
+    to_metadata(&Value::Table(root))
+}
+
+/// Add a field unless the table already has it
+fn push_field(fields: &mut Vec<(String, Value)>, key: &str, value: Value) {
+    if !fields.iter().any(|(k, _)| k == key) {
+        fields.push((key.to_string(), value));
+    }
+}
+
+/// Read the metadata fields out of the top-level table
+fn to_metadata(value: &Value) -> Metadata {
+    let field = |keys: &[&str]| keys.iter().find_map(|key| value.get(key));
+    let abstract_text = field(&["abstract"]).and_then(Value::text);
+    Metadata {
+        title: field(&["title"]).and_then(Value::text),
+        authors: field(&["author", "authors"])
+            .map(to_authors)
+            .unwrap_or_default(),
+        date: field(&["date"]).and_then(Value::text),
+        keywords: field(&["keywords", "tags"])
+            .map(|v| v.texts(Some(',')))
+            .unwrap_or_default(),
+        lang: field(&["lang", "language"]).and_then(Value::text),
+        r#abstract: abstract_text
+            .and_then(|text| super::markdown::parse(&text).ok())
+            .map(|document| document.content)
+            .unwrap_or_default(),
+        bibliography: field(&["bibliography"])
+            .map(|v| v.texts(None))
+            .unwrap_or_default(),
+        title_block: false,
+    }
+}
+
+/// Read authors given as names or as tables with a name and details
+fn to_authors(value: &Value) -> Vec<Author> {
+    let items = match value {
+        Value::List(items) => items.as_slice(),
+        value => std::slice::from_ref(value),
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let detail = |keys: &[&str]| keys.iter().find_map(|key| item.get(key)?.text());
+            Some(Author {
+                name: item.text()?,
+                affiliation: detail(&["affiliation", "institute", "organization"]),
+                email: detail(&["email"]),
+                thanks: detail(&["thanks", "note"]),
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct YamlLine<'a> {
+    indent: usize,
+    text: &'a str,
+}
+
+impl YamlLine<'_> {
+    fn is_blank(&self) -> bool {
+        self.text.is_empty() || self.text.starts_with('#')
+    }
+
+    fn is_list_item(&self) -> bool {
+        self.text == "-" || self.text.starts_with("- ")
+    }
+}
+
+/// Parse the YAML block starting at `pos`, which must be indented by at least `indent`
+fn parse_yaml_node(lines: &mut [YamlLine], pos: &mut usize, indent: usize) -> Value {
+    while lines.get(*pos).is_some_and(YamlLine::is_blank) {
+        *pos += 1;
+    }
+    let Some(first) = lines.get(*pos).copied() else {
+        return Value::Text(String::new());
+    };
+    if first.indent < indent {
+        return Value::Text(String::new());
+    }
+
+    let mut items = Vec::new();
+    let mut fields = Vec::new();
+    while let Some(line) = lines.get(*pos).copied() {
+        if line.is_blank() {
+            *pos += 1;
+            continue;
+        }
+        if line.indent != first.indent || line.is_list_item() != first.is_list_item() {
+            break;
+        }
+
+        if first.is_list_item() {
+            let rest = line.text[1..].trim_start();
+            if rest.is_empty() {
+                *pos += 1;
+                items.push(parse_yaml_node(lines, pos, line.indent + 1));
+            } else if split_yaml_key(rest).is_some() && !rest.starts_with(['"', '\'', '[']) {
+                // A table inside the list item: read it from the item's content on
+                lines[*pos] = YamlLine {
+                    indent: line.indent + line.text.len() - rest.len(),
+                    text: rest,
+                };
+                let indent = lines[*pos].indent;
+                items.push(parse_yaml_node(lines, pos, indent));
+            } else {
+                *pos += 1;
+                items.push(parse_yaml_flow(rest));
+            }
+            continue;
+        }
+
+        *pos += 1;
+        let Some((key, value)) = split_yaml_key(line.text) else {
+            continue;
+        };
+        let value = match value {
+            "" => match lines[*pos..].iter().find(|l| !l.is_blank()) {
+                Some(next)
+                    if next.indent > line.indent
+                        || (next.indent == line.indent && next.is_list_item()) =>
+                {
+                    parse_yaml_node(lines, pos, line.indent)
+                }
+                _ => Value::Text(String::new()),
+            },
+            _ if value.starts_with(['|', '>']) => {
+                Value::Text(parse_yaml_block_scalar(lines, pos, line.indent, value))
+            }
+            _ => parse_yaml_flow(value),
+        };
+        fields.push((key.to_string(), value));
+    }
+
+    if first.is_list_item() {
+        Value::List(items)
+    } else {
+        Value::Table(fields)
+    }
+}
+
+/// Split `key: value` into its key and value
+fn split_yaml_key(text: &str) -> Option<(&str, &str)> {
+    let key_end = if let Some(quoted) = text.strip_prefix('"') {
+        quoted.find('"')? + 2
+    } else {
+        0
+    };
+    let colon = key_end + text[key_end..].find(':')?;
+    let value = &text[colon + 1..];
+    if !value.is_empty() && !value.starts_with([' ', '\t']) {
+        return None;
+    }
+    let key = text[..colon].trim().trim_matches('"');
+    Some((key, value.trim())).filter(|(key, _)| !key.is_empty())
+}
+
+/// Read the indented lines of a `|` or `>` block scalar
+fn parse_yaml_block_scalar(
+    lines: &[YamlLine],
+    pos: &mut usize,
+    indent: usize,
+    style: &str,
+) -> String {
+    let start = *pos;
+    while lines
+        .get(*pos)
+        .is_some_and(|line| line.text.is_empty() || line.indent > indent)
+    {
+        *pos += 1;
+    }
+    let content: Vec<&str> = lines[start..*pos].iter().map(|line| line.text).collect();
+    if style.starts_with('|') {
+        return content.join("\n");
+    }
+    // Folded scalars join lines with spaces, keeping empty lines as paragraph breaks
+    content
+        .split(|line| line.is_empty())
+        .map(|paragraph| paragraph.join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Parse a value written on one line: a quoted or plain string or a `[...]` list
+fn parse_yaml_flow(text: &str) -> Value {
+    if let Some(list) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        let mut items = Vec::new();
+        let mut rest = list.trim();
+        while !rest.is_empty() {
+            let end = if rest.starts_with(['"', '\'']) {
+                let quote = rest.as_bytes()[0] as char;
+                rest[1..].find(quote).map_or(rest.len(), |n| n + 2)
+            } else {
+                rest.find(',').unwrap_or(rest.len())
+            };
+            items.push(parse_yaml_flow(rest[..end].trim()));
+            rest = rest[end..].trim_start_matches([',', ' ']);
+        }
+        return Value::List(items);
+    }
+    if let Some(quoted) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        return Value::Text(unescape(quoted));
+    }
+    if let Some(quoted) = text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        return Value::Text(quoted.replace("''", "'"));
+    }
+    // Plain scalars end at a comment
+    let text = text.find(" #").map_or(text, |n| &text[..n]);
+    Value::Text(text.trim().to_string())
+}
+
+/// Skip whitespace, newlines and comments between TOML statements
+fn skip_toml_space(mut input: &str) -> &str {
+    loop {
+        input = input.trim_start();
+        match input.strip_prefix('#') {
+            Some(comment) => input = comment.find('\n').map_or("", |n| &comment[n..]),
+            None => return input,
+        }
+    }
+}
+
+/// Parse a TOML value, returning it with the remaining input
+fn parse_toml_value(input: &str) -> (Value, &str) {
+    if let Some(rest) = input.strip_prefix("\"\"\"") {
+        let end = rest.find("\"\"\"").unwrap_or(rest.len());
+        let text = unescape(rest[..end].strip_prefix('\n').unwrap_or(&rest[..end]));
+        return (Value::Text(text), rest.get(end + 3..).unwrap_or(""));
+    }
+    if let Some(rest) = input.strip_prefix("'''") {
+        let end = rest.find("'''").unwrap_or(rest.len());
+        let text = rest[..end].strip_prefix('\n').unwrap_or(&rest[..end]);
+        return (
+            Value::Text(text.to_string()),
+            rest.get(end + 3..).unwrap_or(""),
+        );
+    }
+    if let Some(rest) = input.strip_prefix('"') {
+        let mut end = 0;
+        while let Some(n) = rest[end..].find(['"', '\\']) {
+            end += n;
+            if rest[end..].starts_with('"') {
+                break;
+            }
+            end += rest[end..]
+                .chars()
+                .take(2)
+                .map(char::len_utf8)
+                .sum::<usize>();
+        }
+        let end = end.min(rest.len());
+        return (
+            Value::Text(unescape(&rest[..end])),
+            rest.get(end + 1..).unwrap_or(""),
+        );
+    }
+    if let Some(rest) = input.strip_prefix('\'') {
+        let end = rest.find('\'').unwrap_or(rest.len());
+        return (
+            Value::Text(rest[..end].to_string()),
+            rest.get(end + 1..).unwrap_or(""),
+        );
+    }
+    if let Some(mut rest) = input.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = skip_toml_space(rest).trim_start_matches(',');
+            rest = skip_toml_space(rest);
+            if rest.is_empty() {
+                return (Value::List(items), rest);
+            }
+            if let Some(after) = rest.strip_prefix(']') {
+                return (Value::List(items), after);
+            }
+            let (item, after) = parse_toml_value(rest);
+            items.push(item);
+            rest = after;
+        }
+    }
+    if let Some(mut rest) = input.strip_prefix('{') {
+        let mut fields = Vec::new();
+        loop {
+            rest = rest.trim_start_matches([' ', '\t', ',']);
+            if rest.is_empty() {
+                return (Value::Table(fields), rest);
+            }
+            if let Some(after) = rest.strip_prefix('}') {
+                return (Value::Table(fields), after);
+            }
+            let Some(eq) = rest.find('=') else {
+                return (Value::Table(fields), "");
+            };
+            let key = rest[..eq].trim().trim_matches(['"', '\'']).to_string();
+            let (value, after) = parse_toml_value(rest[eq + 1..].trim_start());
+            fields.push((key, value));
+            rest = after;
+        }
+    }
+    // Numbers, booleans and dates end at a delimiter
+    let end = input
+        .find([',', ']', '}', '\n', '#'])
+        .unwrap_or(input.len());
+    (Value::Text(input[..end].trim().to_string()), &input[end..])
+}
+
+/// Resolve the backslash escapes of double-quoted strings
+fn unescape(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('t') => output.push('\t'),
+            Some(other) => output.push(other),
+            None => output.push('\\'),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::document::{Block, Inline};
+
+    #[test]
+    fn test_parse_yaml() {
+        let metadata = parse_yaml(
+            "title: \"On Typst: A Study\"\n\
+             author:\n  - Ada Lovelace\n  - name: Alan Turing\n    affiliation: Manchester\n\
+             date: 2024-03-01 # published\n\
+             keywords: [typst, 'latex']\n\
+             lang: en-GB\n\
+             abstract: |\n  We study $x$.\n\n  Second paragraph.\n\
+             bibliography: refs.bib\n",
+        );
+        assert_eq!(metadata.title.as_deref(), Some("On Typst: A Study"));
+        assert_eq!(metadata.authors[0], Author::new("Ada Lovelace"));
+        assert_eq!(metadata.authors[1].name, "Alan Turing");
+        assert_eq!(
+            metadata.authors[1].affiliation.as_deref(),
+            Some("Manchester")
+        );
+        assert_eq!(metadata.date.as_deref(), Some("2024-03-01"));
+        assert_eq!(metadata.keywords, vec!["typst", "latex"]);
+        assert_eq!(metadata.lang.as_deref(), Some("en-GB"));
+        assert_eq!(metadata.r#abstract.len(), 2);
+        assert!(matches!(
+            &metadata.r#abstract[0],
+            Block::Paragraph(content) if matches!(content[1], Inline::MathInline(_))
+        ));
+        assert_eq!(metadata.bibliography, vec!["refs.bib"]);
+    }
+
+    #[test]
+    fn test_split() {
+        let (metadata, rest) = split("---\ntitle: A\n---\n\n# Body\n").unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("A"));
+        assert_eq!(rest, "\n# Body\n");
+
+        let (metadata, rest) = split("+++\ntitle = \"B\"\n+++\nBody").unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("B"));
+        assert_eq!(rest, "Body");
+
+        // Only the start of the document, and not a thematic break
+        assert!(split("Intro\n---\ntitle: A\n---\n").is_none());
+        assert!(split("---\n\ntext\n---\n").is_none());
+        assert!(split("---\ntitle: A\n").is_none());
+    }
+
+    #[test]
+    fn test_parse_yaml_scalars() {
+        let metadata = parse_yaml("author: Jane Doe\nkeywords: a, b\nabstract: >\n  One\n  line\n");
+        assert_eq!(metadata.authors, vec![Author::new("Jane Doe")]);
+        assert_eq!(metadata.keywords, vec!["a", "b"]);
+        assert_eq!(
+            metadata.r#abstract,
+            vec![Block::Paragraph(vec![Inline::Text("One line".to_string())])]
+        );
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let metadata = parse_toml(
+            "title = \"Notes\" # comment\n\
+             date = 2024-03-01\n\
+             keywords = [\n  \"one\",\n  \"two\",\n]\n\
+             bibliography = ['a.bib', \"b.bib\"]\n\
+             [[authors]]\nname = \"Ada\"\n[[authors]]\nname = \"Alan\"\n",
+        );
+        assert_eq!(metadata.title.as_deref(), Some("Notes"));
+        assert_eq!(metadata.date.as_deref(), Some("2024-03-01"));
+        assert_eq!(metadata.keywords, vec!["one", "two"]);
+        assert_eq!(metadata.bibliography, vec!["a.bib", "b.bib"]);
+        assert_eq!(
+            metadata.authors,
+            vec![Author::new("Ada"), Author::new("Alan")]
+        );
+
+        let metadata = parse_toml("author = [{ name = \"Ada\" }, \"Alan\"]\nlang = \"de\"");
+        assert_eq!(
+            metadata.authors,
+            vec![Author::new("Ada"), Author::new("Alan")]
+        );
+        assert_eq!(metadata.lang.as_deref(), Some("de"));
+    }
+}
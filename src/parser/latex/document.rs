@@ -10,12 +10,15 @@ use nom::{
     IResult,
 };
 
-use crate::ast::document::{Block, Document, Inline, ListKind, Metadata, Style};
+use super::math::OperatorDeclarations;
+use crate::ast::document::{Author, Block, Document, Inline, ListKind, Metadata, Style};
 use crate::error::{Error, Result};
 
 /// Parse a complete LaTeX document into a Document AST
 pub fn parse(input: &str) -> Result<Document> {
-    match parse_document(input) {
+    let verbatim = verbatim_ranges(input);
+    let (operators, input) = OperatorDeclarations::extract(input, &verbatim);
+    match parse_document(&input, &operators) {
         Ok((remaining, doc)) => {
             if remaining.trim().is_empty() {
                 Ok(doc)
@@ -31,20 +34,36 @@ pub fn parse(input: &str) -> Result<Document> {
     }
 }
 
+/// Byte ranges of verbatim environments, whose content is not LaTeX
+fn verbatim_ranges(input: &str) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = input[offset..].find("\\begin{verbatim}") {
+        let start = offset + start;
+        let end = input[start..]
+            .find("\\end{verbatim}")
+            .map_or(input.len(), |end| start + end);
+        ranges.push(start..end);
+        offset = end;
+    }
+    ranges
+}
+
 /// Parse a complete LaTeX document
-fn parse_document(input: &str) -> IResult<&str, Document> {
+fn parse_document<'a>(
+    input: &'a str,
+    operators: &OperatorDeclarations,
+) -> IResult<&'a str, Document> {
     let (input, _) = skip_whitespace_and_comments(input)?;
 
     // Parse optional preamble (documentclass, packages, etc.)
     let (input, metadata) = opt(parse_preamble)(input)?;
+    let mut metadata = metadata.unwrap_or_default();
 
     // Parse document body (between \begin{document} and \end{document})
-    let (input, content) = parse_document_body(input)?;
+    let (input, content) = parse_document_body(input, &mut metadata, operators)?;
 
-    let doc = Document {
-        metadata: metadata.unwrap_or_default(),
-        content,
-    };
+    let doc = Document { metadata, content };
 
     Ok((input, doc))
 }
@@ -63,14 +82,7 @@ fn parse_preamble(input: &str) -> IResult<&str, Metadata> {
 
     // Parse any number of preamble commands
     while let Ok((input, cmd)) = parse_preamble_command(current_input) {
-        match cmd {
-            PreambleCommand::Title(title) => metadata.title = Some(title),
-            PreambleCommand::Author(author) => metadata.author = Some(author),
-            PreambleCommand::Date(date) => metadata.date = Some(date),
-            PreambleCommand::UsePackage(_) => {
-                // Ignore package imports for now
-            }
-        }
+        apply_preamble_command(&mut metadata, cmd);
         current_input = input;
 
         // Check if we've reached \begin{document}
@@ -88,7 +100,144 @@ enum PreambleCommand {
     Title(String),
     Author(String),
     Date(String),
+    Keywords(String),
+    Affiliation(String),
+    Email(String),
     UsePackage(String),
+    MathOperator,
+}
+
+/// Record a preamble command in the metadata
+fn apply_preamble_command(metadata: &mut Metadata, cmd: PreambleCommand) {
+    match cmd {
+        PreambleCommand::Title(title) => metadata.title = Some(plain_text(&title)),
+        PreambleCommand::Author(authors) => metadata.authors = parse_authors(&authors),
+        PreambleCommand::Date(date) => metadata.date = Some(date.trim().to_string()),
+        PreambleCommand::Keywords(keywords) => {
+            metadata.keywords = keywords
+                .replace("\\sep", ",")
+                .split([',', ';'])
+                .map(plain_text)
+                .filter(|keyword| !keyword.is_empty())
+                .collect();
+        }
+        // Affiliations and emails given separately belong to the last author
+        PreambleCommand::Affiliation(affiliation) => {
+            if let Some(author) = metadata.authors.last_mut() {
+                author.affiliation = Some(plain_text(&affiliation));
+            }
+        }
+        PreambleCommand::Email(email) => {
+            if let Some(author) = metadata.authors.last_mut() {
+                author.email = Some(plain_text(&email));
+            }
+        }
+        PreambleCommand::UsePackage(_) => {
+            // Ignore package imports for now
+        }
+        PreambleCommand::MathOperator => {
+            // Collected beforehand by OperatorDeclarations::extract
+        }
+    }
+}
+
+/// Split the argument of `\author` into authors at `\and`. Each author has a name,
+/// then optionally affiliation and email lines separated by `\\`, and `\thanks` notes.
+fn parse_authors(source: &str) -> Vec<Author> {
+    split_top_level(source, "\\and")
+        .into_iter()
+        .filter_map(|source| {
+            let (source, thanks) = take_command_arg(source, "thanks");
+            let (source, email) = take_command_arg(&source, "email");
+            let mut lines = split_top_level(&source, "\\\\")
+                .into_iter()
+                .map(plain_text)
+                .filter(|line| !line.is_empty());
+            let mut author = Author::new(lines.next()?);
+            author.thanks = thanks.map(|thanks| plain_text(&thanks));
+            author.email = email.map(|email| plain_text(&email));
+            let mut affiliation = Vec::new();
+            for line in lines {
+                if author.email.is_none() && line.contains('@') && !line.contains(' ') {
+                    author.email = Some(line);
+                } else {
+                    affiliation.push(line);
+                }
+            }
+            if !affiliation.is_empty() {
+                author.affiliation = Some(affiliation.join(", "));
+            }
+            Some(author)
+        })
+        .collect()
+}
+
+/// Split LaTeX source at a separator command outside of braces
+fn split_top_level<'a>(source: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in source.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '\\' if depth == 0 && i >= start && source[i..].starts_with(separator) => {
+                // `\and` must not match the start of a longer command name
+                let next = source[i + separator.len()..].chars().next();
+                if !next.is_some_and(|c| c.is_ascii_alphabetic()) {
+                    parts.push(&source[start..i]);
+                    start = i + separator.len();
+                }
+            }
+            _ => {}
+        }
+    }
+    parts.push(&source[start..]);
+    parts
+}
+
+/// Remove the first `\name{...}` from LaTeX source, returning the rest and the argument
+fn take_command_arg(source: &str, name: &str) -> (String, Option<String>) {
+    let command = format!("\\{}", name);
+    let mut search = 0;
+    while let Some(n) = source[search..].find(&command) {
+        let start = search + n;
+        let after = source[start + command.len()..].trim_start();
+        if let Ok((rest, arg)) = super::math::parse_braced_raw(after) {
+            let remaining = format!("{}{}", &source[..start], rest);
+            return (remaining, Some(arg.to_string()));
+        }
+        search = start + command.len();
+    }
+    (source.to_string(), None)
+}
+
+/// Reduce LaTeX source to its text: command names and braces are dropped, escaped
+/// characters kept, and whitespace collapsed
+fn plain_text(source: &str) -> String {
+    let mut text = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.peek() {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    while chars.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                        chars.next();
+                    }
+                }
+                Some(',') | Some('\\') => {
+                    chars.next();
+                    text.push(' ');
+                }
+                Some(_) => text.extend(chars.next()),
+                None => {}
+            },
+            '{' | '}' => {}
+            '~' => text.push(' '),
+            c => text.push(c),
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
 /// Parse preamble commands like \title, \author, \usepackage
@@ -99,17 +248,22 @@ fn parse_preamble_command(input: &str) -> IResult<&str, PreambleCommand> {
     let (input, _) = space0(input)?;
 
     match cmd_name {
-        "title" => {
-            let (input, title) = parse_braced_arg(input)?;
-            Ok((input, PreambleCommand::Title(title)))
-        }
-        "author" => {
-            let (input, author) = parse_braced_arg(input)?;
-            Ok((input, PreambleCommand::Author(author)))
-        }
-        "date" => {
-            let (input, date) = parse_braced_arg(input)?;
-            Ok((input, PreambleCommand::Date(date)))
+        "title" | "author" | "date" | "keywords" | "affil" | "affiliation" | "institute"
+        | "email" => {
+            // Short forms for running heads, like \title[Short]{...}, are not needed
+            let (input, _) = opt(delimited(char('['), take_until("]"), char(']')))(input)?;
+            let (input, _) = space0(input)?;
+            let (input, arg) = super::math::parse_braced_raw(input)?;
+            let arg = arg.to_string();
+            let cmd = match cmd_name {
+                "title" => PreambleCommand::Title(arg),
+                "author" => PreambleCommand::Author(arg),
+                "date" => PreambleCommand::Date(arg),
+                "keywords" => PreambleCommand::Keywords(arg),
+                "email" => PreambleCommand::Email(arg),
+                _ => PreambleCommand::Affiliation(arg),
+            };
+            Ok((input, cmd))
         }
         "usepackage" => {
             // Optional argument in brackets
@@ -117,6 +271,12 @@ fn parse_preamble_command(input: &str) -> IResult<&str, PreambleCommand> {
             let (input, pkg) = parse_braced_arg(input)?;
             Ok((input, PreambleCommand::UsePackage(pkg)))
         }
+        "DeclareMathOperator" => {
+            let (input, _) = opt(char('*'))(input)?;
+            let (input, _) = parse_braced_arg(input)?;
+            let (input, _) = parse_braced_arg(input)?;
+            Ok((input, PreambleCommand::MathOperator))
+        }
         _ => {
             // Unknown command - skip it
             let (input, _) = opt(parse_braced_arg)(input)?;
@@ -141,13 +301,38 @@ fn parse_documentclass(input: &str) -> IResult<&str, ()> {
     Ok((input, ()))
 }
 
-/// Parse the document body (between \begin{document} and \end{document})
-fn parse_document_body(input: &str) -> IResult<&str, Vec<Block>> {
-    let (input, _) = skip_whitespace_and_comments(input)?;
-    let (input, _) = tag("\\begin{document}")(input)?;
+/// Parse the document body (between \begin{document} and \end{document}). The title
+/// commands, `\maketitle` and the abstract found there go into the metadata.
+fn parse_document_body<'a>(
+    input: &'a str,
+    metadata: &mut Metadata,
+    operators: &OperatorDeclarations,
+) -> IResult<&'a str, Vec<Block>> {
     let (input, _) = skip_whitespace_and_comments(input)?;
+    let (mut input, _) = tag("\\begin{document}")(input)?;
 
-    let (input, blocks) = many0(parse_block)(input)?;
+    let mut blocks = Vec::new();
+    loop {
+        input = skip_whitespace_and_comments(input)?.0;
+        if let Some(rest) = input.strip_prefix("\\maketitle") {
+            metadata.title_block = true;
+            input = rest;
+        } else if let Some(rest) = input.strip_prefix("\\begin{abstract}") {
+            let (rest, content) = many0(|i| parse_block(i, operators))(rest)?;
+            let (rest, _) = skip_whitespace_and_comments(rest)?;
+            let (rest, _) = tag("\\end{abstract}")(rest)?;
+            metadata.r#abstract = content;
+            input = rest;
+        } else if let Ok((rest, cmd)) = parse_preamble_command(input) {
+            apply_preamble_command(metadata, cmd);
+            input = rest;
+        } else if let Ok((rest, block)) = parse_block(input, operators) {
+            blocks.push(block);
+            input = rest;
+        } else {
+            break;
+        }
+    }
 
     let (input, _) = skip_whitespace_and_comments(input)?;
     let (input, _) = tag("\\end{document}")(input)?;
@@ -156,10 +341,14 @@ fn parse_document_body(input: &str) -> IResult<&str, Vec<Block>> {
 }
 
 /// Parse a block-level element
-fn parse_block(input: &str) -> IResult<&str, Block> {
+fn parse_block<'a>(input: &'a str, operators: &OperatorDeclarations) -> IResult<&'a str, Block> {
     let (input, _) = skip_whitespace_and_comments(input)?;
 
-    alt((parse_section, parse_environment, parse_paragraph))(input)
+    alt((
+        parse_section,
+        |i| parse_environment(i, operators),
+        |i| parse_paragraph(i, operators),
+    ))(input)
 }
 
 /// Parse sectioning commands (\section, \subsection, etc.)
@@ -199,7 +388,10 @@ fn parse_section(input: &str) -> IResult<&str, Block> {
 }
 
 /// Parse an environment (\begin{...} ... \end{...})
-fn parse_environment(input: &str) -> IResult<&str, Block> {
+fn parse_environment<'a>(
+    input: &'a str,
+    operators: &OperatorDeclarations,
+) -> IResult<&'a str, Block> {
     let (input, _) = tag("\\begin{")(input)?;
     let (input, env_name) = alpha1(input)?;
     let (input, _) = char('}')(input)?;
@@ -210,6 +402,7 @@ fn parse_environment(input: &str) -> IResult<&str, Block> {
         "enumerate" => parse_list_environment(input, env_name, ListKind::Ordered),
         "description" => parse_list_environment(input, env_name, ListKind::Description),
         "verbatim" => parse_verbatim_environment(input, env_name),
+        "figure" => parse_figure_environment(input, env_name, operators),
         _ => {
             // Unknown environment - skip it
             let (input, _) = take_until(&format!("\\end{{{}}}", env_name)[..])(input)?;
@@ -272,9 +465,68 @@ fn parse_verbatim_environment<'a>(input: &'a str, env_name: &'a str) -> IResult<
     ))
 }
 
+/// Parse a figure environment: its images, caption and label
+fn parse_figure_environment<'a>(
+    input: &'a str,
+    env_name: &'a str,
+    operators: &OperatorDeclarations,
+) -> IResult<&'a str, Block> {
+    // Placement like [htbp] does not carry over to Typst
+    let (input, _) = opt(delimited(char('['), take_until("]"), char(']')))(input)?;
+    let (input, body) = take_until(&format!("\\end{{{}}}", env_name)[..])(input)?;
+    let (input, _) = tag(&format!("\\end{{{}}}", env_name)[..])(input)?;
+
+    let mut images = Vec::new();
+    for (i, _) in body.match_indices("\\includegraphics") {
+        if let Ok((_, image)) = parse_inline_command(&body[i..]) {
+            if !images.is_empty() {
+                images.push(Inline::Text(" ".to_string()));
+            }
+            images.push(image);
+        }
+    }
+    let caption = take_command_arg(body, "caption").1.and_then(|caption| {
+        parse_paragraph_content(&caption, operators)
+            .ok()
+            .map(|(_, c)| c)
+    });
+    let label = take_command_arg(body, "label").1;
+
+    Ok((
+        input,
+        Block::Figure {
+            content: vec![Block::Paragraph(images)],
+            caption,
+            label,
+        },
+    ))
+}
+
+/// Convert a LaTeX length to a Typst one, giving fractions of the text width as
+/// percentages. Lengths in units Typst lacks give `None`.
+fn typst_length(value: &str) -> Option<String> {
+    let value = value.trim();
+    for reference in ["\\textwidth", "\\linewidth", "\\columnwidth"] {
+        if let Some(factor) = value.strip_suffix(reference) {
+            let factor: f64 = match factor.trim() {
+                "" => 1.0,
+                factor => factor.parse().ok()?,
+            };
+            return Some(format!("{}%", (factor * 10000.0).round() / 100.0));
+        }
+    }
+    let unit_start = value.find(|c: char| c.is_ascii_alphabetic())?;
+    let (number, unit) = value.split_at(unit_start);
+    number.trim().parse::<f64>().ok()?;
+    matches!(unit, "pt" | "mm" | "cm" | "in" | "em").then(|| format!("{}{}", number.trim(), unit))
+}
+
 /// Parse a paragraph (plain text with inline formatting)
-fn parse_paragraph(input: &str) -> IResult<&str, Block> {
-    let (input, content) = parse_paragraph_content(input)?;
+fn parse_paragraph<'a>(
+    input: &'a str,
+    operators: &OperatorDeclarations,
+) -> IResult<&'a str, Block> {
+    let (input, content) = parse_paragraph_content(input, operators)?;
 
     if content.is_empty() {
         Err(nom::Err::Error(nom::error::Error::new(
@@ -287,7 +539,10 @@ fn parse_paragraph(input: &str) -> IResult<&str, Block> {
 }
 
 /// Parse paragraph content (text with inline commands)
-fn parse_paragraph_content(input: &str) -> IResult<&str, Vec<Inline>> {
+fn parse_paragraph_content<'a>(
+    input: &'a str,
+    operators: &OperatorDeclarations,
+) -> IResult<&'a str, Vec<Inline>> {
     let mut inlines = Vec::new();
     let mut current_input = input;
 
@@ -306,7 +561,7 @@ fn parse_paragraph_content(input: &str) -> IResult<&str, Vec<Inline>> {
         }
 
         // Try to parse inline command, math, or text
-        if let Ok((input, inline)) = parse_inline_math(current_input) {
+        if let Ok((input, inline)) = parse_inline_math(current_input, operators) {
             inlines.push(inline);
             current_input = input;
         } else if let Ok((input, inline)) = parse_inline_command(current_input) {
@@ -366,6 +621,44 @@ fn parse_inline_command(input: &str) -> IResult<&str, Inline> {
                 },
             ))
         }
+        "ref" | "eqref" => {
+            let (input, label) = parse_braced_arg(input)?;
+            Ok((input, Inline::Ref(label.trim().to_string())))
+        }
+        "includegraphics" => {
+            let (input, _) = opt(char('*'))(input)?;
+            let (input, options) = opt(delimited(char('['), take_until("]"), char(']')))(input)?;
+            let (input, path) = parse_braced_arg(input)?;
+            let mut alt = String::new();
+            let (mut width, mut height) = (None, None);
+            for option in options.unwrap_or("").split(',') {
+                match option.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                    Some(("width", value)) => width = typst_length(value),
+                    Some(("height", value)) => height = typst_length(value),
+                    Some(("alt", value)) => alt = plain_text(value),
+                    _ => {}
+                }
+            }
+            Ok((
+                input,
+                Inline::Image {
+                    path: path.trim().to_string(),
+                    alt,
+                    width,
+                    height,
+                },
+            ))
+        }
+        // siunitx quantities are set as inline math
+        name if super::siunitx::is_command(name) => {
+            let (input, expr) = super::siunitx::parse_command(input, name)?;
+            Ok((input, Inline::MathInline(expr)))
+        }
+        // So are mhchem formulas
+        name if super::mhchem::is_command(name) => {
+            let (input, expr) = super::mhchem::parse_command(input, name)?;
+            Ok((input, Inline::MathInline(expr)))
+        }
         _ => {
             // Unknown command - treat as text
             Err(nom::Err::Error(nom::error::Error::new(
@@ -377,18 +670,18 @@ fn parse_inline_command(input: &str) -> IResult<&str, Inline> {
 }
 
 /// Parse inline math ($...$)
-fn parse_inline_math(input: &str) -> IResult<&str, Inline> {
+fn parse_inline_math<'a>(
+    input: &'a str,
+    operators: &OperatorDeclarations,
+) -> IResult<&'a str, Inline> {
     let (input, _) = char('$')(input)?;
     let (input, math_content) = take_while(|c| c != '$')(input)?;
     let (input, _) = char('$')(input)?;
 
     // Parse the math expression
-    match super::math::parse(math_content) {
+    match super::math::parse_with_operators(math_content, operators) {
         Ok(expr) => Ok((input, Inline::MathInline(expr))),
-        Err(_) => {
-            // Fallback to text if parsing fails
-            Ok((input, Inline::Text(format!("${math_content}$"))))
-        }
+        Err(_) => Ok((input, Inline::RawMath(math_content.to_string()))),
     }
 }
 
@@ -461,7 +754,7 @@ Some text
 \item First item
 \item Second item
 \end{itemize}";
-        let (_, block) = parse_environment(input).unwrap();
+        let (_, block) = parse_environment(input, &OperatorDeclarations::default()).unwrap();
         match block {
             Block::List { kind, items } => {
                 assert_eq!(kind, ListKind::Unordered);
@@ -483,6 +776,106 @@ Some text
         }
     }
 
+    #[test]
+    fn test_parse_authors() {
+        let authors = parse_authors(
+            r"Ada Lovelace\thanks{Funded by the \emph{Society}.} \\ University of London \\ \texttt{ada@example.org} \and Alan~Turing \and",
+        );
+        assert_eq!(authors.len(), 2);
+        assert_eq!(authors[0].name, "Ada Lovelace");
+        assert_eq!(authors[0].thanks.as_deref(), Some("Funded by the Society."));
+        assert_eq!(
+            authors[0].affiliation.as_deref(),
+            Some("University of London")
+        );
+        assert_eq!(authors[0].email.as_deref(), Some("ada@example.org"));
+        assert_eq!(authors[1], Author::new("Alan Turing"));
+
+        // Braces protect \and, and longer commands are not separators
+        let authors = parse_authors(r"{Smith \and Sons} \andalso");
+        assert_eq!(authors.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_title_matter() {
+        let input = r"\documentclass{article}
+\title{On \textbf{Bold} Claims}
+\author{Ada \and Alan}
+\affil{Cambridge}
+\keywords{typst; latex, conversion}
+\begin{document}
+\maketitle
+\begin{abstract}
+We study $x$.
+\end{abstract}
+\section{Intro}
+Text
+\end{document}";
+        let doc = parse(input).unwrap();
+        let metadata = &doc.metadata;
+        assert_eq!(metadata.title.as_deref(), Some("On Bold Claims"));
+        assert_eq!(metadata.authors[0], Author::new("Ada"));
+        assert_eq!(
+            metadata.authors[1].affiliation.as_deref(),
+            Some("Cambridge")
+        );
+        assert_eq!(metadata.keywords, vec!["typst", "latex", "conversion"]);
+        assert!(metadata.title_block);
+        assert_eq!(metadata.r#abstract.len(), 1);
+        assert_eq!(doc.content.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_figure() {
+        let input = r"\begin{figure}[ht]
+\centering
+\includegraphics[width=0.5\linewidth]{plot.png}
+\caption{Results}
+\label{fig:plot}
+\end{figure}";
+        let (_, block) = parse_environment(input, &OperatorDeclarations::default()).unwrap();
+        match block {
+            Block::Figure {
+                content,
+                caption,
+                label,
+            } => {
+                assert_eq!(
+                    content,
+                    vec![Block::Paragraph(vec![Inline::Image {
+                        path: "plot.png".to_string(),
+                        alt: String::new(),
+                        width: Some("50%".to_string()),
+                        height: None,
+                    }])]
+                );
+                assert_eq!(caption, Some(vec![Inline::Text("Results".to_string())]));
+                assert_eq!(label.as_deref(), Some("fig:plot"));
+            }
+            _ => panic!("Expected figure"),
+        }
+    }
+
+    #[test]
+    fn test_typst_length() {
+        assert_eq!(typst_length(r"\textwidth").as_deref(), Some("100%"));
+        assert_eq!(typst_length(r"0.33\columnwidth").as_deref(), Some("33%"));
+        assert_eq!(typst_length("3.5cm").as_deref(), Some("3.5cm"));
+        assert_eq!(typst_length("2ex"), None);
+        assert_eq!(typst_length(r"0.5\textheight"), None);
+    }
+
+    #[test]
+    fn test_parse_siunitx_in_text() {
+        let input = r"\SI{9.81}{\meter\per\second\squared} downwards";
+        let (remaining, inline) = parse_inline_command(input).unwrap();
+        assert_eq!(remaining, " downwards");
+        assert!(matches!(
+            inline,
+            Inline::MathInline(crate::ast::math::MathExpr::Quantity { .. })
+        ));
+    }
+
     #[test]
     fn test_skip_comments() {
         let input = r"% This is a comment
@@ -1,16 +1,20 @@
 //! LaTeX math expression parser using nom
 
+use std::borrow::Cow;
+use std::ops::Range;
+
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{alpha1, char, digit1, multispace0, one_of},
-    combinator::{map, opt, recognize},
-    multi::{many0, many1},
+    bytes::complete::{tag, take, take_until, take_while, take_while1},
+    character::complete::{alpha1, char, digit1, multispace0, one_of, satisfy},
+    combinator::{map, not, opt, recognize, verify},
+    multi::{many0, many1_count, many_m_n},
     sequence::{delimited, pair, preceded},
     IResult,
 };
 
-use crate::ast::math::MathExpr;
+use super::{mhchem, siunitx, symbols};
+use crate::ast::math::{FontVariant, LimitPlacement, MathClass, MathExpr, MathSpace};
 use crate::error::{Error, Result};
 
 /// Parse a LaTeX math expression into a MathExpr AST
@@ -19,7 +23,7 @@ pub fn parse(input: &str) -> Result<MathExpr> {
     if input.is_empty() {
         return Ok(MathExpr::Group(vec![]));
     }
-    match parse_expr(input) {
+    match parse_braced_content(input) {
         Ok((remaining, expr)) => {
             if remaining.trim().is_empty() {
                 Ok(expr)
@@ -34,28 +38,204 @@ pub fn parse(input: &str) -> Result<MathExpr> {
     }
 }
 
+/// Parse a LaTeX math expression that may use operators declared elsewhere in the document
+pub fn parse_with_operators(input: &str, operators: &OperatorDeclarations) -> Result<MathExpr> {
+    parse(&operators.expand(input))
+}
+
+/// Operators declared with `\DeclareMathOperator`, which later math may use
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OperatorDeclarations {
+    /// Command names without the backslash, with their `\operatorname` expansions
+    operators: Vec<(String, String)>,
+}
+
+impl OperatorDeclarations {
+    /// Collect the declarations in a document, returning them with the source that remains
+    /// once they are taken out. Byte ranges in `skip`, like code, are left alone. A math
+    /// span that only held declarations is removed along with its `$` delimiters.
+    pub fn extract<'a>(input: &'a str, skip: &[Range<usize>]) -> (Self, Cow<'a, str>) {
+        const DECLARE: &str = "\\DeclareMathOperator";
+
+        let mut declarations = Self::default();
+        let mut output = String::new();
+        let mut copied = 0;
+        let mut offset = 0;
+        while let Some(pos) = input[offset..].find(DECLARE) {
+            let start = offset + pos;
+            if skip.iter().any(|range| range.contains(&start)) {
+                offset = start + DECLARE.len();
+                continue;
+            }
+
+            // Take this declaration and any that directly follow it
+            let mut end = start;
+            while let Some(next) = input[end..].trim_start().strip_prefix(DECLARE) {
+                match Self::parse_declaration(next) {
+                    Some((remaining, name, expansion)) => {
+                        declarations.operators.push((name.to_string(), expansion));
+                        end = input.len() - remaining.len();
+                    }
+                    None => break,
+                }
+            }
+            if end == start {
+                offset = start + DECLARE.len();
+                continue;
+            }
+
+            output.push_str(&input[copied..start]);
+            copied = end;
+            offset = end;
+
+            // Drop the delimiters of a math span left empty, like `$\DeclareMathOperator..$`
+            let before = output.trim_end();
+            let after = input[end..].trim_start();
+            let dollars = before.len() - before.trim_end_matches('$').len();
+            let paragraph = before.rsplit("\n\n").next().unwrap_or(before);
+            let in_math = paragraph.matches('$').count() % 2 == 1 || dollars == 2;
+            if (1..=2).contains(&dollars)
+                && in_math
+                && after.starts_with(&before[before.len() - dollars..])
+            {
+                output.truncate(before.len() - dollars);
+                copied = input.len() - after.len() + dollars;
+                offset = copied;
+            }
+        }
+
+        if declarations.operators.is_empty() {
+            return (declarations, Cow::Borrowed(input));
+        }
+        output.push_str(&input[copied..]);
+        (declarations, Cow::Owned(output))
+    }
+
+    /// Parse the arguments of one `\DeclareMathOperator`, returning the remaining input,
+    /// the command name and its expansion
+    fn parse_declaration(input: &str) -> Option<(&str, &str, String)> {
+        let (input, star) = opt(char::<&str, nom::error::Error<&str>>('*'))(input).ok()?;
+        let (input, cmd) = parse_raw_arg(input).ok()?;
+        let (input, text) = parse_raw_arg(input).ok()?;
+        let op = if star.is_some() {
+            "operatorname*"
+        } else {
+            "operatorname"
+        };
+        let name = cmd.strip_prefix('\\')?;
+        Some((input, name, format!("\\{}{{{}}}", op, text)))
+    }
+
+    /// Check whether no operators are declared
+    pub fn is_empty(&self) -> bool {
+        self.operators.is_empty()
+    }
+
+    /// Rewrite uses of the declared operators in math source into `\operatorname` calls
+    pub fn expand<'a>(&self, math: &'a str) -> Cow<'a, str> {
+        if self.operators.is_empty() {
+            return Cow::Borrowed(math);
+        }
+
+        let mut output = String::with_capacity(math.len());
+        let mut i = 0;
+        while i < math.len() {
+            let rest = &math[i..];
+            if let Some(name) = rest.strip_prefix('\\') {
+                let len = name
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(name.len());
+                if len > 0 {
+                    let name = &name[..len];
+                    match self.operators.iter().find(|(cmd, _)| cmd == name) {
+                        Some((_, expansion)) => output.push_str(expansion),
+                        None => output.push_str(&rest[..len + 1]),
+                    }
+                    i += len + 1;
+                    continue;
+                }
+                // Keep escaped characters like `\\` together so `\\argmax` is not a command
+                if let Some(c) = name.chars().next() {
+                    output.push('\\');
+                    output.push(c);
+                    i += 1 + c.len_utf8();
+                    continue;
+                }
+            }
+            let c = rest.chars().next().unwrap();
+            output.push(c);
+            i += c.len_utf8();
+        }
+        Cow::Owned(output)
+    }
+}
+
 /// Parse a complete math expression (potentially with operators at the top level)
 fn parse_expr(input: &str) -> IResult<&str, MathExpr> {
-    let (input, first) = parse_term(input)?;
-    let (input, rest) = many0(pair(
-        preceded(multispace0, one_of("+-=<>")),
-        preceded(multispace0, parse_term),
-    ))(input)?;
+    parse_infix(input, MathClass::Relation)
+}
 
-    if rest.is_empty() {
-        Ok((input, first))
-    } else {
-        // Build a sequence of binary operations
-        let mut result = first;
-        for (op, term) in rest {
-            result = MathExpr::Binary {
-                op: op.to_string(),
-                left: Box::new(result),
-                right: Box::new(term),
-            };
-        }
-        Ok((input, result))
+/// Parse operands joined by infix operators of one class, left-associatively.
+/// Relations bind loosest, so the operands of a relation are binary operations.
+fn parse_infix(input: &str, class: MathClass) -> IResult<&str, MathExpr> {
+    let operand = |input| match class {
+        MathClass::Relation => parse_infix(input, MathClass::Binary),
+        _ => parse_term(input),
+    };
+
+    let (mut input, mut result) = operand(input)?;
+    while let Ok((rest, op)) = parse_infix_operator(input, class) {
+        input = rest;
+        result = match operand(input) {
+            Ok((rest, right)) => {
+                input = rest;
+                MathExpr::Binary {
+                    op,
+                    class,
+                    left: Box::new(result),
+                    right: Box::new(right),
+                }
+            }
+            // An operator without a right operand, as in `x \to`, stays a plain symbol
+            Err(_) => MathExpr::Group(vec![result, MathExpr::Symbol(op)]),
+        };
     }
+    Ok((input, result))
+}
+
+/// Parse an infix operator of the given class (`+`, `=`, `\cdot`, `\le`, ...) as its
+/// Typst name
+fn parse_infix_operator(input: &str, class: MathClass) -> IResult<&str, String> {
+    let chars = match class {
+        MathClass::Relation => "=<>",
+        _ => "+-",
+    };
+    preceded(
+        multispace0,
+        alt((
+            map(one_of(chars), |c: char| c.to_string()),
+            map(
+                preceded(
+                    char('\\'),
+                    verify(alpha1, |name: &str| symbols::class(name) == Some(class)),
+                ),
+                |name: &str| symbols::lookup(name).unwrap_or(name).to_string(),
+            ),
+        )),
+    )(input)
+}
+
+/// Parse a binary operator or relation command, which ends a juxtaposed term
+fn parse_infix_command(input: &str) -> IResult<&str, &str> {
+    preceded(
+        char('\\'),
+        verify(alpha1, |name: &str| {
+            matches!(
+                symbols::class(name),
+                Some(MathClass::Binary | MathClass::Relation)
+            )
+        }),
+    )(input)
 }
 
 /// Parse a term (handles multiplication, division, juxtaposition, and unary plus/minus)
@@ -66,13 +246,20 @@ fn parse_term(input: &str) -> IResult<&str, MathExpr> {
     let (input, unary_sign) = opt(one_of("+-"))(input)?;
     let (input, _) = multispace0(input)?;
 
-    // Try to parse multiple atoms in sequence
-    let (input, atoms) = many1(preceded(multispace0, parse_atom))(input)?;
+    // Try to parse multiple atoms in sequence; a leading operator command is an atom
+    // of its own, as in `\pm 1`
+    let (input, first) = parse_atom(input)?;
+    let (input, rest) = many0(preceded(
+        multispace0,
+        preceded(not(parse_infix_command), parse_atom),
+    ))(input)?;
 
-    let term = if atoms.len() == 1 {
-        atoms.into_iter().next().unwrap()
+    let term = if rest.is_empty() {
+        first
     } else {
         // Multiple atoms means implicit multiplication (juxtaposition)
+        let mut atoms = vec![first];
+        atoms.extend(rest);
         MathExpr::Group(atoms)
     };
 
@@ -86,13 +273,53 @@ fn parse_term(input: &str) -> IResult<&str, MathExpr> {
     Ok((input, result))
 }
 
-/// Parse an atomic math expression (with sub/superscripts)
+/// Parse an atomic math expression (with primes and sub/superscripts)
 fn parse_atom(input: &str) -> IResult<&str, MathExpr> {
     let (input, base) = parse_base(input)?;
 
-    // Check for subscript and/or superscript
-    let (input, sub) = opt(preceded(char('_'), parse_script_arg))(input)?;
-    let (input, sup) = opt(preceded(char('^'), parse_script_arg))(input)?;
+    // Primes written as apostrophes (f'', or after the scripts as in x_1'), then
+    // subscript and superscript in either order
+    let (mut input, mut sub) = (input, None);
+    let mut sup = None;
+    let mut apostrophes = 0;
+    loop {
+        if let Ok((rest, count)) = many1_count(char::<_, nom::error::Error<_>>('\''))(input) {
+            input = rest;
+            apostrophes += count;
+            continue;
+        }
+        if sub.is_none() {
+            if let Ok((rest, arg)) = preceded(char('_'), parse_script_arg)(input) {
+                input = rest;
+                sub = Some(arg);
+                continue;
+            }
+        }
+        if sup.is_none() {
+            if let Ok((rest, arg)) = preceded(char('^'), parse_script_arg)(input) {
+                input = rest;
+                sup = Some(arg);
+                continue;
+            }
+        }
+        break;
+    }
+
+    // Primes written as a superscript: f^\prime, f^{\prime\prime}
+    let sup_primes = sup.as_ref().and_then(prime_count).unwrap_or(0);
+    if sup_primes > 0 {
+        sup = None;
+    }
+
+    let count = apostrophes + sup_primes;
+    let base = if count > 0 {
+        MathExpr::Prime {
+            base: Box::new(base),
+            count,
+        }
+    } else {
+        base
+    };
 
     let expr = match (sub, sup) {
         (Some(sub), Some(sup)) => MathExpr::SubSup {
@@ -114,14 +341,27 @@ fn parse_atom(input: &str) -> IResult<&str, MathExpr> {
     Ok((input, expr))
 }
 
-/// Parse a subscript or superscript argument (can be a single char or braced group)
+/// Count the primes in a superscript made only of \prime commands
+fn prime_count(expr: &MathExpr) -> Option<usize> {
+    match expr {
+        MathExpr::Symbol(s) if s == "prime" => Some(1),
+        MathExpr::Group(exprs) if !exprs.is_empty() => {
+            exprs.iter().map(prime_count).sum::<Option<usize>>()
+        }
+        _ => None,
+    }
+}
+
+/// Parse a subscript or superscript argument (a single char, command, or braced group).
+/// As in TeX, an unbraced script is one character: `x_max` is `x_m` followed by `ax`.
 fn parse_script_arg(input: &str) -> IResult<&str, MathExpr> {
     alt((
         parse_braced_group,
+        parse_latex_command,
         map(recognize(one_of("0123456789")), |s: &str| {
             MathExpr::Symbol(s.to_string())
         }),
-        map(alpha1, |s: &str| MathExpr::Symbol(s.to_string())),
+        parse_symbol,
     ))(input)
 }
 
@@ -143,9 +383,21 @@ fn parse_latex_command(input: &str) -> IResult<&str, MathExpr> {
     // First, check for single-character non-alphabetic commands like \, \: \; \!
     if let Some(first_char) = input.chars().next() {
         match first_char {
-            ',' | ':' | ';' | '!' => {
-                return Ok((&input[1..], MathExpr::Space));
+            ',' => return Ok((&input[1..], MathExpr::Spacing(MathSpace::Thin))),
+            ':' | '>' => return Ok((&input[1..], MathExpr::Spacing(MathSpace::Medium))),
+            ';' => return Ok((&input[1..], MathExpr::Spacing(MathSpace::Thick))),
+            '!' => {
+                let space = MathSpace::Length("-1em/6".to_string());
+                return Ok((&input[1..], MathExpr::Spacing(space)));
+            }
+            ' ' => return Ok((&input[1..], MathExpr::Space)),
+            // Escaped special characters: \{ \} \# \$ \% \_ \|
+            '{' | '}' | '#' | '$' | '%' | '_' => {
+                return Ok((&input[1..], MathExpr::Symbol(first_char.to_string())));
             }
+            '|' => return Ok((&input[1..], MathExpr::Symbol("||".to_string()))),
+            // A bare & would be an alignment point, so keep the ampersand as text
+            '&' => return Ok((&input[1..], MathExpr::Text("&".to_string()))),
             '\\' => {
                 // Double backslash - line break in LaTeX
                 return Ok((&input[1..], MathExpr::Symbol("\\".to_string())));
@@ -157,99 +409,48 @@ fn parse_latex_command(input: &str) -> IResult<&str, MathExpr> {
     let (input, cmd_name) = alpha1(input)?;
 
     match cmd_name {
-        // Greek letters - convert to Typst symbols
-        "alpha" | "beta" | "gamma" | "delta" | "epsilon" | "zeta" | "eta" | "theta" | "iota"
-        | "kappa" | "lambda" | "mu" | "nu" | "xi" | "pi" | "rho" | "sigma" | "tau" | "upsilon"
-        | "phi" | "chi" | "psi" | "omega" | "Gamma" | "Delta" | "Theta" | "Lambda" | "Xi"
-        | "Pi" | "Sigma" | "Upsilon" | "Phi" | "Psi" | "Omega" => {
-            Ok((input, MathExpr::Symbol(cmd_name.to_string())))
-        }
-        // Greek letter variants
-        "varepsilon" => Ok((input, MathExpr::Symbol("epsilon.alt".to_string()))),
-        "vartheta" => Ok((input, MathExpr::Symbol("theta.alt".to_string()))),
-        "varpi" => Ok((input, MathExpr::Symbol("pi.alt".to_string()))),
-        "varrho" => Ok((input, MathExpr::Symbol("rho.alt".to_string()))),
-        "varsigma" => Ok((input, MathExpr::Symbol("sigma.alt".to_string()))),
-        "varphi" => Ok((input, MathExpr::Symbol("phi.alt".to_string()))),
-
-        // Binary operators - convert to Typst symbols
-        "pm" => Ok((input, MathExpr::Symbol("plus.minus".to_string()))),
-        "mp" => Ok((input, MathExpr::Symbol("minus.plus".to_string()))),
-        "times" => Ok((input, MathExpr::Symbol("times".to_string()))),
-        "cdot" => Ok((input, MathExpr::Symbol("dot".to_string()))),
-        "div" => Ok((input, MathExpr::Symbol("div".to_string()))),
-        "ast" => Ok((input, MathExpr::Symbol("ast".to_string()))),
-
-        // Comparison operators
-        "le" | "leq" => Ok((input, MathExpr::Symbol("<=".to_string()))),
-        "ge" | "geq" => Ok((input, MathExpr::Symbol(">=".to_string()))),
-        "ne" | "neq" => Ok((input, MathExpr::Symbol("!=".to_string()))),
-        "approx" => Ok((input, MathExpr::Symbol("approx".to_string()))),
-        "equiv" => Ok((input, MathExpr::Symbol("equiv".to_string()))),
-        "sim" => Ok((input, MathExpr::Symbol("tilde".to_string()))),
-
-        // Set operators
-        "in" => Ok((input, MathExpr::Symbol("in".to_string()))),
-        "notin" => Ok((input, MathExpr::Symbol("in.not".to_string()))),
-        "subset" => Ok((input, MathExpr::Symbol("subset".to_string()))),
-        "supset" => Ok((input, MathExpr::Symbol("supset".to_string()))),
-        "subseteq" => Ok((input, MathExpr::Symbol("subset.eq".to_string()))),
-        "supseteq" => Ok((input, MathExpr::Symbol("supset.eq".to_string()))),
-        "cup" => Ok((input, MathExpr::Symbol("union".to_string()))),
-        "cap" => Ok((input, MathExpr::Symbol("sect".to_string()))),
-        "emptyset" => Ok((input, MathExpr::Symbol("emptyset".to_string()))),
-
-        // Special symbols
-        "infty" => Ok((input, MathExpr::Symbol("infinity".to_string()))),
-        "partial" => Ok((input, MathExpr::Symbol("partial".to_string()))),
-        "hbar" => Ok((input, MathExpr::Symbol("planck".to_string()))),
-        "nabla" => Ok((input, MathExpr::Symbol("nabla".to_string()))),
-        "forall" => Ok((input, MathExpr::Symbol("forall".to_string()))),
-        "exists" => Ok((input, MathExpr::Symbol("exists".to_string()))),
-        "neg" | "lnot" => Ok((input, MathExpr::Symbol("not".to_string()))),
-        "land" => Ok((input, MathExpr::Symbol("and".to_string()))),
-        "lor" => Ok((input, MathExpr::Symbol("or".to_string()))),
-
-        // Arrows
-        "to" | "rightarrow" => Ok((input, MathExpr::Symbol("->".to_string()))),
-        "leftarrow" => Ok((input, MathExpr::Symbol("<-".to_string()))),
-        "leftrightarrow" => Ok((input, MathExpr::Symbol("<->".to_string()))),
-        "Rightarrow" => Ok((input, MathExpr::Symbol("=>".to_string()))),
-        "Leftarrow" => Ok((input, MathExpr::Symbol("<=".to_string()))),
-        "Leftrightarrow" => Ok((input, MathExpr::Symbol("<=>".to_string()))),
-        "mapsto" => Ok((input, MathExpr::Symbol("|->".to_string()))),
-
-        // Dots
-        "ldots" | "dots" => Ok((input, MathExpr::Symbol("...".to_string()))),
-        "cdots" => Ok((input, MathExpr::Symbol("dots.c".to_string()))),
-        "vdots" => Ok((input, MathExpr::Symbol("dots.v".to_string()))),
-        "ddots" => Ok((input, MathExpr::Symbol("dots.down".to_string()))),
-
-        // Delimiters
-        "langle" => Ok((input, MathExpr::Symbol("angle.l".to_string()))),
-        "rangle" => Ok((input, MathExpr::Symbol("angle.r".to_string()))),
-        "lfloor" => Ok((input, MathExpr::Symbol("floor.l".to_string()))),
-        "rfloor" => Ok((input, MathExpr::Symbol("floor.r".to_string()))),
-        "lceil" => Ok((input, MathExpr::Symbol("ceil.l".to_string()))),
-        "rceil" => Ok((input, MathExpr::Symbol("ceil.r".to_string()))),
-        "lvert" => Ok((input, MathExpr::Symbol("|".to_string()))),
-        "rvert" => Ok((input, MathExpr::Symbol("|".to_string()))),
-        "lVert" => Ok((input, MathExpr::Symbol("||".to_string()))),
-        "rVert" => Ok((input, MathExpr::Symbol("||".to_string()))),
-
         // Fractions: \frac{num}{den}
-        "frac" => {
-            let (input, _) = multispace0(input)?;
-            let (input, num) = parse_braced_group(input)?;
+        "frac" | "dfrac" | "tfrac" | "cfrac" => {
             let (input, _) = multispace0(input)?;
-            let (input, den) = parse_braced_group(input)?;
-            Ok((
-                input,
-                MathExpr::Fraction {
-                    num: Box::new(num),
-                    den: Box::new(den),
-                },
-            ))
+            // \cfrac takes an optional numerator alignment, which Typst has no equivalent for
+            let (input, _) = if cmd_name == "cfrac" {
+                opt(delimited(char('['), take_until("]"), char(']')))(input)?
+            } else {
+                (input, None)
+            };
+            let (input, num) = parse_arg(input)?;
+            let (input, den) = parse_arg(input)?;
+            let frac = MathExpr::fraction(num, den);
+            let expr = match cmd_name {
+                "dfrac" | "cfrac" => MathExpr::command("display", vec![frac]),
+                "tfrac" => MathExpr::command("inline", vec![frac]),
+                _ => frac,
+            };
+            Ok((input, expr))
+        }
+
+        // Binomial coefficients: \binom{n}{k}
+        "binom" | "dbinom" | "tbinom" => {
+            let (input, upper) = parse_arg(input)?;
+            let (input, lower) = parse_arg(input)?;
+            let binom = MathExpr::command("binom", vec![upper, lower]);
+            let expr = match cmd_name {
+                "dbinom" => MathExpr::command("display", vec![binom]),
+                "tbinom" => MathExpr::command("inline", vec![binom]),
+                _ => binom,
+            };
+            Ok((input, expr))
+        }
+
+        // Generalized fraction: \genfrac{left}{right}{thickness}{style}{num}{den}
+        "genfrac" => {
+            let (input, left) = parse_raw_arg(input)?;
+            let (input, right) = parse_raw_arg(input)?;
+            let (input, thickness) = parse_raw_arg(input)?;
+            let (input, style) = parse_raw_arg(input)?;
+            let (input, num) = parse_arg(input)?;
+            let (input, den) = parse_arg(input)?;
+            Ok((input, genfrac(left, right, thickness, style, num, den)))
         }
 
         // Square root: \sqrt{x} or \sqrt[n]{x}
@@ -267,200 +468,222 @@ fn parse_latex_command(input: &str) -> IResult<&str, MathExpr> {
             ))
         }
 
-        // Big operators that need renaming for Typst
-        "int" => {
-            let (input, _) = multispace0(input)?;
-            let (input, lower) = opt(preceded(char('_'), parse_script_arg))(input)?;
-            let (input, upper) = opt(preceded(char('^'), parse_script_arg))(input)?;
-            Ok((
-                input,
-                MathExpr::Operator {
-                    name: "integral".to_string(),
-                    lower: lower.map(Box::new),
-                    upper: upper.map(Box::new),
-                },
-            ))
+        // \arg\max and \arg\min form one operator with limits under both words
+        "arg" if arg_extremum(input).is_ok() => {
+            let (input, extremum) = arg_extremum(input)?;
+            let name = format!("arg {}", extremum);
+            parse_operator(input, name, LimitPlacement::Limits, true)
         }
-        "iint" => Ok((input, MathExpr::Symbol("integral.double".to_string()))),
-        "iiint" => Ok((input, MathExpr::Symbol("integral.triple".to_string()))),
-        "oint" => Ok((input, MathExpr::Symbol("integral.cont".to_string()))),
 
         // Big operators: \sum, \prod, \lim, etc.
-        "sum" | "prod" | "lim" | "limsup" | "liminf" | "bigcup" | "bigcap" | "bigoplus"
-        | "bigotimes" | "bigwedge" | "bigvee" | "max" | "min" | "sup" | "inf" | "arg" => {
-            // For operators, limits can be complex expressions in braces
-            let (input, _) = multispace0(input)?;
-            let (input, lower) = opt(preceded(char('_'), parse_script_arg))(input)?;
-            let (input, upper) = opt(preceded(char('^'), parse_script_arg))(input)?;
-            Ok((
-                input,
-                MathExpr::Operator {
-                    name: cmd_name.to_string(),
-                    lower: lower.map(Box::new),
-                    upper: upper.map(Box::new),
-                },
-            ))
+        "sum" | "prod" | "coprod" | "int" | "lim" | "limsup" | "liminf" | "bigcup" | "bigcap"
+        | "bigsqcup" | "biguplus" | "bigoplus" | "bigotimes" | "bigodot" | "bigwedge"
+        | "bigvee" | "max" | "min" | "sup" | "inf" | "arg" | "Pr" | "gcd" | "det" => {
+            // Symbol operators are renamed to their Typst names, text operators stay as-is
+            let name = symbols::lookup(cmd_name).unwrap_or(cmd_name);
+            parse_operator(input, name.to_string(), LimitPlacement::Auto, false)
+        }
+
+        // Common operators that LaTeX leaves to \DeclareMathOperator*
+        "argmax" | "argmin" => {
+            parse_operator(input, cmd_name.to_string(), LimitPlacement::Limits, true)
         }
 
         // Math functions (rendered in upright text in Typst)
         "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
-        | "cosh" | "tanh" | "log" | "ln" | "exp" | "det" | "dim" | "ker" | "deg" | "gcd"
-        | "hom" | "mod" => Ok((input, MathExpr::Symbol(cmd_name.to_string()))),
+        | "cosh" | "tanh" | "log" | "lg" | "ln" | "exp" | "dim" | "ker" | "deg" | "hom" => {
+            Ok((input, MathExpr::Symbol(cmd_name.to_string())))
+        }
 
-        // Font/style commands that take an argument
-        "mathbf" | "textbf" | "bm" => {
-            let (input, _) = multispace0(input)?;
-            let (input, arg) = parse_braced_group(input)?;
+        // Modular arithmetic with amsmath's spacing: a \equiv b \pmod{n}, a \bmod b
+        "pmod" | "mod" | "pod" => {
+            let (input, modulus) = parse_arg(input)?;
+            let mut parts = vec![MathExpr::Spacing(MathSpace::Quad)];
+            if cmd_name != "mod" {
+                parts.push(MathExpr::symbol("("));
+            }
+            if cmd_name != "pod" {
+                // amsmath puts two thin spaces after "mod", Typst adds one itself
+                parts.push(MathExpr::symbol("mod"));
+                parts.push(MathExpr::Spacing(MathSpace::Thin));
+            }
+            parts.push(modulus);
+            if cmd_name != "mod" {
+                parts.push(MathExpr::symbol(")"));
+            }
+            Ok((input, MathExpr::Group(parts)))
+        }
+        "bmod" => {
+            let binary = MathExpr::Text("binary".to_string());
             Ok((
                 input,
-                MathExpr::Command {
-                    name: "bold".to_string(),
-                    args: vec![arg],
-                },
+                MathExpr::command("class", vec![binary, MathExpr::symbol("mod")]),
             ))
         }
-        "mathbb" => {
-            let (input, _) = multispace0(input)?;
-            let (input, arg) = parse_braced_group(input)?;
-            // Convert common blackboard bold letters to Typst symbols
-            let symbol = match &arg {
-                MathExpr::Symbol(s) => match s.as_str() {
-                    "R" => "RR",
-                    "N" => "NN",
-                    "Z" => "ZZ",
-                    "Q" => "QQ",
-                    "C" => "CC",
-                    _ => {
-                        return Ok((
-                            input,
-                            MathExpr::Command {
-                                name: "bb".to_string(),
-                                args: vec![arg],
-                            },
-                        ))
-                    }
-                },
-                _ => {
-                    return Ok((
-                        input,
-                        MathExpr::Command {
-                            name: "bb".to_string(),
-                            args: vec![arg],
-                        },
-                    ))
-                }
+
+        // Font variants: \mathrm, \mathbf, \mathbb, \mathcal, ...
+        "mathrm" | "mathup" | "mathit" | "mathnormal" | "mathbf" | "bm" | "boldsymbol"
+        | "mathsf" | "mathtt" | "mathfrak" | "mathscr" | "mathcal" | "cal" | "mathbb" => {
+            let variant = match cmd_name {
+                "mathrm" | "mathup" => FontVariant::Upright,
+                "mathit" | "mathnormal" => FontVariant::Italic,
+                "mathsf" => FontVariant::Sans,
+                "mathtt" => FontVariant::Mono,
+                "mathfrak" => FontVariant::Fraktur,
+                "mathscr" => FontVariant::Script,
+                "mathcal" | "cal" => FontVariant::Calligraphic,
+                "mathbb" => FontVariant::Blackboard,
+                _ => FontVariant::Bold,
             };
-            Ok((input, MathExpr::Symbol(symbol.to_string())))
-        }
-        "mathcal" | "cal" => {
-            let (input, _) = multispace0(input)?;
-            let (input, arg) = parse_braced_group(input)?;
+            let (input, body) = parse_arg(input)?;
             Ok((
                 input,
-                MathExpr::Command {
-                    name: "cal".to_string(),
-                    args: vec![arg],
+                MathExpr::Font {
+                    variant,
+                    body: Box::new(body),
                 },
             ))
         }
 
-        // Operatorname (custom operators)
+        // Custom operators: \operatorname{Var}, \operatorname*{arg\,max}
         "operatorname" => {
+            let (input, star) = opt(char('*'))(input)?;
             let (input, _) = multispace0(input)?;
-            let (input, _) = char('{')(input)?;
-            // Parse text content until closing brace
-            let mut text = String::new();
-            let mut remaining = input;
-            let mut depth = 1;
-            for (i, c) in input.char_indices() {
-                match c {
-                    '{' => depth += 1,
-                    '}' => {
-                        depth -= 1;
-                        if depth == 0 {
-                            text = input[..i].to_string();
-                            remaining = &input[i + 1..];
-                            break;
-                        }
-                    }
-                    _ => {}
-                }
-            }
-            Ok((
-                remaining,
-                MathExpr::Command {
-                    name: "op".to_string(),
-                    args: vec![MathExpr::Text(text)],
-                },
-            ))
+            let (input, text) = parse_braced_raw(input)?;
+            let limits = if star.is_some() {
+                LimitPlacement::Limits
+            } else {
+                LimitPlacement::Auto
+            };
+            parse_operator(input, operator_text(text), limits, true)
         }
 
-        // Accents and decorations
-        "hat" | "widehat" => {
+        // \mathop{...} makes its argument an operator with limits in display style
+        "mathop" => {
             let (input, _) = multispace0(input)?;
-            let (input, arg) = parse_braced_group(input)?;
-            Ok((
-                input,
-                MathExpr::Command {
-                    name: "hat".to_string(),
-                    args: vec![arg],
-                },
-            ))
+            let (rest, text) = parse_braced_raw(input)?;
+            if !text.is_empty() && text.chars().all(|c| c.is_ascii_alphabetic()) {
+                parse_operator(rest, text.to_string(), LimitPlacement::Limits, true)
+            } else {
+                let (input, body) = parse_arg(input)?;
+                Ok((input, MathExpr::command("limits", vec![body])))
+            }
         }
-        "bar" | "overline" => {
-            let (input, _) = multispace0(input)?;
-            let (input, arg) = parse_braced_group(input)?;
-            Ok((
-                input,
-                MathExpr::Command {
-                    name: "overline".to_string(),
-                    args: vec![arg],
-                },
-            ))
+
+        // Declarations are collected beforehand by OperatorDeclarations::extract
+        "DeclareMathOperator" => {
+            let (input, _) = opt(char('*'))(input)?;
+            let (input, _) = parse_raw_arg(input)?;
+            let (input, _) = parse_raw_arg(input)?;
+            Ok((input, MathExpr::Group(vec![])))
         }
-        "vec" => {
-            let (input, _) = multispace0(input)?;
-            let (input, arg) = parse_braced_group(input)?;
+
+        // Accents, rendered with the Typst accent of the same glyph
+        "hat" | "widehat" | "check" | "widecheck" | "tilde" | "widetilde" | "acute" | "grave"
+        | "bar" | "breve" | "dot" | "ddot" | "dddot" | "ddddot" | "mathring" | "vec"
+        | "overrightarrow" | "overleftarrow" | "overleftrightarrow" | "overrightharpoon"
+        | "overleftharpoon" => {
+            let accent = match cmd_name {
+                "hat" | "widehat" => "hat",
+                "check" | "widecheck" => "caron",
+                "tilde" | "widetilde" => "tilde",
+                "acute" => "acute",
+                "grave" => "grave",
+                "bar" => "macron",
+                "breve" => "breve",
+                "dot" => "dot",
+                "ddot" => "dot.double",
+                "dddot" => "dot.triple",
+                "ddddot" => "dot.quad",
+                "mathring" => "circle",
+                "vec" | "overrightarrow" => "arrow",
+                "overleftarrow" => "arrow.l",
+                "overleftrightarrow" => "arrow.l.r",
+                "overrightharpoon" => "harpoon",
+                _ => "harpoon.lt",
+            };
+            let (input, base) = parse_arg(input)?;
             Ok((
                 input,
-                MathExpr::Command {
-                    name: "arrow".to_string(),
-                    args: vec![arg],
+                MathExpr::Accent {
+                    name: accent.to_string(),
+                    base: Box::new(base),
                 },
             ))
         }
-        "dot" => {
-            let (input, _) = multispace0(input)?;
-            let (input, arg) = parse_braced_group(input)?;
-            Ok((
-                input,
-                MathExpr::Command {
-                    name: "dot".to_string(),
-                    args: vec![arg],
-                },
-            ))
+
+        // Lines over and under an expression
+        "overline" | "underline" => {
+            let (input, arg) = parse_arg(input)?;
+            Ok((input, MathExpr::command(cmd_name, vec![arg])))
         }
-        "ddot" => {
-            let (input, _) = multispace0(input)?;
-            let (input, arg) = parse_braced_group(input)?;
+
+        // Stacking: \overset{top}{base}, \underset{bottom}{base}, \stackrel{top}{base}
+        "overset" | "stackrel" | "underset" => {
+            let (input, attachment) = parse_arg(input)?;
+            let (input, base) = parse_arg(input)?;
+            let (top, bottom) = if cmd_name == "underset" {
+                (None, Some(Box::new(attachment)))
+            } else {
+                (Some(Box::new(attachment)), None)
+            };
             Ok((
                 input,
-                MathExpr::Command {
-                    name: "diaer".to_string(),
-                    args: vec![arg],
+                MathExpr::Attach {
+                    base: Box::new(base),
+                    top,
+                    bottom,
                 },
             ))
         }
-        "tilde" | "widetilde" => {
+
+        // Extensible arrows: \xrightarrow[below]{above}
+        "xrightarrow" | "xleftarrow" | "xleftrightarrow" | "xRightarrow" | "xLeftarrow"
+        | "xLeftrightarrow" | "xmapsto" | "xhookrightarrow" | "xhookleftarrow"
+        | "xtwoheadrightarrow" | "xtwoheadleftarrow" | "xrightharpoonup" | "xrightharpoondown"
+        | "xleftharpoonup" | "xleftharpoondown" | "xrightleftharpoons" | "xleftrightharpoons" => {
+            let arrow = match cmd_name {
+                "xrightarrow" => "arrow.r.long",
+                "xleftarrow" => "arrow.l.long",
+                "xleftrightarrow" => "arrow.l.r.long",
+                "xRightarrow" => "arrow.r.double.long",
+                "xLeftarrow" => "arrow.l.double.long",
+                "xLeftrightarrow" => "arrow.l.r.double.long",
+                "xmapsto" => "arrow.r.long.bar",
+                "xhookrightarrow" => "arrow.r.hook",
+                "xhookleftarrow" => "arrow.l.hook",
+                "xtwoheadrightarrow" => "arrow.r.twohead",
+                "xtwoheadleftarrow" => "arrow.l.twohead",
+                "xrightharpoonup" => "harpoon.rt",
+                "xrightharpoondown" => "harpoon.rb",
+                "xleftharpoonup" => "harpoon.lt",
+                "xleftharpoondown" => "harpoon.lb",
+                "xrightleftharpoons" => "harpoons.rtlb",
+                _ => "harpoons.ltrb",
+            };
+            let (input, below) = parse_optional_arg(input)?;
+            let (input, above) = parse_arg(input)?;
+            let top = match above {
+                MathExpr::Group(ref exprs) if exprs.is_empty() => None,
+                above => Some(Box::new(above)),
+            };
+            let expr = if top.is_none() && below.is_none() {
+                MathExpr::symbol(arrow)
+            } else {
+                MathExpr::Attach {
+                    base: Box::new(MathExpr::symbol(arrow)),
+                    top,
+                    bottom: below.map(Box::new),
+                }
+            };
+            Ok((input, expr))
+        }
+
+        // Multi-line limits: \sum_{\substack{i < n \\ j < m}}
+        "substack" => {
             let (input, _) = multispace0(input)?;
-            let (input, arg) = parse_braced_group(input)?;
-            Ok((
-                input,
-                MathExpr::Command {
-                    name: "tilde".to_string(),
-                    args: vec![arg],
-                },
-            ))
+            let (input, content) = parse_braced_raw(input)?;
+            Ok((input, substack(content)))
         }
 
         // Underbrace with annotation
@@ -501,34 +724,156 @@ fn parse_latex_command(input: &str) -> IResult<&str, MathExpr> {
         }
 
         // Text in math mode
-        "text" | "mathrm" | "textrm" => {
+        // Text mode, which may contain inline math: \text{for all $x$}
+        "text" | "textrm" | "textnormal" | "textup" | "mbox" | "hbox" | "intertext" => {
             let (input, _) = multispace0(input)?;
-            let (input, _) = char('{')(input)?;
-            // Parse text content until closing brace
-            let mut text = String::new();
-            let mut remaining = input;
-            let mut depth = 1;
-            for (i, c) in input.char_indices() {
-                match c {
-                    '{' => depth += 1,
-                    '}' => {
-                        depth -= 1;
-                        if depth == 0 {
-                            text = input[..i].to_string();
-                            remaining = &input[i + 1..];
-                            break;
-                        }
-                    }
-                    _ => {}
+            let (input, raw) = parse_braced_raw(input)?;
+            Ok((input, parse_text_content(raw)))
+        }
+
+        // Styled text: \textbf{...}, \textit{...}
+        "textbf" | "textit" | "emph" | "textsf" | "texttt" => {
+            let variant = match cmd_name {
+                "textbf" => FontVariant::Bold,
+                "textsf" => FontVariant::Sans,
+                "texttt" => FontVariant::Mono,
+                _ => FontVariant::Italic,
+            };
+            let (input, _) = multispace0(input)?;
+            let (input, raw) = parse_braced_raw(input)?;
+            Ok((
+                input,
+                MathExpr::Font {
+                    variant,
+                    body: Box::new(parse_text_content(raw)),
+                },
+            ))
+        }
+
+        // physics package: \abs{x}, \norm*{v}
+        "abs" | "norm" => {
+            let (input, _) = opt(char('*'))(input)?;
+            let (input, arg) = parse_arg(input)?;
+            Ok((input, MathExpr::command(cmd_name, vec![arg])))
+        }
+
+        // physics package derivatives: \dv{f}{x}, \dv[2]{f}{x}, \dv{x}, \pdv{f}{x}{y}
+        "dv" | "pdv" | "derivative" | "partialderivative" => {
+            let partial = matches!(cmd_name, "pdv" | "partialderivative");
+            let (input, star) = opt(char('*'))(input)?;
+            let (input, order) = parse_optional_arg(input)?;
+            let (input, first) = parse_arg(input)?;
+            let max_args = if partial { 3 } else { 2 };
+            let (input, rest) =
+                many_m_n(0, max_args - 1, preceded(multispace0, parse_braced_group))(input)?;
+            // A single argument is the variable of the bare operator
+            let (func, vars) = if rest.is_empty() {
+                (None, vec![first])
+            } else {
+                (Some(Box::new(first)), rest)
+            };
+            Ok((
+                input,
+                MathExpr::Derivative {
+                    func,
+                    vars,
+                    order: order.map(Box::new),
+                    partial,
+                    inline: star.is_some(),
+                },
+            ))
+        }
+
+        // Dirac notation from the physics and braket packages
+        "bra" | "Bra" | "ket" | "Ket" | "braket" | "Braket" | "ketbra" | "dyad" | "expval"
+        | "ev" | "mel" | "matrixel" => {
+            let (input, _) = opt(char('*'))(input)?;
+            parse_dirac(input, cmd_name)
+        }
+
+        // siunitx numbers and units: \num{1.2e-3}, \SI{9.81}{\meter\per\second\squared}
+        // mhchem formulas and units: \ce{H2O}, \pu{123 kJ/mol}
+        name if mhchem::is_command(name) => mhchem::parse_command(input, name),
+
+        // physics also has a \qty, which sizes the delimiters after it
+        name if siunitx::is_command(name) => siunitx::parse_command(input, name)
+            .or_else(|_| parse_auto_sized(input))
+            .or(Ok((input, MathExpr::Group(vec![])))),
+
+        // Negated relations: \not= is eq.not, \not\in is in.not
+        "not" => {
+            let (rest, _) = multispace0(input)?;
+            let negatable: IResult<&str, &str> = preceded(char('\\'), alpha1)(rest);
+            let (after, name) = match (rest.chars().next(), negatable) {
+                (Some(c @ ('=' | '<' | '>')), _) => (&rest[1..], Some(symbols::shorthand_name(c))),
+                (_, Ok((after, cmd))) => (after, symbols::lookup(cmd).map(symbols::negatable_name)),
+                _ => (rest, None),
+            };
+            match name.and_then(symbols::negated) {
+                Some(negated) => Ok((after, MathExpr::Symbol(negated.to_string()))),
+                // Anything without a negated form is struck through
+                None => {
+                    let (input, arg) = parse_arg(rest)?;
+                    Ok((input, MathExpr::command("cancel", vec![arg])))
                 }
             }
-            Ok((remaining, MathExpr::Text(text)))
+        }
+
+        // Strike-through from the cancel package: \cancel, \bcancel, \xcancel, \cancelto
+        "cancel" | "bcancel" | "xcancel" => {
+            let (input, arg) = parse_arg(input)?;
+            Ok((input, MathExpr::command(cmd_name, vec![arg])))
+        }
+        "cancelto" => {
+            let (input, value) = parse_arg(input)?;
+            let (input, arg) = parse_arg(input)?;
+            let cancelled = MathExpr::command("cancel", vec![arg]);
+            Ok((input, MathExpr::superscript(cancelled, value)))
+        }
+
+        // Framed formulas: \boxed{E = mc^2}
+        "boxed" => {
+            let (input, arg) = parse_arg(input)?;
+            Ok((input, MathExpr::command("boxed", vec![arg])))
+        }
+
+        // Colors: \textcolor{red}{x} colors its argument, \color{red} the rest of the group
+        "color" | "textcolor" => {
+            let (input, model) = opt(preceded(
+                multispace0,
+                delimited(char('['), take_until("]"), char(']')),
+            ))(input)?;
+            let (input, spec) = parse_raw_arg(input)?;
+            let (input, body) = if cmd_name == "color" {
+                opt(parse_expr)(input)?
+            } else {
+                map(parse_arg, Some)(input)?
+            };
+            let body = body.unwrap_or(MathExpr::Group(vec![]));
+            let expr = match typst_color(model, spec) {
+                Some(color) => MathExpr::Colored {
+                    color,
+                    body: Box::new(body),
+                },
+                None => body,
+            };
+            Ok((input, expr))
         }
 
         // Left/right delimiters (handle specially)
         "left" => {
             let (input, _) = multispace0(input)?;
             let (input, delim) = parse_delimiter(input)?;
+            // An infix fraction inside \left...\right takes all of the content
+            if let Some(end) = find_matching_right(input) {
+                if find_infix_fraction(&input[..end]).is_some() {
+                    let (rest, body) = parse_braced_content(&input[..end])?;
+                    if rest.trim().is_empty() {
+                        let group = MathExpr::Group(vec![MathExpr::Symbol(delim), body]);
+                        return Ok((&input[end..], group));
+                    }
+                }
+            }
             Ok((input, MathExpr::Symbol(delim)))
         }
         "right" => {
@@ -537,34 +882,82 @@ fn parse_latex_command(input: &str) -> IResult<&str, MathExpr> {
             Ok((input, MathExpr::Symbol(delim)))
         }
 
+        // Sized delimiters: \big(, \Bigl[, \biggr\}, \Biggm|
+        "big" | "Big" | "bigg" | "Bigg" | "bigl" | "Bigl" | "biggl" | "Biggl" | "bigr" | "Bigr"
+        | "biggr" | "Biggr" | "bigm" | "Bigm" | "biggm" | "Biggm" => {
+            let size = match cmd_name.trim_end_matches(['l', 'r', 'm']) {
+                "big" => 1,
+                "Big" => 2,
+                "bigg" => 3,
+                _ => 4,
+            };
+            let (input, _) = multispace0(input)?;
+            let (input, delim) = alt((
+                parse_delimiter,
+                map(preceded(char('\\'), alpha1), |name: &str| {
+                    symbols::lookup(name).unwrap_or(name).to_string()
+                }),
+            ))(input)?;
+            if delim.is_empty() {
+                return Ok((input, MathExpr::Group(vec![])));
+            }
+            Ok((input, MathExpr::SizedDelimiter { delim, size }))
+        }
+
+        // Style switches apply to the rest of the enclosing group
+        "displaystyle" | "textstyle" | "scriptstyle" | "scriptscriptstyle" => {
+            let style = match cmd_name {
+                "displaystyle" => "display",
+                "textstyle" => "inline",
+                "scriptstyle" => "script",
+                _ => "sscript",
+            };
+            let (input, body) = opt(parse_expr)(input)?;
+            match body {
+                Some(body) => Ok((input, MathExpr::command(style, vec![body]))),
+                None => Ok((input, MathExpr::Group(vec![]))),
+            }
+        }
+
         // Environment-style commands
         "begin" => {
             let (input, _) = multispace0(input)?;
-            let (input, _) = char('{')(input)?;
-            let (input, env_name) = alpha1(input)?;
-            let (input, _) = char('}')(input)?;
-
-            // Parse environment content until \end{env_name}
-            let end_marker = format!("\\end{{{}}}", env_name);
-            if let Some(end_pos) = input.find(&end_marker) {
+            let (input, env_name) = delimited(
+                char('{'),
+                take_while1(|c: char| c.is_ascii_alphabetic() || c == '*'),
+                char('}'),
+            )(input)?;
+
+            // Parse environment content until the matching \end{env_name}
+            if let Some((end_pos, end_len)) = find_environment_end(input, env_name) {
                 let content = &input[..end_pos];
-                let remaining = &input[end_pos + end_marker.len()..];
+                let remaining = &input[end_pos + end_len..];
+
+                // Starred environments only differ in numbering
+                let name = env_name.trim_end_matches('*');
+                // Skip column specifications: \begin{array}{cc}, \begin{alignat}{2}
+                let content = match name {
+                    "subarray" | "array" | "alignat" | "alignedat" => {
+                        let trimmed = content.trim_start();
+                        parse_braced_raw(trimmed).map_or(content, |(rest, _)| rest)
+                    }
+                    _ => content,
+                };
 
-                // Parse matrix-like environments
-                match env_name {
-                    "matrix" | "pmatrix" | "bmatrix" | "vmatrix" | "Vmatrix" | "cases"
-                    | "array" | "aligned" | "align" | "gather" | "split" => {
+                match environment_kind(name) {
+                    Some("substack") => Ok((remaining, substack(content))),
+                    Some(kind) => {
                         let rows = parse_matrix_content(content);
                         Ok((
                             remaining,
                             MathExpr::Environment {
-                                name: env_name.to_string(),
+                                name: kind.to_string(),
                                 content: rows,
                             },
                         ))
                     }
-                    _ => {
-                        // For other environments, just parse as expression
+                    None => {
+                        // Other environments (equation, displaymath) hold a plain expression
                         match parse_expr(content.trim()) {
                             Ok((_, expr)) => Ok((remaining, expr)),
                             Err(_) => Ok((remaining, MathExpr::Text(content.to_string()))),
@@ -583,31 +976,209 @@ fn parse_latex_command(input: &str) -> IResult<&str, MathExpr> {
             }
         }
 
+        // Equation labels and numbering controls have no place in the formula itself
+        "label" | "tag" => {
+            let (input, _) = opt(char('*'))(input)?;
+            let (input, _) = parse_raw_arg(input)?;
+            Ok((input, MathExpr::Group(vec![])))
+        }
+        "nonumber" | "notag" => Ok((input, MathExpr::Group(vec![]))),
+
         // Spacing commands
-        "quad" => Ok((input, MathExpr::Space)),
-        "qquad" => Ok((
-            input,
-            MathExpr::Group(vec![MathExpr::Space, MathExpr::Space]),
-        )),
-        "," | ":" | ";" | "!" => Ok((input, MathExpr::Space)),
+        "thinspace" => Ok((input, MathExpr::Spacing(MathSpace::Thin))),
+        "medspace" => Ok((input, MathExpr::Spacing(MathSpace::Medium))),
+        "thickspace" => Ok((input, MathExpr::Spacing(MathSpace::Thick))),
+        "quad" => Ok((input, MathExpr::Spacing(MathSpace::Quad))),
+        "qquad" => Ok((input, MathExpr::Spacing(MathSpace::Wide))),
+        "enspace" | "negthinspace" | "negmedspace" | "negthickspace" => {
+            let length = match cmd_name {
+                "enspace" => "0.5em",
+                "negthinspace" => "-1em/6",
+                "negmedspace" => "-2em/9",
+                _ => "-5em/18",
+            };
+            let space = MathSpace::Length(length.to_string());
+            Ok((input, MathExpr::Spacing(space)))
+        }
 
-        // Other commands - store as-is for now
-        _ => Ok((
-            input,
-            MathExpr::Command {
-                name: cmd_name.to_string(),
-                args: vec![],
-            },
-        )),
-    }
-}
+        // Explicit spaces: \hspace{1cm}, \mkern3mu, \mspace{-2mu}
+        "hspace" | "mspace" | "mkern" | "kern" | "mskip" | "hskip" => {
+            let (input, _) = if cmd_name == "hspace" {
+                opt(char('*'))(input)?
+            } else {
+                (input, None)
+            };
+            let (input, length) = if cmd_name.starts_with('h') || cmd_name == "mspace" {
+                parse_raw_arg(input)?
+            } else {
+                preceded(
+                    multispace0,
+                    recognize(pair(
+                        take_while(|c: char| {
+                            c == '-' || c == '+' || c == '.' || c.is_ascii_digit()
+                        }),
+                        alpha1,
+                    )),
+                )(input)?
+            };
+            let expr = match tex_length(length) {
+                Some(length) => MathExpr::Spacing(MathSpace::Length(length)),
+                None => MathExpr::Space,
+            };
+            Ok((input, expr))
+        }
 
-/// Parse a delimiter character (for \left and \right)
-fn parse_delimiter(input: &str) -> IResult<&str, String> {
-    alt((
-        map(char('('), |_| "(".to_string()),
-        map(char(')'), |_| ")".to_string()),
-        map(char('['), |_| "[".to_string()),
+        // Phantoms keep the space of their content without showing it
+        "phantom" | "hphantom" | "vphantom" => {
+            let (input, body) = parse_arg(input)?;
+            let name = if cmd_name == "phantom" {
+                "hide"
+            } else {
+                cmd_name
+            };
+            Ok((input, MathExpr::command(name, vec![body])))
+        }
+
+        // Plain symbols from the symbol table, other commands stored as-is
+        _ => match symbols::lookup(cmd_name) {
+            Some(symbol) => match symbols::class(cmd_name) {
+                // Keep the class when the glyph alone does not tell it, as for \lvert
+                Some(class) if symbols::typst_class(symbol) != Some(class) => Ok((
+                    input,
+                    MathExpr::ClassedSymbol {
+                        symbol: symbol.to_string(),
+                        class,
+                    },
+                )),
+                _ => Ok((input, MathExpr::Symbol(symbol.to_string()))),
+            },
+            None => Ok((
+                input,
+                MathExpr::Command {
+                    name: cmd_name.to_string(),
+                    args: vec![],
+                },
+            )),
+        },
+    }
+}
+
+/// Environments whose source lies in the AST under another name: `aligned` for all
+/// alignment environments, `gather` for centered lines, `matrix` for plain arrays
+fn environment_kind(name: &str) -> Option<&'static str> {
+    let kind = match name {
+        "matrix" | "smallmatrix" | "array" => "matrix",
+        "pmatrix" => "pmatrix",
+        "bmatrix" => "bmatrix",
+        "Bmatrix" => "Bmatrix",
+        "vmatrix" => "vmatrix",
+        "Vmatrix" => "Vmatrix",
+        "cases" | "dcases" => "cases",
+        "align" | "aligned" | "split" | "flalign" | "alignat" | "alignedat" | "eqnarray" => {
+            "aligned"
+        }
+        "gather" | "gathered" | "multline" => "gather",
+        "subarray" => "substack",
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// Find the `\end{name}` closing an environment, skipping nested environments of the
+/// same name. Returns the position and length of the end marker.
+fn find_environment_end(input: &str, name: &str) -> Option<(usize, usize)> {
+    let begin_marker = format!("\\begin{{{}}}", name);
+    let end_marker = format!("\\end{{{}}}", name);
+    let mut depth = 0;
+    let mut pos = 0;
+    loop {
+        let end = pos + input[pos..].find(&end_marker)?;
+        depth += input[pos..end].matches(&begin_marker).count();
+        if depth == 0 {
+            return Some((end, end_marker.len()));
+        }
+        depth -= 1;
+        pos = end + end_marker.len();
+    }
+}
+
+/// Check whether display math is a LaTeX environment that numbers its equations, like
+/// `\begin{equation}` or `\begin{align}` (but not their starred forms)
+pub fn is_numbered_environment(source: &str) -> bool {
+    let Some(rest) = source.trim_start().strip_prefix("\\begin{") else {
+        return false;
+    };
+    let name = rest.split('}').next().unwrap_or("");
+    matches!(
+        name,
+        "equation" | "align" | "gather" | "multline" | "flalign" | "alignat" | "eqnarray"
+    )
+}
+
+/// The arguments of the `\label`s in display math. Typst numbers display math as a
+/// whole, so an `align` with a label on each line still has one number for them all.
+pub fn equation_labels(source: &str) -> Vec<String> {
+    command_args(source, "label")
+        .into_iter()
+        .map(|(_, label)| label.trim().to_string())
+        .collect()
+}
+
+/// The number that `\tag` gives display math in place of the next in sequence, in
+/// parentheses unless set with `\tag*`
+pub fn equation_tag(source: &str) -> Option<String> {
+    let (starred, tag) = command_args(source, "tag").into_iter().next()?;
+    let tag = tag.trim();
+    Some(if starred {
+        tag.to_string()
+    } else {
+        format!("({})", tag)
+    })
+}
+
+/// The label that math made of a single `\ref` or `\eqref` refers to
+pub fn equation_reference(source: &str) -> Option<String> {
+    let source = source.trim();
+    let rest = source
+        .strip_prefix("\\eqref")
+        .or_else(|| source.strip_prefix("\\ref"))?;
+    match parse_braced_raw(rest.trim_start()) {
+        Ok((remaining, label)) if remaining.trim().is_empty() => Some(label.trim().to_string()),
+        _ => None,
+    }
+}
+
+/// Find the `\name` commands in math source, returning whether each is starred and its
+/// raw argument
+fn command_args<'a>(source: &'a str, name: &str) -> Vec<(bool, &'a str)> {
+    let command = format!("\\{}", name);
+    let mut args = Vec::new();
+    let mut offset = 0;
+    while let Some(pos) = source[offset..].find(&command) {
+        let start = offset + pos;
+        offset = start + command.len();
+        let rest = &source[offset..];
+        // Skip longer names like \tagged and line breaks like \\tag
+        if rest.starts_with(|c: char| c.is_ascii_alphabetic()) || source[..start].ends_with('\\') {
+            continue;
+        }
+        let (rest, starred) = match rest.strip_prefix('*') {
+            Some(rest) => (rest, true),
+            None => (rest, false),
+        };
+        if let Ok((_, arg)) = parse_raw_arg(rest) {
+            args.push((starred, arg));
+        }
+    }
+    args
+}
+
+/// Parse a delimiter character (for \left and \right)
+fn parse_delimiter(input: &str) -> IResult<&str, String> {
+    alt((
+        map(char('('), |_| "(".to_string()),
+        map(char(')'), |_| ")".to_string()),
+        map(char('['), |_| "[".to_string()),
         map(char(']'), |_| "]".to_string()),
         map(char('{'), |_| "{".to_string()),
         map(char('}'), |_| "}".to_string()),
@@ -677,6 +1248,613 @@ fn parse_matrix_content(input: &str) -> Vec<Vec<MathExpr>> {
     rows
 }
 
+/// Build a stack of centered lines from the raw content of \substack or a subarray
+fn substack(content: &str) -> MathExpr {
+    let rows = parse_matrix_content(content)
+        .into_iter()
+        .map(|cells| match cells.len() {
+            1 => cells,
+            _ => vec![MathExpr::Group(cells)],
+        })
+        .collect();
+    MathExpr::Environment {
+        name: "substack".to_string(),
+        content: rows,
+    }
+}
+
+/// Build the expression for \genfrac from its raw delimiter, thickness and style arguments
+fn genfrac(
+    left: &str,
+    right: &str,
+    thickness: &str,
+    style: &str,
+    num: MathExpr,
+    den: MathExpr,
+) -> MathExpr {
+    let delimiter = |raw: &str| {
+        parse_delimiter(raw.trim())
+            .map(|(_, delim)| delim)
+            .unwrap_or_default()
+    };
+    let (left, right) = (delimiter(left), delimiter(right));
+
+    // A zero thickness means no fraction bar: a binomial or a bare stack
+    let thickness = thickness.trim();
+    let digits_end = thickness
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(thickness.len());
+    let no_bar = thickness[..digits_end]
+        .parse::<f64>()
+        .is_ok_and(|value| value == 0.0);
+
+    let expr = if no_bar && left == "(" && right == ")" {
+        MathExpr::command("binom", vec![num, den])
+    } else {
+        let stacked = if no_bar {
+            MathExpr::Environment {
+                name: "matrix".to_string(),
+                content: vec![vec![num], vec![den]],
+            }
+        } else {
+            MathExpr::fraction(num, den)
+        };
+        if left.is_empty() && right.is_empty() {
+            stacked
+        } else {
+            MathExpr::Delimited {
+                left,
+                content: Box::new(stacked),
+                right,
+            }
+        }
+    };
+
+    match style.trim() {
+        "0" => MathExpr::command("display", vec![expr]),
+        "1" => MathExpr::command("inline", vec![expr]),
+        "2" => MathExpr::command("script", vec![expr]),
+        "3" => MathExpr::command("sscript", vec![expr]),
+        _ => expr,
+    }
+}
+
+/// Parse the `\max` or `\min` following `\arg`
+fn arg_extremum(input: &str) -> IResult<&str, &str> {
+    preceded(
+        pair(multispace0, char('\\')),
+        verify(alpha1, |name: &str| name == "max" || name == "min"),
+    )(input)
+}
+
+/// Parse the limit modifiers and limits following an operator name
+fn parse_operator(
+    input: &str,
+    name: String,
+    default_limits: LimitPlacement,
+    custom: bool,
+) -> IResult<&str, MathExpr> {
+    let (input, modifier) = opt(preceded(
+        multispace0,
+        alt((tag("\\nolimits"), tag("\\limits"), tag("\\displaylimits"))),
+    ))(input)?;
+    let limits = match modifier {
+        Some("\\limits") => LimitPlacement::Limits,
+        Some("\\nolimits") => LimitPlacement::Scripts,
+        Some(_) => LimitPlacement::Auto,
+        None => default_limits,
+    };
+
+    // For operators, limits can be complex expressions in braces
+    let (mut input, _) = multispace0(input)?;
+    let (mut lower, mut upper) = (None, None);
+    loop {
+        if lower.is_none() {
+            if let Ok((rest, arg)) = preceded(char('_'), parse_script_arg)(input) {
+                input = rest;
+                lower = Some(arg);
+                continue;
+            }
+        }
+        if upper.is_none() {
+            if let Ok((rest, arg)) = preceded(char('^'), parse_script_arg)(input) {
+                input = rest;
+                upper = Some(arg);
+                continue;
+            }
+        }
+        break;
+    }
+
+    Ok((
+        input,
+        MathExpr::Operator {
+            name,
+            lower: lower.map(Box::new),
+            upper: upper.map(Box::new),
+            limits,
+            custom,
+        },
+    ))
+}
+
+/// Turn the raw argument of \operatorname into plain operator text (`arg\,max` -> `arg max`)
+fn operator_text(raw: &str) -> String {
+    let mut text = String::new();
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(',' | ':' | ';' | ' ') => text.push(' '),
+                Some('!') | None => {}
+                Some(c) => text.push(c),
+            },
+            '{' | '}' => {}
+            _ => text.push(c),
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Convert a TeX length like `1.5cm`, `-3mu` or `\fill` to a Typst length expression
+fn tex_length(length: &str) -> Option<String> {
+    let length = length.trim();
+    // Only the natural width of glue is kept: `1em plus 1fil` -> `1em`
+    let length = length.split(" plus").next()?.split(" minus").next()?.trim();
+    if matches!(length, "\\fill" | "\\hfill" | "\\stretch{1}") {
+        return Some("1fr".to_string());
+    }
+
+    let split = length
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(length.len());
+    let (number, unit) = length.split_at(split);
+    let number = number.trim();
+    let value: f64 = match number {
+        "" | "+" => 1.0,
+        "-" => -1.0,
+        _ => number.parse().ok()?,
+    };
+
+    match unit.trim() {
+        "pt" | "mm" | "cm" | "in" | "em" => Some(format!("{}{}", value, unit.trim())),
+        // Big points and picas only differ from points in the last digits
+        "bp" => Some(format!("{}pt", value)),
+        "pc" => Some(format!("{}pt", value * 12.0)),
+        // Typst has no ex unit; an ex is about half an em in most fonts
+        "ex" => Some(format!("{}em", value / 2.0)),
+        // Math units are 1/18 em
+        "mu" => Some(format!("{}em/18", value)),
+        _ => None,
+    }
+}
+
+/// Commands that only change the font of text, which math text leaves out
+const TEXT_FONT_COMMANDS: &[&str] = &[
+    "emph",
+    "textbf",
+    "textit",
+    "textrm",
+    "textsf",
+    "texttt",
+    "textup",
+    "textsl",
+    "textsc",
+    "textnormal",
+    "text",
+    "mbox",
+];
+
+/// Parse the argument of \text and friends: text where `$...$` switches back to math.
+/// Returns a single `Text` unless the argument contains math.
+fn parse_text_content(raw: &str) -> MathExpr {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut rest = raw;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\\' => {
+                let name_len = rest[1..]
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(rest.len() - 1);
+                // Font changes keep the text of their argument, which the braces unwrap
+                if TEXT_FONT_COMMANDS.contains(&&rest[1..1 + name_len]) {
+                    rest = rest[1 + name_len..].trim_start();
+                    continue;
+                }
+                let mut chars = rest[1..].chars();
+                match chars.next() {
+                    // Escaped characters and control spaces stand for themselves
+                    Some(escaped @ ('$' | '%' | '&' | '#' | '_' | '{' | '}' | ' ')) => {
+                        text.push(escaped);
+                        rest = chars.as_str();
+                    }
+                    _ => {
+                        text.push('\\');
+                        rest = &rest[1..];
+                    }
+                }
+            }
+            '$' => {
+                let math = &rest[1..];
+                let end = math.find('$').unwrap_or(math.len());
+                if !text.is_empty() {
+                    parts.push(MathExpr::Text(std::mem::take(&mut text)));
+                }
+                parts.push(
+                    parse(&math[..end]).unwrap_or_else(|_| MathExpr::Text(math[..end].to_string())),
+                );
+                rest = math.get(end + 1..).unwrap_or("");
+            }
+            // Grouping braces and ties
+            '{' | '}' => rest = &rest[1..],
+            '~' => {
+                text.push(' ');
+                rest = &rest[1..];
+            }
+            _ => {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if !text.is_empty() || parts.is_empty() {
+        parts.push(MathExpr::Text(text));
+    }
+
+    if parts.len() == 1 {
+        parts.pop().unwrap()
+    } else {
+        MathExpr::Group(parts)
+    }
+}
+
+/// Parse the arguments of a Dirac notation command. Inner products take either one
+/// argument split at `|` (braket package) or one argument per part (physics package).
+fn parse_dirac<'a>(input: &'a str, cmd_name: &str) -> IResult<&'a str, MathExpr> {
+    let dirac = |bra: Option<MathExpr>, operator: Option<MathExpr>, ket: Option<MathExpr>| {
+        MathExpr::Dirac {
+            bra: bra.map(Box::new),
+            operator: operator.map(Box::new),
+            ket: ket.map(Box::new),
+        }
+    };
+    let (input, raw) = parse_raw_arg(input)?;
+    let mut parts = Vec::new();
+    for part in split_dirac(raw) {
+        let (rest, expr) = parse_braced_content(part)?;
+        if !rest.trim().is_empty() {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                rest,
+                nom::error::ErrorKind::Verify,
+            )));
+        }
+        parts.push(expr);
+    }
+
+    // The physics package passes the remaining parts as further arguments
+    let wanted: usize = match cmd_name {
+        "bra" | "Bra" | "ket" | "Ket" => 1,
+        "ketbra" | "dyad" | "expval" | "ev" => 2,
+        "mel" | "matrixel" => 3,
+        _ => 2,
+    };
+    let (input, more) = many_m_n(
+        0,
+        wanted.saturating_sub(parts.len()),
+        preceded(multispace0, parse_braced_group),
+    )(input)?;
+    parts.extend(more);
+    let mut parts = parts.into_iter();
+    let (first, second, third) = (parts.next(), parts.next(), parts.next());
+
+    let expr = match cmd_name {
+        "bra" | "Bra" => dirac(first, None, None),
+        "ket" | "Ket" => dirac(None, None, first),
+        "ketbra" | "dyad" => MathExpr::Group(vec![
+            dirac(None, None, first.clone()),
+            dirac(second.or(first), None, None),
+        ]),
+        // \expval{A} is <A>, \expval{A}{psi} is <psi|A|psi>
+        "expval" | "ev" => match second {
+            Some(state) => dirac(Some(state.clone()), first, Some(state)),
+            None => dirac(None, first, None),
+        },
+        _ => match (second, third) {
+            (Some(operator), Some(ket)) => dirac(first, Some(operator), Some(ket)),
+            (Some(ket), None) => dirac(first, None, Some(ket)),
+            // \braket{a} is the norm <a|a>
+            (None, _) => dirac(first.clone(), None, first),
+        },
+    };
+    Ok((input, expr))
+}
+
+/// Split the argument of a braket package command at the bars outside of braces
+fn split_dirac(raw: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in raw.char_indices() {
+        match c {
+            '{' if !escaped => depth += 1,
+            '}' if !escaped => depth -= 1,
+            '|' if !escaped && depth == 0 => {
+                parts.push(&raw[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    parts.push(&raw[start..]);
+    parts
+}
+
+/// Translate an xcolor color (`red`, `blue!30`, `[HTML]{FF8800}`, `[rgb]{1,0.5,0}`)
+/// into a Typst color expression
+fn typst_color(model: Option<&str>, spec: &str) -> Option<String> {
+    let spec = spec.trim();
+    match model.map(str::trim) {
+        Some("HTML") => Some(format!("rgb(\"#{}\")", spec)),
+        Some("rgb") | Some("RGB") => {
+            let parts: Vec<f64> = spec
+                .split(',')
+                .map(|p| p.trim().parse().ok())
+                .collect::<Option<_>>()?;
+            let scale = if model == Some("RGB") { 1.0 } else { 255.0 };
+            let channels: Vec<String> = parts
+                .iter()
+                .map(|p| format!("{}", (p * scale).round()))
+                .collect();
+            Some(format!("rgb({})", channels.join(", ")))
+        }
+        Some("gray") => {
+            let level: f64 = spec.parse().ok()?;
+            Some(format!("luma({})", (level * 255.0).round()))
+        }
+        Some(_) => None,
+        None => {
+            // red!30 is 30% red mixed with white
+            let (name, percent) = match spec.split_once('!') {
+                Some((name, percent)) => (name, percent.parse::<u8>().ok()),
+                None => (spec, None),
+            };
+            let color = match name {
+                "black" | "white" | "gray" | "red" | "green" | "blue" | "yellow" | "orange"
+                | "purple" | "olive" | "teal" | "lime" => name.to_string(),
+                "cyan" => "aqua".to_string(),
+                "magenta" => "fuchsia".to_string(),
+                "darkgray" => "luma(64)".to_string(),
+                "lightgray" => "luma(191)".to_string(),
+                "brown" => "rgb(191, 128, 64)".to_string(),
+                "pink" => "rgb(255, 191, 191)".to_string(),
+                "violet" => "rgb(128, 0, 128)".to_string(),
+                _ => return None,
+            };
+            match percent {
+                Some(percent) if percent < 100 => {
+                    Some(format!("{}.lighten({}%)", color, 100 - percent))
+                }
+                _ => Some(color),
+            }
+        }
+    }
+}
+
+/// Parse a macro argument: a braced group or a single token, as in `\frac12` or `\frac a b`
+fn parse_arg(input: &str) -> IResult<&str, MathExpr> {
+    preceded(
+        multispace0,
+        alt((
+            parse_braced_group,
+            parse_latex_command,
+            map(satisfy(|c| c.is_ascii_alphanumeric()), |c: char| {
+                MathExpr::Symbol(c.to_string())
+            }),
+        )),
+    )(input)
+}
+
+/// Parse an optional bracketed argument like the `[n]` in `\sqrt[n]{x}`
+fn parse_optional_arg(input: &str) -> IResult<&str, Option<MathExpr>> {
+    let (input, _) = multispace0(input)?;
+    let Some(content) = input.strip_prefix('[') else {
+        return Ok((input, None));
+    };
+
+    // Find the closing bracket outside of any braces
+    let mut depth = 0;
+    let end = content.char_indices().find_map(|(i, c)| {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ']' if depth == 0 => return Some(i),
+            _ => {}
+        }
+        None
+    });
+    let Some(end) = end else {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Char,
+        )));
+    };
+
+    let (rest, arg) = parse_braced_content(&content[..end])?;
+    if !rest.trim().is_empty() {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            rest,
+            nom::error::ErrorKind::Verify,
+        )));
+    }
+    Ok((&content[end + 1..], Some(arg)))
+}
+
+/// Parse a macro argument without interpreting it: the text inside braces, a control
+/// sequence, or a single character
+fn parse_raw_arg(input: &str) -> IResult<&str, &str> {
+    preceded(
+        multispace0,
+        alt((
+            parse_braced_raw,
+            recognize(pair(char('\\'), alt((alpha1, take(1usize))))),
+            take(1usize),
+        )),
+    )(input)
+}
+
+/// Parse a braced argument without interpreting it, returning the raw text inside the braces
+pub(super) fn parse_braced_raw(input: &str) -> IResult<&str, &str> {
+    let (input, _) = char('{')(input)?;
+    let mut depth = 1;
+    for (i, c) in input.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((&input[i + 1..], &input[..i]));
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(nom::Err::Error(nom::error::Error::new(
+        input,
+        nom::error::ErrorKind::Char,
+    )))
+}
+
+/// Find a top-level infix fraction command (`\over`, `\choose`, `\atop`) in group content.
+/// Returns the byte range of the command and its name.
+fn find_infix_fraction(input: &str) -> Option<(usize, usize, &str)> {
+    let mut depth = 0;
+    let mut chars = input.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                if depth == 0 {
+                    // End of the enclosing group
+                    return None;
+                }
+                depth -= 1;
+            }
+            '\\' => {
+                let name_start = i + 1;
+                let mut name_end = name_start;
+                while let Some(&(j, next)) = chars.peek() {
+                    if !next.is_ascii_alphabetic() {
+                        if name_end == name_start {
+                            // Escaped character like \{ or \}
+                            chars.next();
+                        }
+                        break;
+                    }
+                    name_end = j + next.len_utf8();
+                    chars.next();
+                }
+                let name = &input[name_start..name_end];
+                match name {
+                    // \left...\right and environments group their content like braces
+                    "left" | "begin" => depth += 1,
+                    "right" | "end" => depth -= 1,
+                    "over" | "choose" | "atop" if depth == 0 => {
+                        return Some((i, name_end, name));
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse content in delimiters that grow with it, like `(...)` after physics' `\qty`
+fn parse_auto_sized(input: &str) -> IResult<&str, MathExpr> {
+    let input = input.trim_start();
+    let (open, close) = match input.chars().next() {
+        Some('(') => ('(', ')'),
+        Some('[') => ('[', ']'),
+        Some('|') => ('|', '|'),
+        _ => {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                input,
+                nom::error::ErrorKind::Char,
+            )))
+        }
+    };
+
+    // Find the closing delimiter outside of braces and nested pairs
+    let mut depth = 0;
+    let mut end = None;
+    let mut chars = input.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            c if c == close && depth == 0 => {
+                end = Some(i);
+                break;
+            }
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            c if c == open && open != close => depth += 1,
+            c if c == close => depth -= 1,
+            _ => {}
+        }
+    }
+    let Some(end) = end else {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Char,
+        )));
+    };
+
+    let (rest, body) = parse_braced_content(&input[1..end])?;
+    if !rest.trim().is_empty() {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            rest,
+            nom::error::ErrorKind::Verify,
+        )));
+    }
+    let group = MathExpr::Group(vec![
+        MathExpr::Symbol(open.to_string()),
+        body,
+        MathExpr::Symbol(close.to_string()),
+    ]);
+    Ok((&input[end + 1..], MathExpr::command("lr", vec![group])))
+}
+
+/// Find the `\right` closing the current `\left`, skipping nested pairs
+fn find_matching_right(input: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut pos = 0;
+    while let Some(n) = input[pos..].find('\\') {
+        let start = pos + n;
+        let rest = &input[start + 1..];
+        let len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        match &rest[..len] {
+            "left" => depth += 1,
+            "right" if depth == 0 => return Some(start),
+            "right" => depth -= 1,
+            // Escaped character like \{ or \|
+            "" => pos = start + 1 + rest.chars().next().map_or(0, char::len_utf8),
+            _ => {}
+        }
+        if len > 0 {
+            pos = start + 1 + len;
+        }
+    }
+    None
+}
+
 /// Parse a braced group like {abc} or {a + b}
 fn parse_braced_group(input: &str) -> IResult<&str, MathExpr> {
     delimited(
@@ -692,6 +1870,36 @@ fn parse_braced_content(input: &str) -> IResult<&str, MathExpr> {
     if input.is_empty() || input.starts_with('}') {
         return Ok((input, MathExpr::Group(vec![])));
     }
+
+    // A group may hold a lone operator, as in \overset{def}{=}
+    if let Some(op) = input.chars().next().filter(|c| "+-=<>".contains(*c)) {
+        let rest = input[1..].trim_start();
+        if rest.is_empty() || rest.starts_with('}') {
+            return Ok((rest, MathExpr::Symbol(op.to_string())));
+        }
+    }
+
+    // Infix fractions split the whole group: {a \over b}, {n \choose k}
+    if let Some((start, end, name)) = find_infix_fraction(input) {
+        let (left_rest, num) = parse_braced_content(&input[..start])?;
+        if !left_rest.trim().is_empty() {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                left_rest,
+                nom::error::ErrorKind::Verify,
+            )));
+        }
+        let (input, den) = parse_braced_content(&input[end..])?;
+        let expr = match name {
+            "choose" => MathExpr::command("binom", vec![num, den]),
+            "atop" => MathExpr::Environment {
+                name: "matrix".to_string(),
+                content: vec![vec![num], vec![den]],
+            },
+            _ => MathExpr::fraction(num, den),
+        };
+        return Ok((input, expr));
+    }
+
     parse_expr(input)
 }
 
@@ -706,7 +1914,6 @@ fn parse_number(input: &str) -> IResult<&str, MathExpr> {
 /// Parse a single-character symbol (letter)
 /// In LaTeX math, each letter is a separate variable (implicit multiplication)
 fn parse_symbol(input: &str) -> IResult<&str, MathExpr> {
-    use nom::character::complete::satisfy;
     map(satisfy(|c| c.is_ascii_alphabetic()), |c: char| {
         MathExpr::Symbol(c.to_string())
     })(input)
@@ -741,6 +1948,22 @@ mod tests {
         assert_eq!(result, MathExpr::Symbol("alpha".to_string()));
     }
 
+    #[test]
+    fn test_parse_table_symbols() {
+        assert_eq!(
+            parse(r"\oplus").unwrap(),
+            MathExpr::Symbol("plus.o".to_string())
+        );
+        assert_eq!(
+            parse(r"\hookrightarrow").unwrap(),
+            MathExpr::Symbol("arrow.r.hook".to_string())
+        );
+        assert_eq!(
+            parse(r"\aleph").unwrap(),
+            MathExpr::Symbol("aleph".to_string())
+        );
+    }
+
     #[test]
     fn test_parse_superscript() {
         let result = parse("x^2").unwrap();
@@ -778,6 +2001,81 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_apostrophe_primes() {
+        let result = parse("f''").unwrap();
+        assert_eq!(
+            result,
+            MathExpr::Prime {
+                base: Box::new(MathExpr::symbol("f")),
+                count: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_superscript_primes() {
+        let expected = MathExpr::Prime {
+            base: Box::new(MathExpr::symbol("f")),
+            count: 2,
+        };
+        assert_eq!(parse(r"f^{\prime\prime}").unwrap(), expected);
+        assert_eq!(parse(r"f'^\prime").unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_primes_with_subscript() {
+        let expected = MathExpr::subscript(
+            MathExpr::Prime {
+                base: Box::new(MathExpr::symbol("f")),
+                count: 1,
+            },
+            MathExpr::symbol("n"),
+        );
+        assert_eq!(parse("f'_n").unwrap(), expected);
+        assert_eq!(parse(r"f^\prime_n").unwrap(), expected);
+        // Primes after the subscript belong to the same base
+        assert_eq!(parse("f_n'").unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_symbol_classes() {
+        let class = |input: &str| parse(input).unwrap().class();
+        assert_eq!(class(r"\in"), MathClass::Relation);
+        assert_eq!(class(r"\not="), MathClass::Relation);
+        assert_eq!(class(r"\le"), MathClass::Relation);
+        assert_eq!(class(r"\cup"), MathClass::Binary);
+        assert_eq!(class(r"\cdot"), MathClass::Binary);
+        assert_eq!(class(r"\langle"), MathClass::Opening);
+        assert_eq!(class(r"\lvert"), MathClass::Opening);
+        assert_eq!(class(r"\rvert"), MathClass::Closing);
+        assert_eq!(class(r"\alpha"), MathClass::Ordinary);
+        assert_eq!(class("|"), MathClass::Ordinary);
+    }
+
+    #[test]
+    fn test_parse_script_takes_one_letter() {
+        assert_eq!(
+            parse("x_ab").unwrap(),
+            MathExpr::Group(vec![
+                MathExpr::subscript(MathExpr::symbol("x"), MathExpr::symbol("a")),
+                MathExpr::symbol("b"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_prime_with_superscript() {
+        let result = parse("f'^2").unwrap();
+        match result {
+            MathExpr::Superscript { base, sup } => {
+                assert!(matches!(*base, MathExpr::Prime { count: 1, .. }));
+                assert_eq!(*sup, MathExpr::symbol("2"));
+            }
+            _ => panic!("Expected superscript, got: {:?}", result),
+        }
+    }
+
     #[test]
     fn test_parse_fraction() {
         let result = parse(r"\frac{a}{b}").unwrap();
@@ -790,6 +2088,212 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_fraction_single_tokens() {
+        let expected = MathExpr::fraction(MathExpr::symbol("1"), MathExpr::symbol("2"));
+        assert_eq!(parse(r"\frac12").unwrap(), expected);
+
+        let expected = MathExpr::fraction(MathExpr::symbol("a"), MathExpr::symbol("b"));
+        assert_eq!(parse(r"\frac a b").unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_fraction_styles() {
+        let frac = MathExpr::fraction(MathExpr::symbol("a"), MathExpr::symbol("b"));
+        assert_eq!(
+            parse(r"\dfrac{a}{b}").unwrap(),
+            MathExpr::command("display", vec![frac.clone()])
+        );
+        assert_eq!(
+            parse(r"\tfrac{a}{b}").unwrap(),
+            MathExpr::command("inline", vec![frac.clone()])
+        );
+        assert_eq!(
+            parse(r"\cfrac[l]{a}{b}").unwrap(),
+            MathExpr::command("display", vec![frac])
+        );
+    }
+
+    #[test]
+    fn test_parse_binom() {
+        let binom = MathExpr::command("binom", vec![MathExpr::symbol("n"), MathExpr::symbol("k")]);
+        assert_eq!(parse(r"\binom{n}{k}").unwrap(), binom);
+        assert_eq!(parse(r"{n \choose k}").unwrap(), binom);
+        assert_eq!(parse(r"\genfrac(){0pt}{}{n}{k}").unwrap(), binom);
+        assert_eq!(
+            parse(r"\dbinom nk").unwrap(),
+            MathExpr::command("display", vec![binom])
+        );
+    }
+
+    #[test]
+    fn test_parse_genfrac() {
+        let result = parse(r"\genfrac{[}{]}{1pt}{2}{a}{b}").unwrap();
+        let expected = MathExpr::command(
+            "script",
+            vec![MathExpr::Delimited {
+                left: "[".to_string(),
+                content: Box::new(MathExpr::fraction(
+                    MathExpr::symbol("a"),
+                    MathExpr::symbol("b"),
+                )),
+                right: "]".to_string(),
+            }],
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_infix_over() {
+        let result = parse(r"x + {a \over b}").unwrap();
+        match result {
+            MathExpr::Binary { right, .. } => {
+                assert_eq!(
+                    *right,
+                    MathExpr::fraction(MathExpr::symbol("a"), MathExpr::symbol("b"))
+                );
+            }
+            _ => panic!("Expected binary expression, got: {:?}", result),
+        }
+
+        // \left...\right is a group of its own, so the fraction stays inside it
+        let expected = MathExpr::Group(vec![
+            MathExpr::Group(vec![
+                MathExpr::symbol("("),
+                MathExpr::fraction(MathExpr::symbol("a"), MathExpr::symbol("b")),
+            ]),
+            MathExpr::symbol(")"),
+        ]);
+        assert_eq!(parse(r"\left( a \over b \right)").unwrap(), expected);
+        assert_eq!(parse(r"{\left( a \over b \right)}").unwrap(), expected);
+
+        // \overline is not an infix fraction
+        assert!(matches!(
+            parse(r"\overline{x}").unwrap(),
+            MathExpr::Command { .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_font_variants() {
+        let cases = [
+            (r"\mathrm{d}", FontVariant::Upright),
+            (r"\mathit{x}", FontVariant::Italic),
+            (r"\mathsf{x}", FontVariant::Sans),
+            (r"\mathtt{x}", FontVariant::Mono),
+            (r"\mathfrak{g}", FontVariant::Fraktur),
+            (r"\mathscr{L}", FontVariant::Script),
+            (r"\boldsymbol{x}", FontVariant::Bold),
+            (r"\mathbb{k}", FontVariant::Blackboard),
+        ];
+        for (input, expected) in cases {
+            match parse(input).unwrap() {
+                MathExpr::Font { variant, .. } => assert_eq!(variant, expected, "for {}", input),
+                other => panic!("Expected font variant for {}, got: {:?}", input, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_mathrm_is_not_text() {
+        let result = parse(r"\mathrm{d}x").unwrap();
+        assert_eq!(
+            result,
+            MathExpr::Group(vec![
+                MathExpr::Font {
+                    variant: FontVariant::Upright,
+                    body: Box::new(MathExpr::symbol("d")),
+                },
+                MathExpr::symbol("x"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_accents() {
+        let cases = [
+            (r"\dot{x}", "dot"),
+            (r"\ddot x", "dot.double"),
+            (r"\check{a}", "caron"),
+            (r"\bar{x}", "macron"),
+            (r"\vec{v}", "arrow"),
+            (r"\overleftarrow{v}", "arrow.l"),
+            (r"\mathring{A}", "circle"),
+        ];
+        for (input, accent) in cases {
+            match parse(input).unwrap() {
+                MathExpr::Accent { name, .. } => assert_eq!(name, accent, "for {}", input),
+                other => panic!("Expected accent for {}, got: {:?}", input, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_accent_multi_char_base() {
+        let result = parse(r"\dot{xy}").unwrap();
+        assert_eq!(
+            result,
+            MathExpr::Accent {
+                name: "dot".to_string(),
+                base: Box::new(MathExpr::Group(vec![
+                    MathExpr::symbol("x"),
+                    MathExpr::symbol("y"),
+                ])),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_overset_underset() {
+        let result = parse(r"\overset{!}{=}").unwrap();
+        assert_eq!(
+            result,
+            MathExpr::Attach {
+                base: Box::new(MathExpr::symbol("=")),
+                top: Some(Box::new(MathExpr::symbol("!"))),
+                bottom: None,
+            }
+        );
+
+        let result = parse(r"\underset{n}{\max}").unwrap();
+        match result {
+            MathExpr::Attach { top, bottom, .. } => {
+                assert!(top.is_none());
+                assert_eq!(bottom.as_deref(), Some(&MathExpr::symbol("n")));
+            }
+            _ => panic!("Expected attach, got: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_xrightarrow() {
+        let result = parse(r"\xrightarrow[g]{f}").unwrap();
+        assert_eq!(
+            result,
+            MathExpr::Attach {
+                base: Box::new(MathExpr::symbol("arrow.r.long")),
+                top: Some(Box::new(MathExpr::symbol("f"))),
+                bottom: Some(Box::new(MathExpr::symbol("g"))),
+            }
+        );
+        assert_eq!(
+            parse(r"\xleftarrow{}").unwrap(),
+            MathExpr::symbol("arrow.l.long")
+        );
+    }
+
+    #[test]
+    fn test_parse_substack() {
+        let result = parse(r"\substack{i<n \\ j<m}").unwrap();
+        match result {
+            MathExpr::Environment { name, content } => {
+                assert_eq!(name, "substack");
+                assert_eq!(content.len(), 2);
+            }
+            _ => panic!("Expected substack, got: {:?}", result),
+        }
+    }
+
     #[test]
     fn test_parse_sqrt() {
         let result = parse(r"\sqrt{x}").unwrap();
@@ -819,7 +2323,9 @@ mod tests {
         // Simplified - just \sum without limits
         let result = parse(r"\sum").unwrap();
         match result {
-            MathExpr::Operator { name, lower, upper } => {
+            MathExpr::Operator {
+                name, lower, upper, ..
+            } => {
                 assert_eq!(name, "sum");
                 assert!(lower.is_none());
                 assert!(upper.is_none());
@@ -833,7 +2339,9 @@ mod tests {
         // Simple single-character limits
         let result = parse(r"\sum_i^n").unwrap();
         match result {
-            MathExpr::Operator { name, lower, upper } => {
+            MathExpr::Operator {
+                name, lower, upper, ..
+            } => {
                 assert_eq!(name, "sum");
                 assert!(lower.is_some());
                 assert!(upper.is_some());
@@ -868,4 +2376,434 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn test_parse_limit_modifiers() {
+        let limits_of = |input: &str| match parse(input).unwrap() {
+            MathExpr::Operator { limits, lower, .. } => {
+                assert!(lower.is_some());
+                limits
+            }
+            other => panic!("Expected operator, got: {:?}", other),
+        };
+        assert_eq!(limits_of(r"\sum_i"), LimitPlacement::Auto);
+        assert_eq!(limits_of(r"\int\limits_a^b"), LimitPlacement::Limits);
+        assert_eq!(limits_of(r"\sum \nolimits_{i=1}"), LimitPlacement::Scripts);
+        assert_eq!(limits_of(r"\lim^a_b"), LimitPlacement::Auto);
+    }
+
+    #[test]
+    fn test_parse_operatorname() {
+        assert_eq!(
+            parse(r"\operatorname*{arg\,max}_x").unwrap(),
+            MathExpr::Operator {
+                name: "arg max".to_string(),
+                lower: Some(Box::new(MathExpr::Symbol("x".to_string()))),
+                upper: None,
+                limits: LimitPlacement::Limits,
+                custom: true,
+            }
+        );
+        match parse(r"\operatorname{Var}").unwrap() {
+            MathExpr::Operator {
+                name,
+                limits,
+                custom,
+                ..
+            } => {
+                assert_eq!(name, "Var");
+                assert_eq!(limits, LimitPlacement::Auto);
+                assert!(custom);
+            }
+            other => panic!("Expected operator, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_operator_declarations() {
+        let input = r"\DeclareMathOperator*{\argmax}{arg\,max} \DeclareMathOperator{\rank}{rank}
+$\argmax_x \rank A$ `\DeclareMathOperator{\foo}{foo}`";
+        let code = input.find('`').unwrap()..input.len();
+        let (operators, rest) = OperatorDeclarations::extract(input, &[code]);
+        assert_eq!(
+            rest,
+            "\n$\\argmax_x \\rank A$ `\\DeclareMathOperator{\\foo}{foo}`"
+        );
+        assert_eq!(
+            operators.expand(r"\argmax_x \argmaxi \\argmax \foo"),
+            r"\operatorname*{arg\,max}_x \argmaxi \\argmax \foo"
+        );
+        assert!(matches!(operators.expand(r"\sum_i x_i"), Cow::Owned(_)));
+        assert!(matches!(
+            OperatorDeclarations::default().expand(r"\sum_i x_i"),
+            Cow::Borrowed(_)
+        ));
+
+        // A math span holding only declarations disappears with its delimiters
+        let (operators, rest) = OperatorDeclarations::extract(
+            "$a$ and $\\DeclareMathOperator{\\Tr}{Tr}$ then $$\\DeclareMathOperator{\\Ad}{Ad}$$ b",
+            &[],
+        );
+        assert_eq!(rest, "$a$ and  then  b");
+        assert!(!operators.is_empty());
+    }
+
+    #[test]
+    fn test_parse_sized_delimiters() {
+        assert_eq!(
+            parse(r"\Bigl(").unwrap(),
+            MathExpr::SizedDelimiter {
+                delim: "(".to_string(),
+                size: 2,
+            }
+        );
+        assert_eq!(
+            parse(r"\biggr\rangle").unwrap(),
+            MathExpr::SizedDelimiter {
+                delim: "angle.r".to_string(),
+                size: 3,
+            }
+        );
+        assert_eq!(parse(r"\big.").unwrap(), MathExpr::Group(vec![]));
+    }
+
+    #[test]
+    fn test_parse_style_switch() {
+        assert_eq!(
+            parse(r"{\textstyle x + y}").unwrap(),
+            MathExpr::command(
+                "inline",
+                vec![MathExpr::Binary {
+                    op: "+".to_string(),
+                    class: MathClass::Binary,
+                    left: Box::new(MathExpr::Symbol("x".to_string())),
+                    right: Box::new(MathExpr::Symbol("y".to_string())),
+                }]
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_spacing_commands() {
+        assert_eq!(
+            parse(r"a\,b\!c\qquad").unwrap(),
+            MathExpr::Group(vec![
+                MathExpr::Symbol("a".to_string()),
+                MathExpr::Spacing(MathSpace::Thin),
+                MathExpr::Symbol("b".to_string()),
+                MathExpr::Spacing(MathSpace::Length("-1em/6".to_string())),
+                MathExpr::Symbol("c".to_string()),
+                MathExpr::Spacing(MathSpace::Wide),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_tex_length() {
+        assert_eq!(tex_length("1cm").as_deref(), Some("1cm"));
+        assert_eq!(tex_length("-2.5pt").as_deref(), Some("-2.5pt"));
+        assert_eq!(tex_length("3mu").as_deref(), Some("3em/18"));
+        assert_eq!(tex_length("1pc").as_deref(), Some("12pt"));
+        assert_eq!(tex_length("2ex").as_deref(), Some("1em"));
+        assert_eq!(tex_length("1em plus 2em").as_deref(), Some("1em"));
+        assert_eq!(tex_length(r"\fill").as_deref(), Some("1fr"));
+        assert_eq!(tex_length("\\linewidth"), None);
+    }
+
+    #[test]
+    fn test_parse_relation_precedence() {
+        let sym = |s: &str| Box::new(MathExpr::Symbol(s.to_string()));
+        // a + b \le c \cdot d groups as (a + b) <= (c dot d)
+        assert_eq!(
+            parse(r"a + b \le c \cdot d").unwrap(),
+            MathExpr::Binary {
+                op: "<=".to_string(),
+                class: MathClass::Relation,
+                left: Box::new(MathExpr::Binary {
+                    op: "+".to_string(),
+                    class: MathClass::Binary,
+                    left: sym("a"),
+                    right: sym("b"),
+                }),
+                right: Box::new(MathExpr::Binary {
+                    op: "dot".to_string(),
+                    class: MathClass::Binary,
+                    left: sym("c"),
+                    right: sym("d"),
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_relation_commands() {
+        match parse(r"f \colon A \to B").unwrap() {
+            MathExpr::Binary {
+                op, class, left, ..
+            } => {
+                assert_eq!(op, "->");
+                assert_eq!(class, MathClass::Relation);
+                assert_eq!(
+                    *left,
+                    MathExpr::Group(vec![
+                        MathExpr::Symbol("f".to_string()),
+                        MathExpr::Symbol("colon".to_string()),
+                        MathExpr::Symbol("A".to_string()),
+                    ])
+                );
+            }
+            other => panic!("Expected relation, got: {:?}", other),
+        }
+        // Operators without an operand stay symbols
+        assert_eq!(
+            parse(r"\pm 1").unwrap(),
+            MathExpr::Group(vec![
+                MathExpr::Symbol("plus.minus".to_string()),
+                MathExpr::Symbol("1".to_string()),
+            ])
+        );
+        assert_eq!(
+            parse(r"x \to").unwrap(),
+            MathExpr::Group(vec![
+                MathExpr::Symbol("x".to_string()),
+                MathExpr::Symbol("->".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_escaped_characters() {
+        assert_eq!(
+            parse(r"\{ \# \& \}").unwrap(),
+            MathExpr::Group(vec![
+                MathExpr::Symbol("{".to_string()),
+                MathExpr::Symbol("#".to_string()),
+                MathExpr::Text("&".to_string()),
+                MathExpr::Symbol("}".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_text_with_math() {
+        assert_eq!(
+            parse(r"\text{for all $x$, \$5}").unwrap(),
+            MathExpr::Group(vec![
+                MathExpr::Text("for all ".to_string()),
+                MathExpr::Symbol("x".to_string()),
+                MathExpr::Text(", $5".to_string()),
+            ])
+        );
+        assert_eq!(
+            parse(r"\textbf{if}").unwrap(),
+            MathExpr::Font {
+                variant: FontVariant::Bold,
+                body: Box::new(MathExpr::Text("if".to_string())),
+            }
+        );
+        // Nested font changes keep their text; other commands stay as written
+        assert_eq!(
+            parse(r"\text{\emph{a} b \foo}").unwrap(),
+            MathExpr::Text(r"a b \foo".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_derivatives() {
+        assert_eq!(
+            parse(r"\dv[2]{f}{x}").unwrap(),
+            MathExpr::Derivative {
+                func: Some(Box::new(MathExpr::Symbol("f".to_string()))),
+                vars: vec![MathExpr::Symbol("x".to_string())],
+                order: Some(Box::new(MathExpr::Symbol("2".to_string()))),
+                partial: false,
+                inline: false,
+            }
+        );
+        assert_eq!(
+            parse(r"\pdv*{x}").unwrap(),
+            MathExpr::Derivative {
+                func: None,
+                vars: vec![MathExpr::Symbol("x".to_string())],
+                order: None,
+                partial: true,
+                inline: true,
+            }
+        );
+        match parse(r"\pdv{f}{x}{y}").unwrap() {
+            MathExpr::Derivative { func, vars, .. } => {
+                assert!(func.is_some());
+                assert_eq!(vars.len(), 2);
+            }
+            other => panic!("Expected derivative, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_dirac() {
+        let symbol = |s: &str| Some(Box::new(MathExpr::Symbol(s.to_string())));
+        assert_eq!(
+            parse(r"\ket{\psi}").unwrap(),
+            MathExpr::Dirac {
+                bra: None,
+                operator: None,
+                ket: symbol("psi"),
+            }
+        );
+        // braket package and physics package spellings agree
+        let expected = MathExpr::Dirac {
+            bra: symbol("phi"),
+            operator: None,
+            ket: symbol("psi"),
+        };
+        assert_eq!(parse(r"\braket{\phi|\psi}").unwrap(), expected);
+        assert_eq!(parse(r"\braket{\phi}{\psi}").unwrap(), expected);
+        assert_eq!(
+            parse(r"\expval{H}{\psi}").unwrap(),
+            MathExpr::Dirac {
+                bra: symbol("psi"),
+                operator: symbol("H"),
+                ket: symbol("psi"),
+            }
+        );
+        assert_eq!(
+            parse(r"\abs*{x}").unwrap(),
+            MathExpr::command("abs", vec![MathExpr::Symbol("x".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_parse_negation() {
+        assert_eq!(
+            parse(r"x \not\in A").unwrap(),
+            MathExpr::Group(vec![
+                MathExpr::Symbol("x".to_string()),
+                MathExpr::Symbol("in.not".to_string()),
+                MathExpr::Symbol("A".to_string()),
+            ])
+        );
+        assert_eq!(
+            parse(r"\not=").unwrap(),
+            MathExpr::Symbol("eq.not".to_string())
+        );
+        assert_eq!(
+            parse(r"\not\perp").unwrap(),
+            MathExpr::command("cancel", vec![MathExpr::Symbol("perp".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(
+            parse(r"\textcolor{red}{x} y").unwrap(),
+            MathExpr::Group(vec![
+                MathExpr::Colored {
+                    color: "red".to_string(),
+                    body: Box::new(MathExpr::Symbol("x".to_string())),
+                },
+                MathExpr::Symbol("y".to_string()),
+            ])
+        );
+        // \color switches the color for the rest of the group
+        assert_eq!(
+            parse(r"\color{blue!30} x").unwrap(),
+            MathExpr::Colored {
+                color: "blue.lighten(70%)".to_string(),
+                body: Box::new(MathExpr::Symbol("x".to_string())),
+            }
+        );
+        assert_eq!(
+            typst_color(Some("HTML"), "FF8800"),
+            Some("rgb(\"#FF8800\")".to_string())
+        );
+        assert_eq!(
+            typst_color(Some("rgb"), "1,0.5,0"),
+            Some("rgb(255, 128, 0)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_modular_arithmetic() {
+        assert_eq!(
+            parse(r"\pmod{n}").unwrap(),
+            MathExpr::Group(vec![
+                MathExpr::Spacing(MathSpace::Quad),
+                MathExpr::Symbol("(".to_string()),
+                MathExpr::Symbol("mod".to_string()),
+                MathExpr::Spacing(MathSpace::Thin),
+                MathExpr::Symbol("n".to_string()),
+                MathExpr::Symbol(")".to_string()),
+            ])
+        );
+        match parse(r"\arg\max_x").unwrap() {
+            MathExpr::Operator {
+                name,
+                limits,
+                custom,
+                ..
+            } => {
+                assert_eq!(name, "arg max");
+                assert_eq!(limits, LimitPlacement::Limits);
+                assert!(custom);
+            }
+            other => panic!("Expected operator, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_environments() {
+        match parse(r"\begin{align*} a &= b \\ c &= d \end{align*}").unwrap() {
+            MathExpr::Environment { name, content } => {
+                assert_eq!(name, "aligned");
+                assert_eq!(content.len(), 2);
+            }
+            other => panic!("Expected environment, got {:?}", other),
+        }
+        // A nested matrix must not close the outer one early
+        match parse(r"\begin{pmatrix} \begin{pmatrix} 1 \end{pmatrix} & 2 \end{pmatrix}").unwrap() {
+            MathExpr::Environment { name, content } => {
+                assert_eq!(name, "pmatrix");
+                assert_eq!(content[0].len(), 2);
+            }
+            other => panic!("Expected environment, got {:?}", other),
+        }
+        assert_eq!(
+            find_environment_end(r"x \end{a} \end{a}", "a"),
+            Some((2, 7))
+        );
+        assert_eq!(environment_kind("gathered"), Some("gather"));
+        assert_eq!(environment_kind("tabular"), None);
+    }
+
+    #[test]
+    fn test_equation_label_and_tag() {
+        let source = r"\begin{equation} x \\tagged \label{ eq:1 } \tag{A} \end{equation}";
+        assert_eq!(equation_labels(source), ["eq:1"]);
+        assert_eq!(equation_tag(source), Some("(A)".to_string()));
+        assert_eq!(
+            equation_tag(r"x \tag*{$\star$}"),
+            Some(r"$\star$".to_string())
+        );
+        assert!(equation_labels("x + y").is_empty());
+        assert_eq!(
+            equation_labels(r"a \label{one} \\ b \label{two}"),
+            ["one", "two"]
+        );
+        assert_eq!(
+            equation_reference(r" \eqref{eq:1} "),
+            Some("eq:1".to_string())
+        );
+        assert_eq!(equation_reference(r"\ref{eq:1} + 1"), None);
+    }
+
+    #[test]
+    fn test_is_numbered_environment() {
+        assert!(is_numbered_environment(r"\begin{align} x \end{align}"));
+        assert!(is_numbered_environment(
+            "\n\\begin{equation}x\\end{equation}"
+        ));
+        assert!(!is_numbered_environment(r"\begin{align*} x \end{align*}"));
+        assert!(!is_numbered_environment(r"\begin{pmatrix} x \end{pmatrix}"));
+        assert!(!is_numbered_environment("x + y"));
+    }
 }
@@ -0,0 +1,438 @@
+//! Chemical formulas and equations from the mhchem package (\ce, \pu)
+
+use nom::{character::complete::multispace0, sequence::preceded, IResult};
+
+use super::math::parse_braced_raw;
+use super::siunitx;
+use crate::ast::math::MathExpr;
+
+/// Check whether a command is one of the mhchem commands handled here
+pub fn is_command(name: &str) -> bool {
+    matches!(name, "ce" | "pu")
+}
+
+/// Parse the argument of an mhchem command, starting right after the command name
+pub fn parse_command<'a>(input: &'a str, name: &str) -> IResult<&'a str, MathExpr> {
+    let (input, source) = preceded(multispace0, parse_braced_raw)(input)?;
+    let expr = match name {
+        // \pu{123 kJ/mol} is a number followed by a unit
+        "pu" => {
+            let source = source.trim();
+            let split = source.find(char::is_whitespace).unwrap_or(source.len());
+            MathExpr::Quantity {
+                values: vec![siunitx::parse_number(&source[..split])],
+                unit: siunitx::parse_unit(&source[split..]),
+            }
+        }
+        _ => MathExpr::Chemistry {
+            source: source.trim().to_string(),
+            expr: Box::new(parse_equation(source)),
+        },
+    };
+    Ok((input, expr))
+}
+
+/// Arrows between reactants and products and their Typst symbols, longest first
+const ARROWS: &[(&str, &str)] = &[
+    ("<=>>", "harpoons.rtlb"),
+    ("<<=>", "harpoons.rtlb"),
+    ("<-->", "arrows.rl"),
+    ("<=>", "harpoons.rtlb"),
+    ("<->", "arrow.l.r.long"),
+    ("->", "arrow.r.long"),
+    ("<-", "arrow.l.long"),
+];
+
+/// Parse a chemical equation: formulas separated by spaces, `+`, arrows and markers
+pub fn parse_equation(source: &str) -> MathExpr {
+    let mut parts = Vec::new();
+    let mut rest = source.trim_start();
+    while !rest.is_empty() {
+        if let Some(&(arrow, symbol)) = ARROWS.iter().find(|(a, _)| rest.starts_with(a)) {
+            rest = &rest[arrow.len()..];
+            // Conditions over and under the arrow: ->[H2O][\Delta]
+            let (after, above) = bracketed(rest);
+            let (after, below) = bracketed(after);
+            rest = after;
+            let label = |text: Option<&str>| {
+                text.filter(|t| !t.trim().is_empty())
+                    .map(|t| Box::new(parse_equation(t)))
+            };
+            let (top, bottom) = (label(above), label(below));
+            parts.push(if top.is_none() && bottom.is_none() {
+                MathExpr::symbol(symbol)
+            } else {
+                MathExpr::Attach {
+                    base: Box::new(MathExpr::symbol(symbol)),
+                    top,
+                    bottom,
+                }
+            });
+        } else if let Some(math) = rest.strip_prefix('$') {
+            let end = math.find('$').unwrap_or(math.len());
+            parts.push(
+                super::math::parse(&math[..end])
+                    .unwrap_or_else(|_| MathExpr::Text(math[..end].to_string())),
+            );
+            rest = math.get(end + 1..).unwrap_or("");
+        } else {
+            let end = word_end(rest);
+            let word = &rest[..end];
+            rest = &rest[end..];
+            parts.push(match word {
+                "+" | "=" => MathExpr::symbol(word),
+                // Gas evolution and precipitation markers
+                "^" => MathExpr::symbol("arrow.t"),
+                "v" => MathExpr::symbol("arrow.b"),
+                _ => parse_formula(word),
+            });
+        }
+        rest = rest.trim_start();
+    }
+
+    if parts.len() == 1 {
+        parts.pop().unwrap()
+    } else {
+        MathExpr::Group(parts)
+    }
+}
+
+/// Find the end of a space-separated word, skipping over spaces inside brackets
+fn word_end(input: &str) -> usize {
+    let mut depth = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '{' | '(' | '[' => depth += 1,
+            '}' | ')' | ']' => depth -= 1,
+            c if c.is_whitespace() && depth <= 0 => return i,
+            _ => {}
+        }
+    }
+    input.len()
+}
+
+/// Split off the content of a `[...]` group at the start of the input
+fn bracketed(input: &str) -> (&str, Option<&str>) {
+    let Some(content) = input.strip_prefix('[') else {
+        return (input, None);
+    };
+    let mut depth = 0;
+    for (i, c) in content.char_indices() {
+        match c {
+            '{' | '[' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            ']' if depth == 0 => return (&content[i + 1..], Some(&content[..i])),
+            ']' => depth -= 1,
+            _ => {}
+        }
+    }
+    (input, None)
+}
+
+/// Find the closing bracket matching the one at the start of the input
+fn matching_close(input: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse a single formula like `2H2O`, `Ca(OH)2`, `SO4^2-`, `Fe^{3+}`, `NaCl(aq)`
+/// or `CuSO4*5H2O`: element symbols upright with counts as subscripts
+pub fn parse_formula(word: &str) -> MathExpr {
+    let mut atoms: Vec<MathExpr> = Vec::new();
+    // Digits at the start of a formula or after a hydrate dot are a coefficient
+    let mut coefficient = true;
+    let mut rest = word;
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() || (coefficient && c == '/') {
+            // Only coefficients have fractions, like 0.5 or 1/2; after a count, a dot
+            // starts a hydrate: CuSO4.5H2O
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || (coefficient && (c == '.' || c == '/'))))
+                .unwrap_or(rest.len());
+            let digits = MathExpr::symbol(&rest[..len]);
+            rest = &rest[len..];
+            match atoms.pop() {
+                Some(atom) if !coefficient => atoms.push(MathExpr::subscript(atom, digits)),
+                atom => {
+                    atoms.extend(atom);
+                    atoms.push(digits);
+                }
+            }
+            continue;
+        }
+        coefficient = false;
+
+        match c {
+            'A'..='Z' => {
+                let len = 1 + rest[1..]
+                    .find(|c: char| !c.is_ascii_lowercase())
+                    .unwrap_or(rest.len() - 1);
+                let element = &rest[..len];
+                rest = &rest[len..];
+                // Neighboring elements without counts share one text run: "CO"_2
+                match atoms.last_mut() {
+                    Some(MathExpr::Text(text))
+                        if text.starts_with(|c: char| c.is_ascii_uppercase())
+                            && text.chars().all(|c| c.is_ascii_alphabetic()) =>
+                    {
+                        text.push_str(element);
+                    }
+                    _ => atoms.push(MathExpr::Text(element.to_string())),
+                }
+            }
+            '(' | '[' | '{' => {
+                let Some(close) = matching_close(rest) else {
+                    atoms.push(MathExpr::Text(rest.to_string()));
+                    break;
+                };
+                let inner = &rest[1..close];
+                rest = &rest[close + 1..];
+                if c == '{' {
+                    // Braces protect text from formula parsing
+                    atoms.push(MathExpr::Text(inner.to_string()));
+                } else if c == '(' && ["s", "l", "g", "aq", "cr"].contains(&inner) {
+                    // States of aggregation stay in running text size
+                    atoms.push(MathExpr::Text(format!("({})", inner)));
+                } else {
+                    let closing = if c == '(' { ")" } else { "]" };
+                    atoms.push(MathExpr::Group(vec![
+                        MathExpr::symbol(c.to_string()),
+                        parse_formula(inner),
+                        MathExpr::symbol(closing),
+                    ]));
+                    // A count after the brackets may be a variable: (CH2)n
+                    let len = rest
+                        .find(|c: char| !c.is_ascii_lowercase())
+                        .unwrap_or(rest.len());
+                    if len > 0 {
+                        let count = MathExpr::symbol(&rest[..len]);
+                        rest = &rest[len..];
+                        let group = atoms.pop().unwrap();
+                        atoms.push(MathExpr::subscript(group, count));
+                    }
+                }
+            }
+            '^' => {
+                rest = &rest[1..];
+                let (after, charge) = match rest.strip_prefix('{') {
+                    Some(_) => match matching_close(rest) {
+                        Some(close) => (&rest[close + 1..], &rest[1..close]),
+                        None => ("", &rest[1..]),
+                    },
+                    None => {
+                        let len = rest
+                            .find(|c: char| !(c.is_ascii_digit() || c == '+' || c == '-'))
+                            .unwrap_or(rest.len());
+                        (&rest[len..], &rest[..len])
+                    }
+                };
+                rest = after;
+                attach_charge(&mut atoms, charge);
+            }
+            // A trailing sign is the charge of an ion: Na+, Cl-, SO4 2-
+            '+' | '-' if rest[1..].chars().all(|c| c == '+' || c == '-') => {
+                attach_charge(&mut atoms, rest);
+                rest = "";
+            }
+            // Bonds inside a formula: CH3-CH3, CH2=CH2, HC#CH
+            '-' | '=' | '#' => {
+                let bond = match c {
+                    '-' => "-",
+                    '=' => "=",
+                    _ => "≡",
+                };
+                atoms.push(MathExpr::Text(bond.to_string()));
+                rest = &rest[1..];
+            }
+            // Addition compounds and hydrates: CuSO4*5H2O, CuSO4.5H2O
+            '*' | '.' => {
+                atoms.push(MathExpr::symbol("dot.op"));
+                coefficient = true;
+                rest = &rest[1..];
+            }
+            // Commands like \Delta or \text{heat} are read as math
+            '\\' => {
+                let name_len = match rest[1..].find(|c: char| !c.is_ascii_alphabetic()) {
+                    // Control symbols like \, are one character long
+                    Some(0) => rest[1..].chars().next().map_or(0, char::len_utf8),
+                    Some(len) => len,
+                    None => rest.len() - 1,
+                };
+                // Take the braced arguments too, as in \text{heat}
+                let mut len = 1 + name_len;
+                while let Ok((after, _)) = super::math::parse_braced_raw(&rest[len..]) {
+                    len = rest.len() - after.len();
+                }
+                atoms.push(
+                    super::math::parse(&rest[..len])
+                        .unwrap_or_else(|_| MathExpr::Text(rest[..len].to_string())),
+                );
+                rest = &rest[len..];
+            }
+            '$' => {
+                let math = &rest[1..];
+                let end = math.find('$').unwrap_or(math.len());
+                atoms.push(
+                    super::math::parse(&math[..end])
+                        .unwrap_or_else(|_| MathExpr::Text(math[..end].to_string())),
+                );
+                rest = math.get(end + 1..).unwrap_or("");
+            }
+            _ => {
+                atoms.push(MathExpr::Text(c.to_string()));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    if atoms.len() == 1 {
+        atoms.pop().unwrap()
+    } else {
+        MathExpr::Group(atoms)
+    }
+}
+
+/// Attach a charge like `2+` as superscript of the last atom, beside any count
+fn attach_charge(atoms: &mut Vec<MathExpr>, charge: &str) {
+    let charge = match charge.len() {
+        1 => MathExpr::symbol(charge),
+        _ => {
+            let split = charge
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(charge.len());
+            let (count, signs) = charge.split_at(split);
+            let mut parts: Vec<MathExpr> = Vec::new();
+            if !count.is_empty() {
+                parts.push(MathExpr::symbol(count));
+            }
+            parts.extend(signs.chars().map(|c| MathExpr::symbol(c.to_string())));
+            MathExpr::Group(parts)
+        }
+    };
+    let expr = match atoms.pop() {
+        Some(MathExpr::Subscript { base, sub }) => MathExpr::SubSup {
+            base,
+            sub,
+            sup: Box::new(charge),
+        },
+        Some(atom) => MathExpr::superscript(atom, charge),
+        // A charge with nothing before it is set on an empty base
+        None => MathExpr::superscript(MathExpr::Text(String::new()), charge),
+    };
+    atoms.push(expr);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> MathExpr {
+        MathExpr::Text(s.to_string())
+    }
+
+    #[test]
+    fn test_parse_formula_counts() {
+        assert_eq!(
+            parse_formula("2H2O"),
+            MathExpr::Group(vec![
+                MathExpr::symbol("2"),
+                MathExpr::subscript(text("H"), MathExpr::symbol("2")),
+                text("O"),
+            ])
+        );
+        assert_eq!(
+            parse_formula("CO2"),
+            MathExpr::subscript(text("CO"), MathExpr::symbol("2"))
+        );
+        // The dot after a count starts a hydrate rather than a decimal count
+        assert_eq!(
+            parse_formula("CuSO4.5H2O"),
+            MathExpr::Group(vec![
+                MathExpr::subscript(text("CuSO"), MathExpr::symbol("4")),
+                MathExpr::symbol("dot.op"),
+                MathExpr::symbol("5"),
+                MathExpr::subscript(text("H"), MathExpr::symbol("2")),
+                text("O"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_formula_charges() {
+        assert_eq!(
+            parse_formula("SO4^2-"),
+            MathExpr::SubSup {
+                base: Box::new(text("SO")),
+                sub: Box::new(MathExpr::symbol("4")),
+                sup: Box::new(MathExpr::Group(vec![
+                    MathExpr::symbol("2"),
+                    MathExpr::symbol("-"),
+                ])),
+            }
+        );
+        assert_eq!(
+            parse_formula("Na+"),
+            MathExpr::superscript(text("Na"), MathExpr::symbol("+"))
+        );
+    }
+
+    #[test]
+    fn test_parse_formula_states_and_groups() {
+        assert_eq!(
+            parse_formula("NaCl(aq)"),
+            MathExpr::Group(vec![text("NaCl"), text("(aq)")])
+        );
+        match parse_formula("Ca(OH)2") {
+            MathExpr::Group(atoms) => {
+                assert_eq!(atoms[0], text("Ca"));
+                assert!(matches!(atoms[1], MathExpr::Subscript { .. }));
+            }
+            other => panic!("Expected group, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_equation() {
+        match parse_equation("H2O + CO2 <=> H2CO3") {
+            MathExpr::Group(parts) => {
+                assert_eq!(parts.len(), 5);
+                assert_eq!(parts[1], MathExpr::symbol("+"));
+                assert_eq!(parts[3], MathExpr::symbol("harpoons.rtlb"));
+            }
+            other => panic!("Expected group, got {:?}", other),
+        }
+        match parse_equation("A ->[\\Delta] B") {
+            MathExpr::Group(parts) => assert!(matches!(
+                &parts[1],
+                MathExpr::Attach {
+                    top: Some(_),
+                    bottom: None,
+                    ..
+                }
+            )),
+            other => panic!("Expected group, got {:?}", other),
+        }
+        match parse_equation(r"A + B ->[\text{heat}] C") {
+            MathExpr::Group(parts) => match &parts[3] {
+                MathExpr::Attach { top: Some(top), .. } => {
+                    assert_eq!(**top, MathExpr::Text("heat".to_string()));
+                }
+                other => panic!("Expected arrow with label, got {:?}", other),
+            },
+            other => panic!("Expected group, got {:?}", other),
+        }
+    }
+}
@@ -2,6 +2,9 @@
 
 pub mod document;
 pub mod math;
+pub mod mhchem;
+pub mod siunitx;
+pub mod symbols;
 // pub mod tokenizer;   // Reserved for future use
 // pub mod commands;    // Reserved for future use
 // pub mod environments; // Reserved for future use
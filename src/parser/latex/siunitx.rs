@@ -0,0 +1,403 @@
+//! Numbers, units and quantities from the siunitx package
+
+use nom::{
+    bytes::complete::take_until,
+    character::complete::{char, multispace0},
+    combinator::opt,
+    sequence::{delimited, preceded},
+    IResult,
+};
+
+use super::math::parse_braced_raw;
+use crate::ast::math::{MathExpr, SiNumber, UnitFactor};
+
+/// Check whether a command is one of the siunitx commands handled here
+pub fn is_command(name: &str) -> bool {
+    matches!(
+        name,
+        "num" | "numrange" | "si" | "unit" | "SI" | "qty" | "SIrange" | "qtyrange" | "ang"
+    )
+}
+
+/// Parse the arguments of a siunitx command, starting right after the command name
+pub fn parse_command<'a>(input: &'a str, name: &str) -> IResult<&'a str, MathExpr> {
+    let (input, _) = options(input)?;
+    let quantity = |values, unit| MathExpr::Quantity { values, unit };
+    match name {
+        "num" => {
+            let (input, number) = arg(input)?;
+            Ok((input, quantity(vec![parse_number(number)], vec![])))
+        }
+        "numrange" => {
+            let (input, from) = arg(input)?;
+            let (input, to) = arg(input)?;
+            let values = vec![parse_number(from), parse_number(to)];
+            Ok((input, quantity(values, vec![])))
+        }
+        "si" | "unit" => {
+            let (input, unit) = arg(input)?;
+            Ok((input, quantity(vec![], parse_unit(unit))))
+        }
+        "SIrange" | "qtyrange" => {
+            let (input, from) = arg(input)?;
+            let (input, to) = arg(input)?;
+            let (input, unit) = arg(input)?;
+            let values = vec![parse_number(from), parse_number(to)];
+            Ok((input, quantity(values, parse_unit(unit))))
+        }
+        "ang" => {
+            let (input, angle) = arg(input)?;
+            Ok((input, parse_angle(angle)))
+        }
+        // \SI{9.81}{\meter}, where siunitx 2 also allows a pre-unit option in between
+        _ => {
+            let (input, number) = arg(input)?;
+            let (input, _) = options(input)?;
+            let (input, unit) = arg(input)?;
+            Ok((
+                input,
+                quantity(vec![parse_number(number)], parse_unit(unit)),
+            ))
+        }
+    }
+}
+
+/// Skip an optional key-value option list: `[per-mode = symbol]`
+fn options(input: &str) -> IResult<&str, Option<&str>> {
+    preceded(
+        multispace0,
+        opt(delimited(char('['), take_until("]"), char(']'))),
+    )(input)
+}
+
+fn arg(input: &str) -> IResult<&str, &str> {
+    preceded(multispace0, parse_braced_raw)(input)
+}
+
+/// Read a number like `1.2e-3`, `-.5`, `1,5` or `3.0 \pm 0.2`
+pub fn parse_number(raw: &str) -> SiNumber {
+    let number: String = raw
+        .replace("\\pm", "+-")
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '{' && *c != '}')
+        .map(|c| if c == ',' { '.' } else { c })
+        .collect();
+
+    let (number, exponent) = match number.find(['e', 'E']) {
+        Some(i) => (&number[..i], Some(number[i + 1..].trim_start_matches('+'))),
+        None => (number.as_str(), None),
+    };
+    let (mantissa, uncertainty) = match number.split_once("+-") {
+        Some((mantissa, uncertainty)) => (mantissa, Some(uncertainty)),
+        None => (number, None),
+    };
+
+    // siunitx adds the zero left out before a decimal point
+    let mantissa = match mantissa.strip_prefix('-') {
+        Some(rest) if rest.starts_with('.') => format!("-0{}", rest),
+        _ if mantissa.starts_with('.') => format!("0{}", mantissa),
+        _ => mantissa.to_string(),
+    };
+    SiNumber {
+        mantissa,
+        uncertainty: uncertainty.map(str::to_string),
+        exponent: exponent.map(str::to_string),
+    }
+}
+
+/// Read a unit written with macros (`\kilo\meter\per\second\squared`) or literally
+/// (`km/s^2`, `kg.m.s^{-2}`)
+pub fn parse_unit(raw: &str) -> Vec<UnitFactor> {
+    let mut factors: Vec<UnitFactor> = Vec::new();
+    let mut prefix: Option<(&str, &str)> = None;
+    // Power set by \square or \cubic for the next unit
+    let mut next_power = 1;
+    // \per puts the next unit in the denominator, a slash all of the following ones
+    let mut per = false;
+    let mut slash = false;
+
+    let mut rest = raw;
+    while let Some(c) = rest.chars().next() {
+        let mut unit: Option<(String, String)> = None;
+        if c == '\\' {
+            let name_len = rest[1..]
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len() - 1);
+            let name = &rest[1..1 + name_len];
+            rest = &rest[1 + name_len..];
+            match name {
+                // Control symbols like \, are spacing
+                "" => {
+                    let skip = rest.chars().next().map_or(0, char::len_utf8);
+                    rest = &rest[skip..];
+                }
+                "per" => per = true,
+                "square" => next_power = 2,
+                "cubic" => next_power = 3,
+                "squared" | "cubed" => {
+                    let power = if name == "squared" { 2 } else { 3 };
+                    if let Some(last) = factors.last_mut() {
+                        last.power = last.power.signum() * power;
+                    }
+                }
+                "tothe" | "raiseto" => {
+                    let (after, power) = arg(rest).unwrap_or((rest, ""));
+                    rest = after;
+                    let power = power.trim().parse().unwrap_or(1);
+                    if name == "raiseto" {
+                        next_power = power;
+                    } else if let Some(last) = factors.last_mut() {
+                        last.power = last.power.signum() * power;
+                    }
+                }
+                _ => {
+                    if let Some(&(_, symbol)) = PREFIXES.iter().find(|(n, _)| *n == name) {
+                        prefix = Some((name, symbol));
+                    } else {
+                        let (symbol, long_name) = UNITS
+                            .iter()
+                            .find(|(n, _, _)| *n == name)
+                            .map(|&(_, symbol, long_name)| (symbol, long_name))
+                            .unwrap_or((name, name));
+                        unit = Some((symbol.to_string(), long_name.to_string()));
+                    }
+                }
+            }
+        } else if c.is_alphabetic() || c == '%' || c == '°' {
+            let len = rest
+                .find(|c: char| !(c.is_alphabetic() || c == '%' || c == '°'))
+                .unwrap_or(rest.len());
+            unit = Some((rest[..len].to_string(), rest[..len].to_string()));
+            rest = &rest[len..];
+        } else if c == '^' {
+            rest = &rest[1..];
+            let (after, power) = match arg(rest) {
+                Ok(parsed) => parsed,
+                Err(_) => {
+                    let len = rest
+                        .char_indices()
+                        .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+                        .map_or(rest.len(), |(i, _)| i);
+                    (&rest[len..], &rest[..len])
+                }
+            };
+            rest = after;
+            if let (Some(last), Ok(power)) = (factors.last_mut(), power.trim().parse::<i32>()) {
+                last.power = last.power.signum() * power;
+            }
+        } else {
+            // Products are written with `.`, `~`, `*` or spaces
+            if c == '/' {
+                slash = true;
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+
+        if let Some((symbol, long_name)) = unit {
+            let (symbol, name) = match prefix.take() {
+                Some((prefix_name, prefix_symbol)) => (
+                    format!("{}{}", prefix_symbol, symbol),
+                    format!("{} {}", prefix_name, long_name),
+                ),
+                None => (symbol, long_name),
+            };
+            let sign = if per || slash { -1 } else { 1 };
+            factors.push(UnitFactor {
+                symbol,
+                name,
+                power: sign * next_power,
+            });
+            per = false;
+            next_power = 1;
+        }
+    }
+    factors
+}
+
+/// Read an angle given as decimal degrees or as `degrees;minutes;seconds`
+pub fn parse_angle(raw: &str) -> MathExpr {
+    let marks = ["degree", "prime", "prime.double"];
+    let mut parts = Vec::new();
+    for (part, mark) in raw.split(';').zip(marks) {
+        let part = part.trim();
+        if !part.is_empty() {
+            parts.push(MathExpr::Symbol(parse_number(part).mantissa));
+            parts.push(MathExpr::Symbol(mark.to_string()));
+        }
+    }
+    MathExpr::Group(parts)
+}
+
+/// Prefix macros and their symbols
+static PREFIXES: &[(&str, &str)] = &[
+    ("quecto", "q"),
+    ("ronto", "r"),
+    ("yocto", "y"),
+    ("zepto", "z"),
+    ("atto", "a"),
+    ("femto", "f"),
+    ("pico", "p"),
+    ("nano", "n"),
+    ("micro", "μ"),
+    ("milli", "m"),
+    ("centi", "c"),
+    ("deci", "d"),
+    ("deca", "da"),
+    ("deka", "da"),
+    ("hecto", "h"),
+    ("kilo", "k"),
+    ("mega", "M"),
+    ("giga", "G"),
+    ("tera", "T"),
+    ("peta", "P"),
+    ("exa", "E"),
+    ("zetta", "Z"),
+    ("yotta", "Y"),
+    ("ronna", "R"),
+    ("quetta", "Q"),
+];
+
+/// Unit macros, their symbols and their spelled-out names
+static UNITS: &[(&str, &str, &str)] = &[
+    // SI base units
+    ("meter", "m", "meter"),
+    ("metre", "m", "meter"),
+    ("second", "s", "second"),
+    ("kilogram", "kg", "kilo gram"),
+    ("gram", "g", "gram"),
+    ("ampere", "A", "ampere"),
+    ("kelvin", "K", "kelvin"),
+    ("mole", "mol", "mole"),
+    ("candela", "cd", "candela"),
+    // Derived units
+    ("hertz", "Hz", "hertz"),
+    ("newton", "N", "newton"),
+    ("pascal", "Pa", "pascal"),
+    ("joule", "J", "joule"),
+    ("watt", "W", "watt"),
+    ("coulomb", "C", "coulomb"),
+    ("volt", "V", "volt"),
+    ("farad", "F", "farad"),
+    ("ohm", "Ω", "ohm"),
+    ("siemens", "S", "siemens"),
+    ("weber", "Wb", "weber"),
+    ("tesla", "T", "tesla"),
+    ("henry", "H", "henry"),
+    ("degreeCelsius", "°C", "degree Celsius"),
+    ("celsius", "°C", "degree Celsius"),
+    ("lumen", "lm", "lumen"),
+    ("lux", "lx", "lux"),
+    ("becquerel", "Bq", "becquerel"),
+    ("gray", "Gy", "gray"),
+    ("sievert", "Sv", "sievert"),
+    ("katal", "kat", "katal"),
+    ("radian", "rad", "radian"),
+    ("steradian", "sr", "steradian"),
+    // Units accepted for use with SI
+    ("minute", "min", "minute"),
+    ("hour", "h", "hour"),
+    ("day", "d", "day"),
+    ("degree", "°", "degree"),
+    ("arcminute", "′", "arcminute"),
+    ("arcsecond", "″", "arcsecond"),
+    ("hectare", "ha", "hectare"),
+    ("litre", "L", "liter"),
+    ("liter", "L", "liter"),
+    ("tonne", "t", "tonne"),
+    ("electronvolt", "eV", "electronvolt"),
+    ("dalton", "Da", "dalton"),
+    ("astronomicalunit", "au", "astronomicalunit"),
+    ("atomicmassunit", "u", "atomicmassunit"),
+    // Other units
+    ("angstrom", "Å", "angstrom"),
+    ("bar", "bar", "bar"),
+    ("barn", "b", "barn"),
+    ("bel", "B", "bel"),
+    ("decibel", "dB", "deci bel"),
+    ("neper", "Np", "neper"),
+    ("percent", "%", "percent"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn factor(symbol: &str, name: &str, power: i32) -> UnitFactor {
+        UnitFactor {
+            symbol: symbol.to_string(),
+            name: name.to_string(),
+            power,
+        }
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(
+            parse_number("1.2e-3"),
+            SiNumber {
+                mantissa: "1.2".to_string(),
+                uncertainty: None,
+                exponent: Some("-3".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_number(r"3,0 \pm 0,2"),
+            SiNumber {
+                mantissa: "3.0".to_string(),
+                uncertainty: Some("0.2".to_string()),
+                exponent: None,
+            }
+        );
+        assert_eq!(parse_number("-.5").mantissa, "-0.5");
+    }
+
+    #[test]
+    fn test_parse_unit_macros() {
+        assert_eq!(
+            parse_unit(r"\kilo\meter\per\second\squared"),
+            vec![factor("km", "kilo meter", 1), factor("s", "second", -2)]
+        );
+        assert_eq!(
+            parse_unit(r"\square\meter \per\kelvin"),
+            vec![factor("m", "meter", 2), factor("K", "kelvin", -1)]
+        );
+    }
+
+    #[test]
+    fn test_parse_literal_unit() {
+        assert_eq!(
+            parse_unit("kg.m/s^2"),
+            vec![
+                factor("kg", "kg", 1),
+                factor("m", "m", 1),
+                factor("s", "s", -2)
+            ]
+        );
+        assert_eq!(
+            parse_unit("m s^{-1}"),
+            vec![factor("m", "m", 1), factor("s", "s", -1)]
+        );
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let (rest, expr) = parse_command("[per-mode=symbol]{9.81}{\\meter} m", "SI").unwrap();
+        assert_eq!(rest, " m");
+        assert_eq!(
+            expr,
+            MathExpr::Quantity {
+                values: vec![parse_number("9.81")],
+                unit: vec![factor("m", "meter", 1)],
+            }
+        );
+        assert_eq!(
+            parse_command("{12;30;}", "ang").unwrap().1,
+            MathExpr::Group(vec![
+                MathExpr::Symbol("12".to_string()),
+                MathExpr::Symbol("degree".to_string()),
+                MathExpr::Symbol("30".to_string()),
+                MathExpr::Symbol("prime".to_string()),
+            ])
+        );
+    }
+}
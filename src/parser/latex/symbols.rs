@@ -0,0 +1,644 @@
+//! LaTeX symbol commands mapped to Typst symbol names
+//!
+//! The Typst names come from the symbol reference in `reference/symbols/sym.md`.
+//! A few entries use Typst shorthands (`->`, `<=`, ...) or a literal Unicode
+//! character when Typst has no name for the glyph.
+
+use crate::ast::math::MathClass::{
+    self, Binary, Closing, Large, Opening, Ordinary, Punctuation, Relation,
+};
+
+/// Look up the Typst symbol for a LaTeX command name (without the backslash)
+pub fn lookup(cmd: &str) -> Option<&'static str> {
+    entry(cmd).map(|(_, typst, _)| *typst)
+}
+
+/// Look up the TeX math class of a LaTeX symbol command (without the backslash)
+pub fn class(cmd: &str) -> Option<MathClass> {
+    entry(cmd).map(|(_, _, class)| *class)
+}
+
+/// Look up the TeX math class of a Typst symbol name, if the LaTeX commands written
+/// with it agree on one: `|` is opening as `\lvert` but closing as `\rvert`
+pub fn typst_class(name: &str) -> Option<MathClass> {
+    let mut classes = SYMBOLS
+        .iter()
+        .filter(|(_, typst, _)| *typst == name)
+        .map(|(_, _, class)| *class);
+    match classes.next() {
+        Some(class) => classes.all(|other| other == class).then_some(class),
+        // Negations outside the table, like `eq.not` for \not=, are relations
+        None => name.ends_with(".not").then_some(Relation),
+    }
+}
+
+/// Check whether a name is the Typst name of a symbol in the table
+pub fn is_typst_name(name: &str) -> bool {
+    SYMBOLS.iter().any(|(_, typst, _)| *typst == name)
+}
+
+/// Typst name of a shorthand relation character, e.g. `eq` for `=`
+pub fn shorthand_name(c: char) -> &'static str {
+    match c {
+        '<' => "lt",
+        '>' => "gt",
+        _ => "eq",
+    }
+}
+
+/// Typst name to negate for a symbol that may be written as a shorthand, e.g. `lt.eq`
+/// for `<=`
+pub fn negatable_name(symbol: &str) -> &str {
+    match symbol {
+        "<=" => "lt.eq",
+        ">=" => "gt.eq",
+        "->" => "arrow.r",
+        "<-" => "arrow.l",
+        "<->" => "arrow.l.r",
+        "=>" => "arrow.r.double",
+        "<=>" => "arrow.l.r.double",
+        _ => symbol,
+    }
+}
+
+/// Typst name of the negated form of a symbol, e.g. `in.not` for `in`
+pub fn negated(name: &str) -> Option<String> {
+    let negated = format!("{}.not", name);
+    (is_typst_name(&negated) || NEGATED_EXTRA.contains(&negated.as_str())).then_some(negated)
+}
+
+/// Negated Typst symbols without a LaTeX command of their own in the table
+const NEGATED_EXTRA: &[&str] = &[
+    "eq.not",
+    "equiv.not",
+    "approx.not",
+    "subset.not",
+    "supset.not",
+    "in.rev.not",
+];
+
+fn entry(cmd: &str) -> Option<&'static (&'static str, &'static str, MathClass)> {
+    SYMBOLS.iter().find(|(latex, _, _)| *latex == cmd)
+}
+
+/// LaTeX command name to Typst symbol and the math class LaTeX gives it, covering
+/// the LaTeX core, amssymb, stmaryrd and mathtools
+static SYMBOLS: &[(&str, &str, MathClass)] = &[
+    // Greek letters
+    ("alpha", "alpha", Ordinary),
+    ("beta", "beta", Ordinary),
+    ("gamma", "gamma", Ordinary),
+    ("delta", "delta", Ordinary),
+    ("epsilon", "epsilon", Ordinary),
+    ("zeta", "zeta", Ordinary),
+    ("eta", "eta", Ordinary),
+    ("theta", "theta", Ordinary),
+    ("iota", "iota", Ordinary),
+    ("kappa", "kappa", Ordinary),
+    ("lambda", "lambda", Ordinary),
+    ("mu", "mu", Ordinary),
+    ("nu", "nu", Ordinary),
+    ("xi", "xi", Ordinary),
+    ("omicron", "omicron", Ordinary),
+    ("pi", "pi", Ordinary),
+    ("rho", "rho", Ordinary),
+    ("sigma", "sigma", Ordinary),
+    ("tau", "tau", Ordinary),
+    ("upsilon", "upsilon", Ordinary),
+    ("phi", "phi", Ordinary),
+    ("chi", "chi", Ordinary),
+    ("psi", "psi", Ordinary),
+    ("omega", "omega", Ordinary),
+    ("Gamma", "Gamma", Ordinary),
+    ("Delta", "Delta", Ordinary),
+    ("Theta", "Theta", Ordinary),
+    ("Lambda", "Lambda", Ordinary),
+    ("Xi", "Xi", Ordinary),
+    ("Pi", "Pi", Ordinary),
+    ("Sigma", "Sigma", Ordinary),
+    ("Upsilon", "Upsilon", Ordinary),
+    ("Phi", "Phi", Ordinary),
+    ("Psi", "Psi", Ordinary),
+    ("Omega", "Omega", Ordinary),
+    // Greek letter variants
+    ("varepsilon", "epsilon.alt", Ordinary),
+    ("vartheta", "theta.alt", Ordinary),
+    ("varkappa", "kappa.alt", Ordinary),
+    ("varpi", "pi.alt", Ordinary),
+    ("varrho", "rho.alt", Ordinary),
+    ("varsigma", "sigma.alt", Ordinary),
+    ("varphi", "phi.alt", Ordinary),
+    ("digamma", "digamma", Ordinary),
+    ("backepsilon", "epsilon.alt.rev", Ordinary),
+    // Hebrew letters
+    ("aleph", "aleph", Ordinary),
+    ("beth", "beth", Ordinary),
+    ("gimel", "gimel", Ordinary),
+    ("daleth", "daleth", Ordinary),
+    // Letter-like symbols
+    ("ell", "ell", Ordinary),
+    ("hbar", "planck", Ordinary),
+    ("hslash", "planck", Ordinary),
+    ("Re", "Re", Ordinary),
+    ("Im", "Im", Ordinary),
+    ("wp", "℘", Ordinary),
+    ("imath", "dotless.i", Ordinary),
+    ("jmath", "dotless.j", Ordinary),
+    ("partial", "partial", Ordinary),
+    ("nabla", "nabla", Ordinary),
+    ("infty", "infinity", Ordinary),
+    ("mho", "Omega.inv", Ordinary),
+    ("complement", "complement", Ordinary),
+    ("emptyset", "emptyset", Ordinary),
+    ("varnothing", "nothing", Ordinary),
+    // Logic
+    ("forall", "forall", Ordinary),
+    ("exists", "exists", Ordinary),
+    ("nexists", "exists.not", Ordinary),
+    ("neg", "not", Ordinary),
+    ("lnot", "not", Ordinary),
+    ("land", "and", Binary),
+    ("lor", "or", Binary),
+    ("top", "top", Ordinary),
+    ("bot", "bot", Ordinary),
+    ("therefore", "therefore", Relation),
+    ("because", "because", Relation),
+    // Binary operators
+    ("pm", "plus.minus", Binary),
+    ("mp", "minus.plus", Binary),
+    ("times", "times", Binary),
+    ("div", "div", Binary),
+    ("cdot", "dot", Binary),
+    ("centerdot", "dot", Binary),
+    ("ast", "ast", Binary),
+    ("star", "star.op", Binary),
+    ("circ", "compose", Binary),
+    ("bullet", "bullet.op", Binary),
+    ("oplus", "plus.o", Binary),
+    ("ominus", "minus.o", Binary),
+    ("otimes", "times.o", Binary),
+    ("oslash", "slash.o", Binary),
+    ("odot", "dot.o", Binary),
+    ("circledast", "ast.op.o", Binary),
+    ("circleddash", "dash.o", Binary),
+    ("boxplus", "plus.square", Binary),
+    ("boxminus", "minus.square", Binary),
+    ("boxtimes", "times.square", Binary),
+    ("boxdot", "dot.square", Binary),
+    ("setminus", "without", Binary),
+    ("smallsetminus", "without", Binary),
+    ("cap", "inter", Binary),
+    ("cup", "union", Binary),
+    ("Cap", "inter.double", Binary),
+    ("Cup", "union.double", Binary),
+    ("sqcap", "inter.sq", Binary),
+    ("sqcup", "union.sq", Binary),
+    ("uplus", "union.plus", Binary),
+    ("wedge", "and", Binary),
+    ("vee", "or", Binary),
+    ("curlywedge", "and.curly", Binary),
+    ("curlyvee", "or.curly", Binary),
+    ("barwedge", "⊼", Binary),
+    ("amalg", "product.co", Binary),
+    ("wr", "wreath", Binary),
+    ("dotplus", "plus.dot", Binary),
+    ("divideontimes", "times.div", Binary),
+    ("ltimes", "times.l", Binary),
+    ("rtimes", "times.r", Binary),
+    ("leftthreetimes", "times.three.l", Binary),
+    ("rightthreetimes", "times.three.r", Binary),
+    ("intercal", "⊺", Binary),
+    ("dagger", "dagger", Binary),
+    ("dag", "dagger", Binary),
+    ("ddagger", "dagger.double", Binary),
+    ("ddag", "dagger.double", Binary),
+    ("diamond", "diamond.stroked.small", Binary),
+    ("bigtriangleup", "triangle.stroked.t", Binary),
+    ("bigtriangledown", "triangle.stroked.b", Binary),
+    ("triangleleft", "triangle.stroked.small.l", Binary),
+    ("triangleright", "triangle.stroked.small.r", Binary),
+    // Equality and order relations
+    ("le", "<=", Relation),
+    ("leq", "<=", Relation),
+    ("ge", ">=", Relation),
+    ("geq", ">=", Relation),
+    ("ne", "!=", Relation),
+    ("neq", "!=", Relation),
+    ("leqq", "lt.equiv", Relation),
+    ("geqq", "gt.equiv", Relation),
+    ("leqslant", "lt.eq.slant", Relation),
+    ("geqslant", "gt.eq.slant", Relation),
+    ("ll", "lt.double", Relation),
+    ("gg", "gt.double", Relation),
+    ("lll", "lt.triple", Relation),
+    ("ggg", "gt.triple", Relation),
+    ("lessdot", "lt.dot", Relation),
+    ("gtrdot", "gt.dot", Relation),
+    ("lesssim", "lt.tilde", Relation),
+    ("gtrsim", "gt.tilde", Relation),
+    ("lessapprox", "lt.approx", Relation),
+    ("gtrapprox", "gt.approx", Relation),
+    ("lessgtr", "lt.gt", Relation),
+    ("gtrless", "gt.lt", Relation),
+    ("lesseqgtr", "lt.eq.gt", Relation),
+    ("gtreqless", "gt.eq.lt", Relation),
+    ("nless", "lt.not", Relation),
+    ("ngtr", "gt.not", Relation),
+    ("nleq", "lt.eq.not", Relation),
+    ("ngeq", "gt.eq.not", Relation),
+    ("lneq", "lt.neq", Relation),
+    ("gneq", "gt.neq", Relation),
+    ("lneqq", "lt.nequiv", Relation),
+    ("gneqq", "gt.nequiv", Relation),
+    ("lnsim", "lt.ntilde", Relation),
+    ("gnsim", "gt.ntilde", Relation),
+    ("lnapprox", "lt.napprox", Relation),
+    ("gnapprox", "gt.napprox", Relation),
+    ("prec", "prec", Relation),
+    ("succ", "succ", Relation),
+    ("preceq", "prec.eq", Relation),
+    ("succeq", "succ.eq", Relation),
+    ("precsim", "prec.tilde", Relation),
+    ("succsim", "succ.tilde", Relation),
+    ("precapprox", "prec.approx", Relation),
+    ("succapprox", "succ.approx", Relation),
+    ("preccurlyeq", "prec.curly.eq", Relation),
+    ("succcurlyeq", "succ.curly.eq", Relation),
+    ("nprec", "prec.not", Relation),
+    ("nsucc", "succ.not", Relation),
+    ("npreceq", "prec.curly.eq.not", Relation),
+    ("nsucceq", "succ.curly.eq.not", Relation),
+    ("precneqq", "prec.nequiv", Relation),
+    ("succneqq", "succ.nequiv", Relation),
+    ("precnsim", "prec.ntilde", Relation),
+    ("succnsim", "succ.ntilde", Relation),
+    ("precnapprox", "prec.napprox", Relation),
+    ("succnapprox", "succ.napprox", Relation),
+    ("approx", "approx", Relation),
+    ("approxeq", "approx.eq", Relation),
+    ("equiv", "equiv", Relation),
+    ("sim", "tilde", Relation),
+    ("simeq", "tilde.eq", Relation),
+    ("cong", "tilde.equiv", Relation),
+    ("ncong", "tilde.equiv.not", Relation),
+    ("nsim", "tilde.not", Relation),
+    ("backsim", "tilde.rev", Relation),
+    ("backsimeq", "tilde.eq.rev", Relation),
+    ("asymp", "asymp", Relation),
+    ("doteq", "≐", Relation),
+    ("triangleq", "eq.delta", Relation),
+    ("propto", "prop", Relation),
+    ("varpropto", "prop", Relation),
+    ("lhd", "lt.tri", Relation),
+    ("rhd", "gt.tri", Relation),
+    ("unlhd", "lt.tri.eq", Relation),
+    ("unrhd", "gt.tri.eq", Relation),
+    ("vartriangleleft", "lt.tri", Relation),
+    ("vartriangleright", "gt.tri", Relation),
+    ("trianglelefteq", "lt.tri.eq", Relation),
+    ("trianglerighteq", "gt.tri.eq", Relation),
+    ("ntriangleleft", "lt.tri.not", Relation),
+    ("ntriangleright", "gt.tri.not", Relation),
+    ("ntrianglelefteq", "lt.tri.eq.not", Relation),
+    ("ntrianglerighteq", "gt.tri.eq.not", Relation),
+    // Other relations
+    ("models", "models", Relation),
+    ("vdash", "tack.r", Relation),
+    ("dashv", "tack.l", Relation),
+    ("vDash", "tack.r.double", Relation),
+    ("Vdash", "forces", Relation),
+    ("nvdash", "tack.r.not", Relation),
+    ("nvDash", "tack.r.double.not", Relation),
+    ("nVdash", "forces.not", Relation),
+    ("perp", "perp", Relation),
+    ("parallel", "parallel", Relation),
+    ("nparallel", "parallel.not", Relation),
+    ("mid", "divides", Relation),
+    ("nmid", "divides.not", Relation),
+    ("smile", "smile", Relation),
+    ("frown", "frown", Relation),
+    ("bowtie", "join", Relation),
+    ("Join", "join.l.r", Relation),
+    ("multimap", "multimap", Relation),
+    // Set relations
+    ("in", "in", Relation),
+    ("notin", "in.not", Relation),
+    ("ni", "in.rev", Relation),
+    ("owns", "in.rev", Relation),
+    ("subset", "subset", Relation),
+    ("supset", "supset", Relation),
+    ("subseteq", "subset.eq", Relation),
+    ("supseteq", "supset.eq", Relation),
+    ("subsetneq", "subset.neq", Relation),
+    ("supsetneq", "supset.neq", Relation),
+    ("nsubseteq", "subset.eq.not", Relation),
+    ("nsupseteq", "supset.eq.not", Relation),
+    ("Subset", "subset.double", Relation),
+    ("Supset", "supset.double", Relation),
+    ("sqsubset", "subset.sq", Relation),
+    ("sqsupset", "supset.sq", Relation),
+    ("sqsubseteq", "subset.eq.sq", Relation),
+    ("sqsupseteq", "supset.eq.sq", Relation),
+    // mathtools colon relations
+    ("coloneqq", "colon.eq", Relation),
+    ("Coloneqq", "colon.double.eq", Relation),
+    ("eqqcolon", "eq.colon", Relation),
+    ("colon", "colon", Punctuation),
+    // Arrows
+    ("to", "->", Relation),
+    ("rightarrow", "->", Relation),
+    ("gets", "<-", Relation),
+    ("leftarrow", "<-", Relation),
+    ("leftrightarrow", "<->", Relation),
+    ("Rightarrow", "=>", Relation),
+    ("Leftarrow", "arrow.l.double", Relation),
+    ("Leftrightarrow", "<=>", Relation),
+    ("mapsto", "|->", Relation),
+    ("longmapsto", "arrow.r.long.bar", Relation),
+    ("longrightarrow", "arrow.r.long", Relation),
+    ("longleftarrow", "arrow.l.long", Relation),
+    ("longleftrightarrow", "arrow.l.r.long", Relation),
+    ("Longrightarrow", "arrow.r.double.long", Relation),
+    ("Longleftarrow", "arrow.l.double.long", Relation),
+    ("Longleftrightarrow", "arrow.l.r.double.long", Relation),
+    ("implies", "arrow.r.double.long", Relation),
+    ("impliedby", "arrow.l.double.long", Relation),
+    ("iff", "arrow.l.r.double.long", Relation),
+    ("uparrow", "arrow.t", Relation),
+    ("downarrow", "arrow.b", Relation),
+    ("updownarrow", "arrow.t.b", Relation),
+    ("Uparrow", "arrow.t.double", Relation),
+    ("Downarrow", "arrow.b.double", Relation),
+    ("Updownarrow", "arrow.t.b.double", Relation),
+    ("nearrow", "arrow.tr", Relation),
+    ("searrow", "arrow.br", Relation),
+    ("swarrow", "arrow.bl", Relation),
+    ("nwarrow", "arrow.tl", Relation),
+    ("hookrightarrow", "arrow.r.hook", Relation),
+    ("hookleftarrow", "arrow.l.hook", Relation),
+    ("twoheadrightarrow", "arrow.r.twohead", Relation),
+    ("twoheadleftarrow", "arrow.l.twohead", Relation),
+    ("rightarrowtail", "arrow.r.tail", Relation),
+    ("leftarrowtail", "arrow.l.tail", Relation),
+    ("looparrowright", "arrow.r.loop", Relation),
+    ("looparrowleft", "arrow.l.loop", Relation),
+    ("curvearrowright", "arrow.cw.half", Relation),
+    ("curvearrowleft", "arrow.ccw.half", Relation),
+    ("circlearrowright", "arrow.cw", Relation),
+    ("circlearrowleft", "arrow.ccw", Relation),
+    ("rightrightarrows", "arrows.rr", Relation),
+    ("leftleftarrows", "arrows.ll", Relation),
+    ("rightleftarrows", "arrows.rl", Relation),
+    ("leftrightarrows", "arrows.lr", Relation),
+    ("upuparrows", "arrows.tt", Relation),
+    ("downdownarrows", "arrows.bb", Relation),
+    ("Rrightarrow", "arrow.r.triple", Relation),
+    ("Lleftarrow", "arrow.l.triple", Relation),
+    ("rightsquigarrow", "arrow.r.squiggly", Relation),
+    ("leadsto", "arrow.r.squiggly", Relation),
+    ("leftrightsquigarrow", "arrow.l.r.wave", Relation),
+    ("dashrightarrow", "arrow.r.dashed", Relation),
+    ("dashleftarrow", "arrow.l.dashed", Relation),
+    ("nrightarrow", "arrow.r.not", Relation),
+    ("nleftarrow", "arrow.l.not", Relation),
+    ("nleftrightarrow", "arrow.l.r.not", Relation),
+    ("nRightarrow", "arrow.r.double.not", Relation),
+    ("nLeftarrow", "arrow.l.double.not", Relation),
+    ("nLeftrightarrow", "arrow.l.r.double.not", Relation),
+    // Harpoons
+    ("rightharpoonup", "harpoon.rt", Relation),
+    ("rightharpoondown", "harpoon.rb", Relation),
+    ("leftharpoonup", "harpoon.lt", Relation),
+    ("leftharpoondown", "harpoon.lb", Relation),
+    ("upharpoonleft", "harpoon.tl", Relation),
+    ("upharpoonright", "harpoon.tr", Relation),
+    ("downharpoonleft", "harpoon.bl", Relation),
+    ("downharpoonright", "harpoon.br", Relation),
+    ("rightleftharpoons", "harpoons.rtlb", Relation),
+    ("leftrightharpoons", "harpoons.ltrb", Relation),
+    // stmaryrd
+    ("mapsfrom", "arrow.l.bar", Relation),
+    ("longmapsfrom", "arrow.l.long.bar", Relation),
+    ("Mapsto", "arrow.r.double.bar", Relation),
+    ("Mapsfrom", "arrow.l.double.bar", Relation),
+    ("llbracket", "bracket.l.double", Opening),
+    ("rrbracket", "bracket.r.double", Closing),
+    ("Lbag", "bag.l", Opening),
+    ("Rbag", "bag.r", Closing),
+    ("oast", "ast.op.o", Binary),
+    ("obar", "bar.v.circle", Binary),
+    ("olessthan", "lt.circle", Binary),
+    ("ogreaterthan", "gt.circle", Binary),
+    ("boxast", "ast.square", Binary),
+    ("sslash", "slash.double", Binary),
+    ("interleave", "interleave", Binary),
+    ("bigsqcap", "inter.sq.big", Large),
+    ("biginterleave", "interleave.big", Large),
+    // Large operators
+    ("sum", "sum", Large),
+    ("prod", "product", Large),
+    ("coprod", "product.co", Large),
+    ("bigcup", "union.big", Large),
+    ("bigcap", "inter.big", Large),
+    ("bigsqcup", "union.sq.big", Large),
+    ("biguplus", "union.plus.big", Large),
+    ("bigoplus", "plus.o.big", Large),
+    ("bigotimes", "times.o.big", Large),
+    ("bigodot", "dot.o.big", Large),
+    ("bigwedge", "and.big", Large),
+    ("bigvee", "or.big", Large),
+    ("int", "integral", Large),
+    ("iint", "integral.double", Large),
+    ("iiint", "integral.triple", Large),
+    ("iiiint", "integral.quad", Large),
+    ("oint", "integral.cont", Large),
+    ("oiint", "integral.surf", Large),
+    ("oiiint", "integral.vol", Large),
+    // Dots
+    ("ldots", "...", Ordinary),
+    ("dots", "...", Ordinary),
+    ("dotsc", "...", Ordinary),
+    ("dotso", "...", Ordinary),
+    ("cdots", "dots.h.c", Ordinary),
+    ("dotsb", "dots.h.c", Ordinary),
+    ("dotsm", "dots.h.c", Ordinary),
+    ("dotsi", "dots.h.c", Ordinary),
+    ("vdots", "dots.v", Ordinary),
+    ("ddots", "dots.down", Ordinary),
+    ("iddots", "dots.up", Ordinary),
+    // Delimiters
+    ("langle", "angle.l", Opening),
+    ("rangle", "angle.r", Closing),
+    ("lfloor", "floor.l", Opening),
+    ("rfloor", "floor.r", Closing),
+    ("lceil", "ceil.l", Opening),
+    ("rceil", "ceil.r", Closing),
+    ("lvert", "|", Opening),
+    ("rvert", "|", Closing),
+    ("vert", "|", Ordinary),
+    ("lVert", "||", Opening),
+    ("rVert", "||", Closing),
+    ("Vert", "||", Ordinary),
+    ("lbrace", "brace.l", Opening),
+    ("rbrace", "brace.r", Closing),
+    ("lbrack", "bracket.l", Opening),
+    ("rbrack", "bracket.r", Closing),
+    ("ulcorner", "corner.l.t", Opening),
+    ("urcorner", "corner.r.t", Closing),
+    ("llcorner", "corner.l.b", Opening),
+    ("lrcorner", "corner.r.b", Closing),
+    ("lmoustache", "mustache.l", Opening),
+    ("rmoustache", "mustache.r", Closing),
+    ("backslash", "backslash", Ordinary),
+    // Geometry and miscellaneous
+    ("angle", "angle", Ordinary),
+    ("measuredangle", "angle.arc", Ordinary),
+    ("sphericalangle", "angle.spheric", Ordinary),
+    ("prime", "prime", Ordinary),
+    ("backprime", "prime.rev", Ordinary),
+    ("degree", "degree", Ordinary),
+    ("diameter", "diameter", Ordinary),
+    ("square", "square.stroked", Ordinary),
+    ("Box", "square.stroked", Ordinary),
+    ("blacksquare", "square.filled", Ordinary),
+    ("triangle", "triangle.stroked.t", Ordinary),
+    ("vartriangle", "triangle.stroked.small.t", Relation),
+    ("blacktriangle", "triangle.filled.small.t", Ordinary),
+    ("triangledown", "triangle.stroked.small.b", Ordinary),
+    ("blacktriangledown", "triangle.filled.small.b", Ordinary),
+    ("blacktriangleleft", "triangle.filled.small.l", Relation),
+    ("blacktriangleright", "triangle.filled.small.r", Relation),
+    ("lozenge", "lozenge.stroked", Ordinary),
+    ("blacklozenge", "lozenge.filled", Ordinary),
+    ("Diamond", "diamond.stroked", Ordinary),
+    ("bigcirc", "circle.stroked.big", Binary),
+    ("bigstar", "star.filled", Ordinary),
+    ("clubsuit", "suit.club.stroked", Ordinary),
+    ("diamondsuit", "suit.diamond.stroked", Ordinary),
+    ("heartsuit", "suit.heart.stroked", Ordinary),
+    ("spadesuit", "suit.spade.filled", Ordinary),
+    ("flat", "flat", Ordinary),
+    ("natural", "natural", Ordinary),
+    ("sharp", "sharp", Ordinary),
+    ("checkmark", "checkmark", Ordinary),
+    ("maltese", "maltese", Ordinary),
+    ("S", "section", Ordinary),
+    ("P", "pilcrow", Ordinary),
+    ("copyright", "copyright", Ordinary),
+    ("pounds", "pound", Ordinary),
+    ("yen", "yen", Ordinary),
+    ("euro", "euro", Ordinary),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const REFERENCE: &str = include_str!("../../../reference/symbols/sym.md");
+
+    /// Typst shorthands used in the table, with the symbol they stand for
+    const SHORTHANDS: &[(&str, &str)] = &[
+        ("<=", "lt.eq"),
+        (">=", "gt.eq"),
+        ("!=", "eq.not"),
+        ("->", "arrow.r"),
+        ("<-", "arrow.l"),
+        ("<->", "arrow.l.r"),
+        ("=>", "arrow.r.double"),
+        ("<=>", "arrow.l.r.double"),
+        ("|->", "arrow.r.bar"),
+        ("...", "dots.h"),
+        ("|", "bar.v"),
+        ("||", "bar.v.double"),
+    ];
+
+    /// Symbol names listed in the second column of the reference tables
+    fn reference_names() -> HashSet<&'static str> {
+        REFERENCE
+            .lines()
+            .filter(|line| line.starts_with("| "))
+            .filter_map(|line| line.split(" | ").nth(1))
+            .map(str::trim)
+            .filter(|name| !name.starts_with('-') && *name != "Name")
+            .collect()
+    }
+
+    /// A name is valid if listed, or if it is a base symbol with listed variants
+    fn is_reference_name(names: &HashSet<&str>, name: &str) -> bool {
+        names.contains(name)
+            || names.iter().any(|n| {
+                n.strip_prefix(name)
+                    .is_some_and(|rest| rest.starts_with('.'))
+            })
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup("alpha"), Some("alpha"));
+        assert_eq!(lookup("oplus"), Some("plus.o"));
+        assert_eq!(lookup("hookrightarrow"), Some("arrow.r.hook"));
+        assert_eq!(lookup("notacommand"), None);
+    }
+
+    #[test]
+    fn test_class() {
+        assert_eq!(class("alpha"), Some(Ordinary));
+        assert_eq!(class("cdot"), Some(Binary));
+        assert_eq!(class("leq"), Some(Relation));
+        assert_eq!(class("to"), Some(Relation));
+        assert_eq!(class("colon"), Some(Punctuation));
+        assert_eq!(class("langle"), Some(Opening));
+        assert_eq!(typst_class("union"), Some(Binary));
+        assert_eq!(typst_class("in"), Some(Relation));
+        assert_eq!(typst_class("eq.not"), Some(Relation));
+        assert_eq!(typst_class("|"), None);
+        assert_eq!(class("rrbracket"), Some(Closing));
+        assert_eq!(class("bigcup"), Some(Large));
+        assert_eq!(class("notacommand"), None);
+    }
+
+    #[test]
+    fn test_negated() {
+        assert_eq!(negated("in"), Some("in.not".to_string()));
+        assert_eq!(negated(shorthand_name('=')), Some("eq.not".to_string()));
+        assert_eq!(negated(negatable_name("<=")), Some("lt.eq.not".to_string()));
+        assert_eq!(negated("perp"), None);
+    }
+
+    #[test]
+    fn test_no_duplicate_commands() {
+        let mut seen = HashSet::new();
+        for (latex, _, _) in SYMBOLS {
+            assert!(seen.insert(latex), "duplicate entry for \\{}", latex);
+        }
+    }
+
+    #[test]
+    fn test_shorthands_exist_in_reference() {
+        let names = reference_names();
+        for (shorthand, name) in SHORTHANDS {
+            assert!(
+                names.contains(name),
+                "shorthand {} stands for unknown symbol {}",
+                shorthand,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_all_symbols_exist_in_reference() {
+        let names = reference_names();
+        for (latex, typst, _) in SYMBOLS {
+            let is_shorthand = SHORTHANDS.iter().any(|(s, _)| s == typst);
+            let is_literal = typst.chars().count() == 1 && !typst.is_ascii();
+            assert!(
+                is_shorthand || is_literal || is_reference_name(&names, typst),
+                "\\{} maps to {}, which is not in the symbol reference",
+                latex,
+                typst
+            );
+        }
+    }
+}
@@ -1,28 +1,421 @@
 //! Markdown parser using pulldown-cmark
 
+use std::borrow::Cow;
+use std::ops::Range;
+
 use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
 
+use super::latex::math::OperatorDeclarations;
 use crate::ast::document::{Block, Document, Inline, ListKind, Style};
 use crate::error::Result;
 
+/// Delimiters that mark math in Markdown text
+///
+/// Bracket delimiters are off by default, since `\(` and `\[` are also plain
+/// escaped punctuation in CommonMark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MathDelimiters {
+    /// Only `$...$` and `$$...$$`
+    #[default]
+    Dollars,
+    /// Also `\(...\)` and `\[...\]`, as written by MathJax and Jupyter
+    DollarsAndBrackets,
+}
+
 /// Parse Markdown text into a Document AST
 pub fn parse(input: &str) -> Result<Document> {
+    parse_with_delimiters(input, MathDelimiters::default())
+}
+
+/// Parse Markdown text into a Document AST, recognizing the given math delimiters
+pub fn parse_with_delimiters(input: &str, delimiters: MathDelimiters) -> Result<Document> {
     // Enable math parsing in pulldown-cmark
     let mut options = Options::empty();
     options.insert(Options::ENABLE_MATH);
     options.insert(Options::ENABLE_STRIKETHROUGH);
 
-    let parser = Parser::new_ext(input, options);
-    let mut converter = MarkdownConverter::new();
+    // Front matter counts only at the very start; pulldown-cmark would take any `---` pair
+    let (metadata, input) = match super::front_matter::split(input) {
+        Some((metadata, rest)) => (Some(metadata), rest),
+        None => (None, input),
+    };
+
+    let (operators, input) = OperatorDeclarations::extract(input, &code_ranges(input));
+    let input = match delimiters {
+        MathDelimiters::Dollars => input,
+        MathDelimiters::DollarsAndBrackets => match replace_bracket_delimiters(&input) {
+            Cow::Borrowed(_) => input,
+            Cow::Owned(replaced) => Cow::Owned(replaced),
+        },
+    };
+    let input = wrap_display_environments(&input);
+    let parser = Parser::new_ext(&input, options);
+    let mut converter = MarkdownConverter::new(operators);
     converter.process_events(parser)?;
-    Ok(converter.into_document())
+    let mut document = converter.into_document();
+    if let Some(metadata) = metadata {
+        document.metadata = metadata;
+    }
+    Ok(document)
+}
+
+/// Byte ranges of code in Markdown text: fenced and indented code blocks and code spans
+fn code_ranges(input: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut fence: Option<(&str, usize)> = None;
+    let mut indented: Option<usize> = None;
+    let mut prev_blank = true;
+    let mut in_list = false;
+    let mut text_start = 0;
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim_start();
+        if let Some((marker, fence_start)) = fence {
+            if trimmed.starts_with(marker) {
+                ranges.push(fence_start..offset);
+                fence = None;
+                text_start = offset;
+            }
+            continue;
+        }
+
+        let blank = trimmed.is_empty();
+        let indent = line[..line.len() - trimmed.len()]
+            .chars()
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum::<usize>();
+        // Indented lines start a code block after a blank line, unless they continue a list
+        if !blank && indent >= 4 && (indented.is_some() || (prev_blank && !in_list)) {
+            if indented.is_none() {
+                code_span_ranges(input, text_start..start, &mut ranges);
+                indented = Some(start);
+            }
+            prev_blank = false;
+            continue;
+        }
+        if blank {
+            prev_blank = true;
+            continue;
+        }
+        if let Some(code_start) = indented.take() {
+            ranges.push(code_start..start);
+            text_start = start;
+        }
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            code_span_ranges(input, text_start..start, &mut ranges);
+            fence = Some((&trimmed[..3], start));
+        } else if indent == 0 {
+            let marker = trimmed.trim_start_matches(|c: char| c.is_ascii_digit());
+            let is_item = ["- ", "* ", "+ "].iter().any(|m| trimmed.starts_with(m))
+                || (marker.len() < trimmed.len()
+                    && (marker.starts_with(". ") || marker.starts_with(") ")));
+            in_list = is_item || (in_list && !prev_blank);
+        }
+        prev_blank = false;
+    }
+    match (fence, indented) {
+        (Some((_, code_start)), _) | (None, Some(code_start)) => {
+            code_span_ranges(input, text_start..code_start, &mut ranges);
+            ranges.push(code_start..input.len());
+        }
+        (None, None) => code_span_ranges(input, text_start..input.len(), &mut ranges),
+    }
+    ranges.sort_by_key(|range| range.start);
+    ranges
+}
+
+/// Add the byte ranges of backtick code spans within `text` of the input
+fn code_span_ranges(input: &str, text: Range<usize>, ranges: &mut Vec<Range<usize>>) {
+    let mut i = text.start;
+    while let Some(n) = input[i..text.end].find('`') {
+        let start = i + n;
+        // Backslash-escaped backticks do not open a code span
+        if input[..start].ends_with('\\') {
+            i = start + 1;
+            continue;
+        }
+        let run =
+            input[start..text.end].len() - input[start..text.end].trim_start_matches('`').len();
+        let ticks = &input[start..start + run];
+        i = start + run;
+        let mut search = i;
+        while let Some(n) = input[search..text.end].find(ticks) {
+            let close = search + n;
+            let len =
+                input[close..text.end].len() - input[close..text.end].trim_start_matches('`').len();
+            if len == run {
+                ranges.push(start..close + run);
+                i = close + run;
+                break;
+            }
+            search = close + len;
+        }
+    }
+}
+
+/// Rewrite `\(...\)` as `$...$` and `\[...\]` as `$$...$$` before pulldown-cmark reads
+/// the backslashes as escapes. Code and link destinations are copied unchanged.
+fn replace_bracket_delimiters(input: &str) -> Cow<'_, str> {
+    if !input.contains("\\(") && !input.contains("\\[") {
+        return Cow::Borrowed(input);
+    }
+
+    let code = code_ranges(input);
+    let bytes = input.as_bytes();
+    let mut output = String::with_capacity(input.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(range) = code.iter().find(|range| range.contains(&i)) {
+            i = range.end;
+            continue;
+        }
+        // Link reference definitions hold a destination too
+        if (i == 0 || bytes[i - 1] == b'\n') && is_link_definition(&input[i..]) {
+            i = input[i..].find('\n').map_or(input.len(), |n| i + n + 1);
+            continue;
+        }
+
+        match bytes[i] {
+            b']' if bytes.get(i + 1) == Some(&b'(') => i = link_destination_end(input, i + 1),
+            b'\\' if matches!(bytes.get(i + 1), Some(b'(') | Some(b'[')) => {
+                let display = bytes[i + 1] == b'[';
+                let closing = if display { b']' } else { b')' };
+                match find_bracket_end(&input[i + 2..], closing) {
+                    Some(len) => {
+                        let math = input[i + 2..i + 2 + len].trim();
+                        output.push_str(&input[copied..i]);
+                        let dollars = if display { "$$" } else { "$" };
+                        output.push_str(dollars);
+                        output.push_str(math);
+                        output.push_str(dollars);
+                        i += len + 4;
+                        copied = i;
+                    }
+                    None => i += 2,
+                }
+            }
+            // Keep escaped characters, including `\\(`, as they are
+            b'\\' => i += 2,
+            _ => i += 1,
+        }
+    }
+    if copied == 0 {
+        return Cow::Borrowed(input);
+    }
+    output.push_str(&input[copied..]);
+    Cow::Owned(output)
+}
+
+/// Check whether a line is a link reference definition, like `[id]: http://example.com`
+fn is_link_definition(line: &str) -> bool {
+    let line = line.trim_start_matches(' ');
+    line.starts_with('[')
+        && line
+            .find("]:")
+            .is_some_and(|end| !line[..end].contains(['\n', ']']))
+}
+
+/// Find the end of a link destination in parentheses starting at `open`, where nested
+/// parentheses balance and escaped ones do not count
+fn link_destination_end(input: &str, open: usize) -> usize {
+    let bytes = input.as_bytes();
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            b'\n' => break,
+            _ => {}
+        }
+        i += 1;
+    }
+    // Not a link after all
+    open
+}
+
+/// Find the length of math content up to a `\)` or `\]` closing delimiter. Content that
+/// spans a blank line or contains a dollar sign is left for Markdown.
+fn find_bracket_end(input: &str, closing: u8) -> Option<usize> {
+    let bytes = input.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if bytes.get(i + 1) == Some(&closing) => return Some(i),
+            b'\\' => i += 2,
+            b'$' => return None,
+            b'\n'
+                if input[i + 1..]
+                    .trim_start_matches([' ', '\t'])
+                    .starts_with('\n') =>
+            {
+                return None
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// LaTeX environments that MathJax accepts outside of math delimiters
+const DISPLAY_ENVIRONMENTS: &[&str] = &[
+    "equation",
+    "align",
+    "gather",
+    "multline",
+    "flalign",
+    "alignat",
+    "eqnarray",
+    "displaymath",
+];
+
+/// Wrap LaTeX display environments written directly in the text, as MathJax allows, in
+/// `$$` so that their content reaches the math parser without Markdown escapes applied.
+/// Environments in code, in existing display math or spanning a blank line stay as they are.
+fn wrap_display_environments(input: &str) -> Cow<'_, str> {
+    if !input.contains("\\begin{") {
+        return Cow::Borrowed(input);
+    }
+
+    let code = code_ranges(input);
+    let mut output = String::with_capacity(input.len());
+    let mut in_display_math = false;
+    let mut offset = 0;
+    while offset < input.len() {
+        let line_end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |n| offset + n + 1);
+        let line = &input[offset..line_end];
+        let trimmed = line.trim_start();
+        let in_code = code.iter().any(|range| range.contains(&offset));
+        if !in_code && trimmed.matches("$$").count() % 2 == 1 {
+            in_display_math = !in_display_math;
+        } else if !in_code && !in_display_math {
+            if let Some(env_end) = display_environment_end(&input[offset..]) {
+                let env_end = offset + env_end;
+                output.push_str("$$\n");
+                output.push_str(input[offset..env_end].trim_start());
+                output.push_str("\n$$\n");
+                // Text after `\end{...}` on the same line stays prose
+                let rest_end = input[env_end..]
+                    .find('\n')
+                    .map_or(input.len(), |n| env_end + n + 1);
+                let rest = input[env_end..rest_end].trim_start_matches([' ', '\t']);
+                if !rest.trim().is_empty() {
+                    output.push_str(rest);
+                }
+                offset = rest_end;
+                continue;
+            }
+        }
+        output.push_str(line);
+        offset = line_end;
+    }
+    Cow::Owned(output)
+}
+
+/// Find the end of a display environment that starts the (indented) input, if it closes
+/// before any blank line
+fn display_environment_end(input: &str) -> Option<usize> {
+    let indent = input.len() - input.trim_start_matches([' ', '\t']).len();
+    let name = input[indent..]
+        .strip_prefix("\\begin{")
+        .and_then(|rest| rest.split_once('}'))
+        .map(|(name, _)| name)
+        .filter(|name| DISPLAY_ENVIRONMENTS.contains(&name.trim_end_matches('*')))?;
+    let end = format!("\\end{{{}}}", name);
+    let end = input.find(&end)? + end.len();
+    let body = &input[..end];
+    let blank_line = body.split('\n').skip(1).any(|line| line.trim().is_empty());
+    (!blank_line).then_some(end)
+}
+
+/// The text of inline elements, without formatting
+fn plain_text(content: &[Inline]) -> String {
+    let mut text = String::new();
+    for inline in content {
+        match inline {
+            Inline::Text(t) | Inline::Code(t) => text.push_str(t),
+            Inline::Formatted { content, .. } | Inline::Link { text: content, .. } => {
+                text.push_str(&plain_text(content));
+            }
+            Inline::Image { alt, .. } => text.push_str(alt),
+            Inline::LineBreak => text.push(' '),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Point references to label aliases at the labels they stand for
+fn resolve_aliases(blocks: &mut [Block], aliases: &[(String, String)]) {
+    fn resolve_inlines(inlines: &mut [Inline], aliases: &[(String, String)]) {
+        for inline in inlines {
+            match inline {
+                Inline::Ref(label) => {
+                    if let Some((_, target)) = aliases.iter().find(|(alias, _)| alias == label) {
+                        *label = target.clone();
+                    }
+                }
+                Inline::Formatted { content, .. } | Inline::Link { text: content, .. } => {
+                    resolve_inlines(content, aliases);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for block in blocks {
+        match block {
+            Block::Heading { content, .. } | Block::Paragraph(content) => {
+                resolve_inlines(content, aliases);
+            }
+            Block::List { items, .. } => {
+                for item in items {
+                    resolve_aliases(item, aliases);
+                }
+            }
+            Block::Quote(blocks) => resolve_aliases(blocks, aliases),
+            Block::Figure {
+                content, caption, ..
+            } => {
+                resolve_aliases(content, aliases);
+                if let Some(caption) = caption {
+                    resolve_inlines(caption, aliases);
+                }
+            }
+            Block::Table { rows } => {
+                for cell in rows.iter_mut().flatten() {
+                    resolve_inlines(&mut cell.content, aliases);
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
 /// Converter from pulldown-cmark events to our AST
 struct MarkdownConverter {
     document: Document,
-    current_block: Option<BlockBuilder>,
+    /// Operators declared in the document, applied to each math span
+    operators: OperatorDeclarations,
+    /// Blocks being built, innermost last: list items and quotes hold the blocks above them
+    block_stack: Vec<BlockBuilder>,
     inline_stack: Vec<InlineBuilder>,
+    /// Title of the last image, which becomes the caption if the image stands alone
+    image_title: Option<String>,
+    /// Further labels of display math, with the label of the block they belong to
+    label_aliases: Vec<(String, String)>,
 }
 
 /// Helper for building blocks
@@ -46,16 +439,30 @@ enum BlockBuilder {
 
 /// Helper for building inline elements
 enum InlineBuilder {
-    Formatted { style: Style, content: Vec<Inline> },
-    Link { url: String, text: Vec<Inline> },
+    Formatted {
+        style: Style,
+        content: Vec<Inline>,
+    },
+    Link {
+        url: String,
+        text: Vec<Inline>,
+    },
+    Image {
+        path: String,
+        title: String,
+        alt: Vec<Inline>,
+    },
 }
 
 impl MarkdownConverter {
-    fn new() -> Self {
+    fn new(operators: OperatorDeclarations) -> Self {
         Self {
             document: Document::new(),
-            current_block: None,
+            operators,
+            block_stack: Vec::new(),
             inline_stack: Vec::new(),
+            image_title: None,
+            label_aliases: Vec::new(),
         }
     }
 
@@ -64,8 +471,10 @@ impl MarkdownConverter {
             self.process_event(event)?;
         }
 
-        // Finalize any remaining block
-        self.finalize_current_block();
+        // Finalize any remaining blocks
+        while !self.block_stack.is_empty() {
+            self.finalize_current_block();
+        }
 
         Ok(())
     }
@@ -76,7 +485,7 @@ impl MarkdownConverter {
             Event::End(tag_end) => self.handle_end_tag(tag_end)?,
             Event::Text(text) => self.handle_text(text.as_ref()),
             Event::Code(code) => self.handle_inline_code(code.as_ref()),
-            Event::SoftBreak => self.handle_text(" "),
+            Event::SoftBreak => self.handle_soft_break(),
             Event::HardBreak => self.add_inline(Inline::LineBreak),
             Event::Rule => self.add_block(Block::HorizontalRule),
             Event::Html(_) | Event::InlineHtml(_) => {
@@ -87,30 +496,52 @@ impl MarkdownConverter {
             }
             Event::InlineMath(math) => {
                 // Parse LaTeX math expression
-                match super::latex::math::parse(math.as_ref()) {
+                if let Some(label) = super::latex::math::equation_reference(&math) {
+                    self.add_inline(Inline::Ref(label));
+                    return Ok(());
+                }
+                match super::latex::math::parse_with_operators(math.as_ref(), &self.operators) {
                     Ok(expr) => self.add_inline(Inline::MathInline(expr)),
-                    Err(_) => {
-                        // Fallback: preserve as text if parsing fails
-                        self.add_inline(Inline::Text(format!("${math}$")));
-                    }
+                    Err(_) => self.add_inline(Inline::RawMath(math.to_string())),
                 }
             }
             Event::DisplayMath(math) => {
                 // For display math, finalize current block and add math block
-                self.finalize_current_block();
-                match super::latex::math::parse(math.as_ref()) {
+                let in_paragraph =
+                    matches!(self.block_stack.last(), Some(BlockBuilder::Paragraph(_)));
+                if let Some(BlockBuilder::Paragraph(content)) = self.block_stack.last_mut() {
+                    // The line break before the math does not belong to the text
+                    if matches!(content.last(), Some(Inline::Text(t)) if t == " ") {
+                        content.pop();
+                    }
+                }
+                if in_paragraph {
+                    self.finalize_current_block();
+                }
+                match super::latex::math::parse_with_operators(math.as_ref(), &self.operators) {
                     Ok(expr) => {
+                        let tag = super::latex::math::equation_tag(&math);
+                        let mut labels = super::latex::math::equation_labels(&math).into_iter();
+                        let label = labels.next();
+                        // Typst gives the block one number, so the labels of its other
+                        // lines name that number too
+                        if let Some(label) = &label {
+                            self.label_aliases
+                                .extend(labels.map(|alias| (alias, label.clone())));
+                        }
                         self.add_block(Block::MathBlock {
                             expr,
-                            numbered: false,
+                            numbered: tag.is_some()
+                                || super::latex::math::is_numbered_environment(&math),
+                            label,
+                            tag,
                         });
                     }
-                    Err(_) => {
-                        // Fallback: preserve as text
-                        self.add_block(Block::Paragraph(vec![Inline::Text(format!(
-                            "$$ {math} $$"
-                        ))]));
-                    }
+                    Err(_) => self.add_block(Block::RawMathBlock(math.to_string())),
+                }
+                // Text after the math continues the paragraph it interrupted
+                if in_paragraph {
+                    self.block_stack.push(BlockBuilder::Paragraph(Vec::new()));
                 }
             }
         }
@@ -120,7 +551,7 @@ impl MarkdownConverter {
     fn handle_start_tag(&mut self, tag: Tag) -> Result<()> {
         match tag {
             Tag::Paragraph => {
-                self.current_block = Some(BlockBuilder::Paragraph(Vec::new()));
+                self.block_stack.push(BlockBuilder::Paragraph(Vec::new()));
             }
             Tag::Heading { level, .. } => {
                 let level_num = match level {
@@ -131,7 +562,7 @@ impl MarkdownConverter {
                     HeadingLevel::H5 => 5,
                     HeadingLevel::H6 => 6,
                 };
-                self.current_block = Some(BlockBuilder::Heading {
+                self.block_stack.push(BlockBuilder::Heading {
                     level: level_num,
                     content: Vec::new(),
                 });
@@ -147,7 +578,7 @@ impl MarkdownConverter {
                     }
                     CodeBlockKind::Indented => None,
                 };
-                self.current_block = Some(BlockBuilder::CodeBlock {
+                self.block_stack.push(BlockBuilder::CodeBlock {
                     lang,
                     code: String::new(),
                 });
@@ -158,19 +589,19 @@ impl MarkdownConverter {
                 } else {
                     ListKind::Unordered
                 };
-                self.current_block = Some(BlockBuilder::List {
+                self.block_stack.push(BlockBuilder::List {
                     kind,
                     items: Vec::new(),
                     current_item: None,
                 });
             }
             Tag::Item => {
-                if let Some(BlockBuilder::List { current_item, .. }) = &mut self.current_block {
+                if let Some(BlockBuilder::List { current_item, .. }) = self.block_stack.last_mut() {
                     *current_item = Some(Vec::new());
                 }
             }
             Tag::BlockQuote(_) => {
-                self.current_block = Some(BlockBuilder::Quote(Vec::new()));
+                self.block_stack.push(BlockBuilder::Quote(Vec::new()));
             }
             Tag::Strong => {
                 self.inline_stack.push(InlineBuilder::Formatted {
@@ -196,6 +627,15 @@ impl MarkdownConverter {
                     text: Vec::new(),
                 });
             }
+            Tag::Image {
+                dest_url, title, ..
+            } => {
+                self.inline_stack.push(InlineBuilder::Image {
+                    path: dest_url.to_string(),
+                    title: title.to_string(),
+                    alt: Vec::new(),
+                });
+            }
             // Skip other tags for now
             _ => {}
         }
@@ -219,7 +659,7 @@ impl MarkdownConverter {
                     items,
                     current_item,
                     ..
-                }) = &mut self.current_block
+                }) = self.block_stack.last_mut()
                 {
                     if let Some(item_blocks) = current_item.take() {
                         items.push(item_blocks);
@@ -232,7 +672,7 @@ impl MarkdownConverter {
                     items,
                     current_item,
                     ..
-                }) = &mut self.current_block
+                }) = self.block_stack.last_mut()
                 {
                     if let Some(item_blocks) = current_item.take() {
                         items.push(item_blocks);
@@ -252,13 +692,61 @@ impl MarkdownConverter {
                     self.add_inline(Inline::Link { text, url });
                 }
             }
+            TagEnd::Image => {
+                if let Some(InlineBuilder::Image { path, title, alt }) = self.inline_stack.pop() {
+                    self.image_title = Some(title).filter(|title| !title.is_empty());
+                    self.add_inline(Inline::Image {
+                        path,
+                        alt: plain_text(&alt),
+                        width: None,
+                        height: None,
+                    });
+                }
+            }
             _ => {}
         }
         Ok(())
     }
 
-    fn handle_text(&mut self, text: &str) {
-        self.add_inline(Inline::Text(text.to_string()));
+    fn handle_text(&mut self, mut text: &str) {
+        // MathJax also resolves `\ref` and `\eqref` written in the text, but not in code
+        if matches!(
+            self.block_stack.last(),
+            Some(BlockBuilder::CodeBlock { .. })
+        ) {
+            self.add_inline(Inline::Text(text.to_string()));
+            return;
+        }
+        loop {
+            let command = ["\\ref{", "\\eqref{"]
+                .iter()
+                .filter_map(|command| text.find(command).map(|start| (start, command.len())))
+                .min();
+            let Some((start, len)) = command else {
+                break;
+            };
+            let Some(end) = text[start..].find('}') else {
+                break;
+            };
+            if start > 0 {
+                self.add_inline(Inline::Text(text[..start].to_string()));
+            }
+            let label = text[start + len..start + end].trim();
+            self.add_inline(Inline::Ref(label.to_string()));
+            text = &text[start + end + 1..];
+        }
+        if !text.is_empty() {
+            self.add_inline(Inline::Text(text.to_string()));
+        }
+    }
+
+    fn handle_soft_break(&mut self) {
+        // A paragraph resumed after display math does not start with the line break
+        let resumed = self.inline_stack.is_empty()
+            && matches!(self.block_stack.last(), Some(BlockBuilder::Paragraph(c)) if c.is_empty());
+        if !resumed {
+            self.handle_text(" ");
+        }
     }
 
     fn handle_inline_code(&mut self, code: &str) {
@@ -272,11 +760,11 @@ impl MarkdownConverter {
                 InlineBuilder::Formatted { content, .. } => {
                     content.push(inline);
                 }
-                InlineBuilder::Link { text, .. } => {
+                InlineBuilder::Link { text, .. } | InlineBuilder::Image { alt: text, .. } => {
                     text.push(inline);
                 }
             }
-        } else if let Some(block) = &mut self.current_block {
+        } else if let Some(block) = self.block_stack.last_mut() {
             match block {
                 BlockBuilder::Heading { content, .. } => {
                     content.push(inline);
@@ -315,19 +803,37 @@ impl MarkdownConverter {
     }
 
     fn add_block(&mut self, block: Block) {
-        self.document.content.push(block);
+        // Blocks inside a list item or quote belong to it
+        match self.block_stack.last_mut() {
+            Some(BlockBuilder::List {
+                current_item: Some(item_blocks),
+                ..
+            }) => item_blocks.push(block),
+            Some(BlockBuilder::Quote(blocks)) => blocks.push(block),
+            _ => self.document.content.push(block),
+        }
     }
 
     fn finalize_current_block(&mut self) {
-        if let Some(builder) = self.current_block.take() {
+        if let Some(builder) = self.block_stack.pop() {
             let block = match builder {
                 BlockBuilder::Heading { level, content } => Block::Heading { level, content },
                 BlockBuilder::Paragraph(content) => {
-                    if !content.is_empty() {
-                        Block::Paragraph(content)
-                    } else {
+                    let image_title = self.image_title.take();
+                    if content.is_empty() {
                         return; // Skip empty paragraphs
                     }
+                    match image_title {
+                        // An image with a title on its own becomes a figure
+                        Some(title) if matches!(content.as_slice(), [Inline::Image { .. }]) => {
+                            Block::Figure {
+                                content: vec![Block::Paragraph(content)],
+                                caption: Some(vec![Inline::Text(title)]),
+                                label: None,
+                            }
+                        }
+                        _ => Block::Paragraph(content),
+                    }
                 }
                 BlockBuilder::CodeBlock { lang, code } => Block::CodeBlock { lang, code },
                 BlockBuilder::List { kind, items, .. } => Block::List { kind, items },
@@ -337,7 +843,10 @@ impl MarkdownConverter {
         }
     }
 
-    fn into_document(self) -> Document {
+    fn into_document(mut self) -> Document {
+        if !self.label_aliases.is_empty() {
+            resolve_aliases(&mut self.document.content, &self.label_aliases);
+        }
         self.document
     }
 }
@@ -461,4 +970,168 @@ mod tests {
             _ => panic!("Expected paragraph"),
         }
     }
+
+    #[test]
+    fn test_parse_display_environment() {
+        let input = "Text\n\\begin{align}\na &= b \\\\\nc &= d\n\\end{align}\nAfter";
+        let doc = parse(input).unwrap();
+
+        assert_eq!(doc.content.len(), 3);
+        match &doc.content[1] {
+            Block::MathBlock { numbered, .. } => assert!(numbered),
+            other => panic!("Expected math block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_wrap_display_environments() {
+        let input = "\\begin{equation*}x\\end{equation*}\n";
+        assert_eq!(
+            wrap_display_environments(input),
+            "$$\n\\begin{equation*}x\\end{equation*}\n$$\n"
+        );
+        let fenced = "```\n\\begin{align}\nx\n\\end{align}\n```\n";
+        assert_eq!(wrap_display_environments(fenced), fenced);
+        let delimited = "$$\n\\begin{align}\nx\n\\end{align}\n$$\n";
+        assert_eq!(wrap_display_environments(delimited), delimited);
+        let indented = "Code:\n\n    \\begin{align}\n    x\n    \\end{align}\n";
+        assert_eq!(wrap_display_environments(indented), indented);
+        let blank = "\\begin{align}\nx\n\n\\end{align}\n";
+        assert_eq!(wrap_display_environments(blank), blank);
+        assert_eq!(
+            wrap_display_environments("\\begin{align}\nx\n\\end{align} and text\nmore\n"),
+            "$$\n\\begin{align}\nx\n\\end{align}\n$$\nand text\nmore\n"
+        );
+    }
+
+    #[test]
+    fn test_code_ranges() {
+        let input = "Text `a` and ``b`c``.\n\n    indented\n\n- item\n\n    continued\n\n```\nfenced\n```\nend";
+        let code: Vec<&str> = code_ranges(input).into_iter().map(|r| &input[r]).collect();
+        assert_eq!(
+            code,
+            ["`a`", "``b`c``", "    indented\n\n", "```\nfenced\n```\n"]
+        );
+    }
+
+    #[test]
+    fn test_replace_bracket_delimiters() {
+        assert_eq!(
+            replace_bracket_delimiters(r"a \( x \) b \[y\]"),
+            "a $x$ b $$y$$"
+        );
+        assert_eq!(
+            replace_bracket_delimiters("\\[\n\\frac{a}{b} \\\\ c\n\\]\n"),
+            "$$\\frac{a}{b} \\\\ c$$\n"
+        );
+        // Code, escapes and unclosed delimiters are left alone
+        for input in [
+            r"`\(x\)` and ``a \[y\] b``",
+            "```\n\\(x\\)\n```\n",
+            r"\\(x\\)",
+            "\\(x\n\ny\\)",
+            r"\(a $b$\)",
+            "Code:\n\n    \\(x\\)\n",
+            r"[link](http://a.b/\(x\)) and ![image](a\(b\).png)",
+            "[id]: http://a.b/\\(x\\)\n",
+        ] {
+            assert_eq!(replace_bracket_delimiters(input), input);
+        }
+    }
+
+    #[test]
+    fn test_parse_bracket_math() {
+        let doc =
+            parse_with_delimiters(r"Let \(x\) be", MathDelimiters::DollarsAndBrackets).unwrap();
+        match &doc.content[0] {
+            Block::Paragraph(content) => {
+                assert!(matches!(content[1], Inline::MathInline(_)));
+            }
+            _ => panic!("Expected paragraph"),
+        }
+
+        let doc = parse(r"Let \(x\) be").unwrap();
+        match &doc.content[0] {
+            Block::Paragraph(content) => {
+                assert!(!content.iter().any(|i| matches!(i, Inline::MathInline(_))));
+            }
+            _ => panic!("Expected paragraph"),
+        }
+    }
+
+    #[test]
+    fn test_parse_image() {
+        let doc = parse("See ![an *icon*](icon.png) here").unwrap();
+        match &doc.content[0] {
+            Block::Paragraph(content) => {
+                assert_eq!(
+                    content[1],
+                    Inline::Image {
+                        path: "icon.png".to_string(),
+                        alt: "an icon".to_string(),
+                        width: None,
+                        height: None,
+                    }
+                );
+            }
+            _ => panic!("Expected paragraph"),
+        }
+
+        let doc = parse(r#"![Plot](plot.png "Results")"#).unwrap();
+        match &doc.content[0] {
+            Block::Figure {
+                content, caption, ..
+            } => {
+                assert_eq!(content.len(), 1);
+                assert_eq!(caption, &Some(vec![Inline::Text("Results".to_string())]));
+            }
+            other => panic!("Expected figure, got {:?}", other),
+        }
+
+        // A titled image inside text stays inline
+        let doc = parse(r#"Text ![Plot](plot.png "Results")"#).unwrap();
+        assert!(matches!(&doc.content[0], Block::Paragraph(_)));
+    }
+
+    #[test]
+    fn test_parse_nested_list() {
+        let input =
+            "- One\n  - One.a\n    1. Deep\n- Two\n\n  More about two.\n\n  ```\n  code\n  ```\n";
+        let doc = parse(input).unwrap();
+        assert_eq!(doc.content.len(), 1);
+
+        let Block::List { items, .. } = &doc.content[0] else {
+            panic!("Expected list");
+        };
+        assert_eq!(items.len(), 2);
+        match &items[0][1] {
+            Block::List { kind, items } => {
+                assert_eq!(*kind, ListKind::Unordered);
+                assert!(matches!(
+                    &items[0][1],
+                    Block::List {
+                        kind: ListKind::Ordered,
+                        ..
+                    }
+                ));
+            }
+            other => panic!("Expected nested list, got {:?}", other),
+        }
+        assert_eq!(items[1].len(), 3);
+        assert!(matches!(&items[1][1], Block::Paragraph(_)));
+        assert!(matches!(&items[1][2], Block::CodeBlock { .. }));
+    }
+
+    #[test]
+    fn test_parse_quote_with_list() {
+        let doc = parse("> Text\n> - item\n\nAfter").unwrap();
+        assert_eq!(doc.content.len(), 2);
+        match &doc.content[0] {
+            Block::Quote(blocks) => {
+                assert!(matches!(&blocks[0], Block::Paragraph(_)));
+                assert!(matches!(&blocks[1], Block::List { .. }));
+            }
+            other => panic!("Expected quote, got {:?}", other),
+        }
+    }
 }
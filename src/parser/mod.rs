@@ -1,4 +1,5 @@
 //! Parsers for different input formats
 
+pub mod front_matter;
 pub mod latex;
 pub mod markdown;
@@ -74,6 +74,7 @@ impl WasmConverter {
         let config = Config {
             strict_mode,
             preserve_comments,
+            ..Config::default()
         };
         Self {
             converter: Converter::with_config(config),
@@ -1,5 +1,4 @@
-#set document(title: "A Simple Article")
-#set document(author: "John Doe")
+#set document(title: "A Simple Article", author: "John Doe")
 
 = Introduction
 
@@ -17,7 +17,7 @@ Using the `aligned` environment allows for beautiful step-by-step proofs where t
 $ nabla dot bold(E) &= frac(rho, epsilon.alt_0) \
   nabla dot bold(B) &= 0 \
   nabla times bold(E) &= -frac(partial bold(B), partial t) \
-  nabla times bold(B) &= mu_0 ( bold(J) + epsilon.alt_0 frac(partial bold(E), partial t) ) $
+  nabla times bold(B) &= mu_0 (bold(J) + epsilon.alt_0 frac(partial bold(E), partial t)) $
 
 #line(length: 100%)
 
@@ -25,7 +25,7 @@ $ nabla dot bold(E) &= frac(rho, epsilon.alt_0) \
 
 This example shows a transition matrix for a Markov Chain, demonstrating the use of fractions within a matrix and specialized brackets.
 
-$ P = mat(1 - q, q, 0; p, 1 - p - q, q; 0, p, 1 - p) times [ frac(sum_(i = 1)^n X_i, sqrt(op("Var") ( hat(beta) ))) ] $
+$ P = mat(1 - q, q, 0; p, 1 - p - q, q; 0, p, 1 - p) times [frac(sum_(i = 1)^n X_i, sqrt(op("Var") (hat(beta))))] $
 
 #line(length: 100%)
 
@@ -33,7 +33,7 @@ $ P = mat(1 - q, q, 0; p, 1 - p - q, q; 0, p, 1 - p) times [ frac(sum_(i = 1)^n
 
 A complex definite integral involving trigonometric functions and limits.
 
-$ integral_0^infinity frac(sin ( x ), x) d x = lim_(t -> infinity) ( sum_(k = 1)^n frac(( - 1 )^(k - 1) t^(2 k - 1), ( 2 k - 1 ) ! ( 2 k - 1 )) ) = pi/2 $
+$ integral_0^infinity frac(sin (x), x) d x = lim_(t -> infinity) (sum_(k = 1)^n frac(( - 1)^(k - 1) t^(2 k - 1), (2 k - 1) ! (2 k - 1))) = pi/2 $
 
 #line(length: 100%)
 
@@ -41,10 +41,10 @@ $ integral_0^infinity frac(sin ( x ), x) d x = lim_(t -> infinity) ( sum_(k = 1)
 
 The definition of the Dirac Delta function or a complex piecewise system:
 
-$ delta ( x ) = cases(
+$ delta (x) = cases(
   + infinity "if" x = 0,
   0 "if" x != 0
-)   "subject to"   integral_(-infinity)^infinity delta ( x )   d x = 1 $
+) quad "subject to" quad integral_(-infinity)^infinity delta (x) thin d x = 1 $
 
 #line(length: 100%)
 
@@ -52,4 +52,4 @@ $ delta ( x ) = cases(
 
 Combining fractions, square roots, sums, and indices in a single line:
 
-$ Psi ( bold(r) , t ) = underbrace(frac(1, sqrt(( 2 pi planck )^3)), "Normalization") integral_(RR^3) phi ( bold(p) ) exp [ i/planck ( bold(p) dot bold(r) - E t ) ] d^3 bold(p) $
+$ Psi (bold(r), t) = underbrace(frac(1, sqrt((2 pi planck)^3)), "Normalization") integral_(RR^3) phi (bold(p)) exp [i/planck (bold(p) dot bold(r) - E t)] d^3 bold(p) $
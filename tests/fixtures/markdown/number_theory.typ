@@ -0,0 +1,21 @@
+= Number Theory
+
+Fermat's little theorem: for a prime $p$ and an integer $a$ not divisible by $p$,
+
+$ a^(p - 1) equiv 1 quad (mod thin p) $
+
+The greatest common divisor satisfies Bézout's identity $gcd (a, b) = a x + b y$, and
+
+$ gcd_(1 <= i <= n) a_i divides op("lcm") (a_1, ..., a_n) $
+
+Euclid's algorithm repeats $a <- b$, $b <- a class("binary", mod) b$ until $b = 0$.
+
+Chinese remainder theorem: the system $x equiv a_i quad (mod thin n_i)$ has a unique solution modulo $N = product_(i = 1)^k n_i$ when the $n_i$ are pairwise coprime.
+
+Euler's theorem generalizes Fermat's:
+
+$ a^(phi.alt (n)) equiv 1 quad mod thin n $
+
+The number of digits of $n$ in binary is $floor.l lg n floor.r + 1$, and the most likely residue is
+
+$ hat(r) = op("arg max", limits: #true)_(0 <= r < m) Pr [X equiv r quad (m)] $
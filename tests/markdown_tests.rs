@@ -1,4 +1,15 @@
-use latex2typst::convert_markdown;
+use latex2typst::converter::math::{
+    ChemistryNotation, IdentifierPolicy, PhysicsNotation, UnitNotation,
+};
+use latex2typst::parser::markdown::MathDelimiters;
+use latex2typst::{convert_markdown, Config, Converter, InputFormat, MathFallback};
+
+/// Convert Markdown with a configuration other than the default
+fn convert_with(config: Config, input: &str) -> String {
+    Converter::with_config(config)
+        .convert(input, InputFormat::Markdown)
+        .unwrap()
+}
 
 #[test]
 fn test_basic_markdown() {
@@ -18,6 +29,32 @@ fn test_math_markdown() {
     assert_eq!(result, expected);
 }
 
+#[test]
+fn test_number_theory_markdown() {
+    let input = include_str!("fixtures/markdown/number_theory.md");
+    let expected = include_str!("fixtures/markdown/number_theory.typ");
+
+    let result = convert_markdown(input).unwrap();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_modular_arithmetic() {
+    let result = convert_markdown(r"$a \equiv b \pmod{n}$, $a \bmod b$, $a \mod{n}$").unwrap();
+    assert!(result.contains("$a equiv b quad (mod thin n)$"));
+    assert!(result.contains(r#"$a class("binary", mod) b$"#));
+    assert!(result.contains("$a quad mod thin n$"));
+}
+
+#[test]
+fn test_number_theory_operators() {
+    let result = convert_markdown(r"$\gcd_{i} a_i$, $\lg n$, $\arg\min_x f(x)$, $\Pr(A)$").unwrap();
+    assert!(result.contains("$gcd_i a_i$"));
+    assert!(result.contains("$lg n$"));
+    assert!(result.contains(r#"$op("arg min", limits: #true)_x f (x)$"#));
+    assert!(result.contains("$Pr (A)$"));
+}
+
 #[test]
 fn test_simple_heading() {
     let input = "# Hello World";
@@ -252,3 +289,369 @@ fn test_math_dots() {
     let result = convert_markdown(input).unwrap();
     assert!(result.contains("..."));
 }
+
+#[test]
+fn test_math_fraction_variants() {
+    let input = r"$\frac12 + \dfrac{x}{y+1} + \binom{n}{k} + {a \choose b}$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("1/2"));
+    assert!(result.contains("display(frac(x, y + 1))"));
+    assert!(result.contains("binom(n, k)"));
+    assert!(result.contains("binom(a, b)"));
+}
+
+#[test]
+fn test_math_continued_fraction() {
+    let input = r"$$\cfrac{1}{1 + \cfrac{1}{x}}$$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("display(frac(1, 1 + display(1/x)))"));
+}
+
+#[test]
+fn test_math_stacking_commands() {
+    let input = r"$f \overset{\text{def}}{=} g \xrightarrow{h} k$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("attach(limits(=), t: \"def\")"));
+    assert!(result.contains("attach(limits(arrow.r.long), t: h)"));
+}
+
+#[test]
+fn test_math_substack_limits() {
+    let input = r"$$\sum_{\substack{i<n \\ j<m}} a_{ij}$$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("sum_(i < n \\ j < m)"));
+}
+
+#[test]
+fn test_math_accents() {
+    let input = r"$\dot{xy} + \ddot{x} + \overleftarrow{AB} + \underline{z}$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("dot(x y)"));
+    assert!(result.contains("dot.double(x)"));
+    assert!(result.contains("accent(A B, arrow.l)"));
+    assert!(result.contains("underline(z)"));
+}
+
+#[test]
+fn test_math_font_variants() {
+    let input = r"$\int f \, \mathrm{d}x + \mathbb{1}_A + \mathbb{R} + \mathfrak{g}$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("upright(d) x"));
+    assert!(result.contains("bb(1)_A"));
+    assert!(result.contains("RR"));
+    assert!(result.contains("frak(g)"));
+}
+
+#[test]
+fn test_math_operator_limits() {
+    let input =
+        r"$$\int\limits_0^1 f + \lim\nolimits_{x \to 0} g + \Pr(A) + \operatorname{Var}(X)$$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("limits(integral)_0^1"));
+    assert!(result.contains("scripts(lim)_(x -> 0)"));
+    assert!(result.contains("Pr (A)"));
+    assert!(result.contains("op(\"Var\") (X)"));
+}
+
+#[test]
+fn test_math_declared_operator() {
+    let input = r"Let $\DeclareMathOperator*{\argmax}{arg\,max}$ be declared.
+
+$$\argmax_{x \in X} f(x)$$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("op(\"arg max\", limits: #true)_(x in X)"));
+}
+
+#[test]
+fn test_math_declared_operator_outside_code() {
+    let input = r"\DeclareMathOperator{\rank}{rank}
+
+Use `\rank` in code and $\rank A$ in math.
+
+```
+\rank
+```
+";
+    let result = convert_markdown(input).unwrap();
+    assert!(!result.contains("DeclareMathOperator"));
+    assert!(result.contains("`\\rank`"));
+    assert!(result.contains("```\n\\rank\n```"));
+    assert!(result.contains("$op(\"rank\") A$"));
+}
+
+#[test]
+fn test_math_sizes_and_styles() {
+    let input = r"$\Bigl( \frac{a}{b} \Bigr) + {\displaystyle\sum_i x_i}$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("lr(\\(, size: #180%) a/b lr(\\), size: #180%)"));
+    assert!(result.contains("display(sum_i x_i)"));
+}
+
+#[test]
+fn test_math_spacing() {
+    let input = r"$\int f(x) \, dx \quad x \hspace{1cm} y \! z \phantom{w}$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("thin d x quad x #h(1cm) y #h(-1em/6) z hide(w)"));
+}
+
+#[test]
+fn test_math_relation_commands() {
+    let input = r"$x \in A \cup B \implies f(x) \le g(x) \cdot h(x)$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("x in A union B arrow.r.double.long f (x) <= g (x) dot h (x)"));
+}
+
+#[test]
+fn test_math_multi_letter_scripts() {
+    // Unbraced scripts take one letter, as in TeX, so no run reaches Typst as a name
+    let input = r"$v_{init} + x_max + a_in + x_lim + e^{ab}$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("v_(i n i t) + x_m a x + a_i n + x_l i m + e^(a b)"));
+}
+
+#[test]
+fn test_math_quoted_identifiers() {
+    let result = convert_with(
+        Config {
+            math_identifiers: IdentifierPolicy::Quote,
+            ..Config::default()
+        },
+        r"$v_{init} + x_{in}$",
+    );
+    assert!(result.contains("v_\"init\" + x_\"in\""));
+}
+
+#[test]
+fn test_math_escaping() {
+    let input = r#"$\{x \mid \text{"quoted"}\} + \frac{a,b}{2} + \begin{pmatrix} \frac{a} & 1 \end{pmatrix}$"#;
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains(r#"\{x divides "\"quoted\""\}"#));
+    assert!(result.contains(r"frac(a\, b, 2)"));
+    assert!(result.contains(r#"mat("\\frac{a}", 1)"#));
+}
+
+#[test]
+fn test_math_text_with_inline_math() {
+    let input = r"$$f(x) = 1 \quad \text{if $x \in A$ and } \mbox{$x > 0$}$$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains(r#"quad "if " x in A " and " x > 0"#));
+}
+
+#[test]
+fn test_math_fallback() {
+    let input = "Broken $\\frac{a}$ math\n\n$$\n\\frac{x}\n$$";
+
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains(r"Broken \$\frac{a}\$ math"));
+    assert!(!result.contains("mitex"));
+
+    let result = convert_with(
+        Config {
+            math_fallback: MathFallback::Mitex,
+            ..Config::default()
+        },
+        input,
+    );
+    assert!(result.starts_with("#import \"@preview/mitex:0.2.5\": *\n\n"));
+    assert_eq!(result.matches("#import").count(), 1);
+    assert!(result.contains(r#"Broken #mi("\\frac{a}") math"#));
+    assert!(result.contains(r#"#mitex("\\frac{x}")"#));
+}
+
+#[test]
+fn test_math_physics_notation() {
+    let input = r"$$\dv{f}{x} + \pdv[2]{u}{t} = \abs{\braket{\phi}{\psi}}$$";
+
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains(
+        "frac(dif f, dif x) + frac(diff^2 u, diff t^2) = abs(lr(angle.l phi mid(bar.v) psi angle.r))"
+    ));
+
+    let result = convert_with(
+        Config {
+            math_physics: PhysicsNotation::Physica,
+            ..Config::default()
+        },
+        input,
+    );
+    assert!(result.starts_with("#import \"@preview/physica:0.9.3\": *\n\n"));
+    assert!(result.contains("dv(f, x) + pdv(u, t, 2) = abs(braket(phi, psi))"));
+}
+
+#[test]
+fn test_math_siunitx() {
+    let input = r"$g = \SI{9.81}{\meter\per\second\squared}$, $\num{1.2e-3}$ and $\si{kg.m/s^2}$";
+
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains(r#"$g = 9.81 "m"/"s"^2$"#));
+    assert!(result.contains("$1.2 times 10^(-3)$"));
+    assert!(result.contains(r#"$("kg" "m")/"s"^2$"#));
+
+    let result = convert_markdown(r"$\SI{3}{\per\second}$ and $\si{\per\square\metre}$").unwrap();
+    assert!(result.contains(r#"$3 "s"^(-1)$"#));
+    assert!(result.contains(r#"$"m"^(-2)$"#));
+
+    // physics' \qty sizes the delimiters after it and is otherwise dropped
+    let result = convert_markdown(r"$\qty(\frac{a}{b}) + \qty|x|^2 + \qty y$").unwrap();
+    assert!(result.contains("$lr((a/b)) + lr(| x |)^2 + y$"));
+
+    let result = convert_with(
+        Config {
+            math_units: UnitNotation::Unify,
+            ..Config::default()
+        },
+        input,
+    );
+    assert!(result.starts_with("#import \"@preview/unify:0.7.1\": *\n\n"));
+    assert!(result.contains(r#"$g = #qty("9.81", "meter per second squared")$"#));
+    assert!(result.contains(r##"$#num("1.2e-3")$"##));
+}
+
+#[test]
+fn test_math_mhchem() {
+    let input = r"$\ce{H2O + CO2 -> H2CO3}$ and $\ce{SO4^2- + Ba^{2+} -> BaSO4 v}$";
+
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains(r#"$"H"_2 "O" + "CO"_2 arrow.r.long "H"_2 "CO"_3$"#));
+    assert!(result.contains(r#"$"SO"_4^(2 -) + "Ba"^(2 +) arrow.r.long "BaSO"_4 arrow.b$"#));
+
+    let result = convert_with(
+        Config {
+            math_chemistry: ChemistryNotation::Typsium,
+            ..Config::default()
+        },
+        input,
+    );
+    assert!(result.starts_with("#import \"@preview/typsium:0.2.0\": *\n\n"));
+    assert!(result.contains(r##"$#ce("H2O + CO2 -> H2CO3")$"##));
+}
+
+#[test]
+fn test_math_negation_and_decorations() {
+    let input = r"$$a \not= b, x \not\in A, \xcancel{y}, \boxed{E = mc^2}, \textcolor{red}{z}$$";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("a eq.not b, x in.not A"));
+    assert!(result.contains("cancel(y, cross: #true)"));
+    assert!(result.contains("#box(stroke: 0.5pt, inset: 3pt)[$display(E = m c^2)$]"));
+    assert!(result.contains("#text(fill: red)[$display(z)$]"));
+
+    // Inline math keeps the nested equations inline
+    let result = convert_markdown(r"$\boxed{\sum_i x_i}$").unwrap();
+    assert!(result.contains("#box(stroke: 0.5pt, inset: 3pt)[$sum_i x_i$]"));
+}
+
+#[test]
+fn test_display_environments() {
+    let input = "\\begin{align}\na &= b \\\\\nc &= d\n\\end{align}\n\n\\begin{gather*}\nx\n\\end{gather*}\n\n$M = \\begin{Bmatrix} 1 & 2 \\end{Bmatrix}$";
+    let result = convert_markdown(input).unwrap();
+    assert!(
+        result.contains("#math.equation(block: true, numbering: \"(1)\", $ a &= b \\\n  c &= d $)")
+    );
+    assert!(result.contains("\n$ x $\n"));
+    assert!(result.contains(r#"$M = mat(delim: "{", 1, 2)$"#));
+
+    // Text after the environment stays prose; indented code stays code
+    let input = "Text\n\\begin{equation*}\nx\n\\end{equation*} so done.\n\n    \\begin{align}\n    y\n    \\end{align}\n";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("Text\n\n$ x $\n\nso done."));
+    assert!(result.contains("```\n\\begin{align}\ny\n\\end{align}\n```"));
+}
+
+#[test]
+fn test_equation_labels() {
+    let input = r"\begin{equation}
+E = mc^2 \label{eq:energy}
+\end{equation}
+
+$$a = b \tag{A}$$
+
+See \eqref{eq:energy} and $\ref{eq:energy}$.";
+    let result = convert_markdown(input).unwrap();
+    assert!(result
+        .contains("#math.equation(block: true, numbering: \"(1)\", $ E = m c^2 $) <eq:energy>\n"));
+    assert!(result.contains(
+        "#math.equation(block: true, numbering: _ => \"(A)\", $ a = b $)\n#counter(math.equation).update(n => n - 1)\n"
+    ));
+    assert!(result.contains("See @eq:energy and @eq:energy."));
+
+    // An align has one number in Typst, which the labels of all its lines refer to
+    let input = "See \\eqref{eq:b}.\n\n\\begin{align}\na &= 1 \\label{eq:a} \\\\\nb &= 2 \\label{eq:b}\n\\end{align}";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.contains("See @eq:a."));
+    assert!(result.contains("$) <eq:a>\n"));
+
+    // References in code stay as written
+    let result =
+        convert_markdown("```latex\nSee Figure~\\ref{fig:a} and \\eqref{eq:1}\n```").unwrap();
+    assert!(result.contains("See Figure~\\ref{fig:a} and \\eqref{eq:1}\n"));
+}
+
+#[test]
+fn test_bracket_math_delimiters() {
+    let input = "Energy \\(E = mc^2\\) is\n\n\\[\n\\sum_i x_i\n\\]\n\nand `\\(code\\)` or [a link](http://a.b/\\(x\\)).";
+    let result = convert_with(
+        Config {
+            math_delimiters: MathDelimiters::DollarsAndBrackets,
+            ..Config::default()
+        },
+        input,
+    );
+    assert!(result.contains("Energy $E = m c^2$ is"));
+    assert!(result.contains("$ sum_i x_i $"));
+    assert!(result.contains(r"`\(code\)`"));
+    assert!(result.contains(r#"#link("http://a.b/(x)")[a link]"#));
+
+    // Brackets are only math when asked for
+    let result = convert_markdown(input).unwrap();
+    assert!(!result.contains("$E = m c^2$"));
+}
+
+#[test]
+fn test_front_matter() {
+    let input = "---\ntitle: Notes\nauthor:\n  - Ada\n  - Alan\ndate: 2024-03-01\nkeywords: [math, typst]\nlang: de\nabstract: A short $x$ summary.\nbibliography: refs.bib\n---\n\n# Intro\n";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.starts_with(
+        "#set document(title: \"Notes\", author: (\"Ada\", \"Alan\"), \
+         date: datetime(year: 2024, month: 3, day: 1), keywords: (\"math\", \"typst\"))\n"
+    ));
+    assert!(result.contains("#set text(lang: \"de\")"));
+    assert!(result.contains("A short $x$ summary."));
+    assert!(result.ends_with("#bibliography(\"refs.bib\")\n"));
+    assert!(!result.contains("#line"));
+
+    // Later `---` pairs are a rule and a setext heading, not more front matter
+    let input = "---\ntitle: A\n---\n\nIntro.\n\n---\ntitle: B\n---\n\nBody";
+    let result = convert_markdown(input).unwrap();
+    assert!(result.starts_with("#set document(title: \"A\")\n"));
+    assert!(result.contains("Intro.\n\n#line(length: 100%)\n\n== title: B\n\nBody"));
+
+    let result = convert_markdown("Intro.\n\n---\nNext Section\n---\n\nBody").unwrap();
+    assert!(!result.contains("#set document"));
+    assert!(result.contains("== Next Section"));
+
+    let toml = "+++\ntitle = \"Notes\"\nauthors = [\"Ada\"]\n+++\n\nBody";
+    let result = convert_markdown(toml).unwrap();
+    assert!(result.contains("#set document(title: \"Notes\", author: \"Ada\")"));
+}
+
+#[test]
+fn test_images() {
+    let input = "An ![icon](img/icon.svg) inline.\n\n![Logo](logo.png)\n\n![Chart](chart.png \"Monthly sales\")";
+    let result = convert_markdown(input).unwrap();
+    // Images in running text are boxed to stay in the line; alone, they are blocks
+    assert!(result.contains(r#"An #box(image("img/icon.svg", alt: "icon")) inline."#));
+    assert!(result.contains("\n#image(\"logo.png\", alt: \"Logo\")\n"));
+    assert!(result.contains(
+        "#figure(\n  image(\"chart.png\", alt: \"Chart\"),\n  caption: [Monthly sales],\n)\n"
+    ));
+}
+
+#[test]
+fn test_nested_lists() {
+    let input = "1. Setup\n   - install\n   - configure\n2. Run\n\n   With $x > 0$:\n\n   ```sh\n   run\n   ```\n";
+    let result = convert_markdown(input).unwrap();
+    assert_eq!(
+        result,
+        "+ Setup\n  - install\n  - configure\n+ Run\n\n  With $x > 0$:\n\n  ```sh\n  run\n  ```\n"
+    );
+}